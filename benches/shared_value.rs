@@ -0,0 +1,55 @@
+//! Cloning a large config as `Value` and as `SharedValue`.
+
+#[macro_use]
+extern crate criterion;
+extern crate config_model;
+
+use std::hint::black_box;
+use criterion::Criterion;
+use config_model::{SharedValue, Table, Value};
+
+/// A table of `services` tables, each with a few scalars and an array.
+fn large_config(services: usize) -> Value {
+    let mut root = Table::new();
+    for i in 0..services {
+        let mut service = Table::new();
+        service.insert("host".to_string(), Value::String(format!("service-{}.internal", i)));
+        service.insert("port".to_string(), Value::Integer(8000 + i as i64));
+        service.insert("weight".to_string(), Value::Float(0.5));
+        service.insert("enabled".to_string(), Value::Boolean(true));
+        service.insert("tags".to_string(),
+                       Value::Array((0..8).map(|t| Value::String(format!("tag-{}", t))).collect()));
+        root.insert(format!("service_{}", i), Value::Table(service));
+    }
+    Value::Table(root)
+}
+
+fn clone(c: &mut Criterion) {
+    let value = large_config(1000);
+    let shared = SharedValue::from(value.clone());
+    c.bench_function("clone/value", |b| b.iter(|| black_box(&value).clone()));
+    c.bench_function("clone/shared_value", |b| b.iter(|| black_box(&shared).clone()));
+}
+
+fn mutate_clone(c: &mut Criterion) {
+    let value = large_config(1000);
+    let shared = SharedValue::from(value.clone());
+    c.bench_function("mutate_clone/value", |b| {
+        b.iter(|| {
+            let mut value = black_box(&value).clone();
+            value.set_path("service_500.port", Value::Integer(1)).unwrap();
+            value
+        })
+    });
+    c.bench_function("mutate_clone/shared_value", |b| {
+        b.iter(|| {
+            let mut shared = black_box(&shared).clone();
+            let service = shared.as_table_mut().unwrap().get_mut("service_500").unwrap();
+            service.as_table_mut().unwrap().insert("port".to_string(), SharedValue::Integer(1));
+            shared
+        })
+    });
+}
+
+criterion_group!(benches, clone, mutate_clone);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+//! Normalizing string getters on input that needs no change and on input that does.
+
+#[macro_use]
+extern crate criterion;
+extern crate config_model;
+
+use std::hint::black_box;
+use criterion::Criterion;
+use config_model::Value;
+use config_model::decode::Path;
+
+fn trimmed(c: &mut Criterion) {
+    let clean = Value::String("production-eu-west-1".to_string());
+    let dirty = Value::String("  production-eu-west-1 \n".to_string());
+    c.bench_function("as_str_trimmed/clean", |b| {
+        b.iter(|| Path::new(black_box(&clean), "value").as_str_trimmed().map(|s| s.len()))
+    });
+    c.bench_function("as_str_trimmed/dirty", |b| {
+        b.iter(|| Path::new(black_box(&dirty), "value").as_str_trimmed().map(|s| s.len()))
+    });
+}
+
+fn lowercased(c: &mut Criterion) {
+    let clean = Value::String("production-eu-west-1".to_string());
+    let dirty = Value::String("Production-EU-West-1".to_string());
+    c.bench_function("as_str_lowercased/clean", |b| {
+        b.iter(|| Path::new(black_box(&clean), "value").as_str_lowercased().map(|s| s.len()))
+    });
+    c.bench_function("as_str_lowercased/dirty", |b| {
+        b.iter(|| Path::new(black_box(&dirty), "value").as_str_lowercased().map(|s| s.len()))
+    });
+}
+
+criterion_group!(benches, trimmed, lowercased);
+criterion_main!(benches);
@@ -0,0 +1,134 @@
+//! `#[derive(DecodeConfig)]` for the `config_model` crate, enabled by its `derive` feature.
+//!
+//! The derive implements `config_model::decode::DecodeConfig` for a struct with named
+//! fields, reading every field as a property of the table at the path:
+//!
+//! - a field is required, and decoded with its own `DecodeConfig` implementation;
+//! - an `Option` field may be missing, a present property must still decode;
+//! - `#[config(default)]` or `#[config(default = expr)]` makes a missing property take
+//!   `Default::default()` or `expr`;
+//! - `#[config(rename = "key")]` reads the property `key` instead of the field name;
+//! - `#[config(desc = "...")]` is the description used in errors, it defaults to the
+//!   property name.
+//!
+//! Type parameters of the struct must implement `DecodeConfig` themselves, the derive adds
+//! that bound to each of them.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Data, DeriveInput, Expr, Fields, Field, LitStr, Type};
+
+#[proc_macro_derive(DecodeConfig, attributes(config))]
+pub fn derive_decode_config(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+/// Field options from `#[config(...)]` attributes.
+#[derive(Default)]
+struct Options {
+    desc: Option<LitStr>,
+    rename: Option<LitStr>,
+    default: Option<Option<Expr>>,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => {
+            match data.fields {
+                Fields::Named(ref fields) => &fields.named,
+                _ => {
+                    return Err(syn::Error::new_spanned(input,
+                                                       "DecodeConfig requires named fields"))
+                }
+            }
+        }
+        _ => return Err(syn::Error::new_spanned(input, "DecodeConfig supports only structs")),
+    };
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(syn::parse_quote!(::config_model::decode::DecodeConfig));
+    }
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let fields = fields.iter().map(decode_field).collect::<syn::Result<Vec<_>>>()?;
+    Ok(quote! {
+        impl #impl_generics ::config_model::decode::DecodeConfig for #name #type_generics
+            #where_clause
+        {
+            fn decode(path: &::config_model::decode::Path)
+                      -> ::config_model::decode::Result<Self> {
+                Ok(#name {
+                    #(#fields,)*
+                })
+            }
+        }
+    })
+}
+
+fn decode_field(field: &Field) -> syn::Result<TokenStream2> {
+    let options = parse_options(field)?;
+    let ident = field.ident.as_ref().expect("named field");
+    let key = match options.rename {
+        Some(rename) => rename.value(),
+        None => ident.to_string().trim_start_matches("r#").to_string(),
+    };
+    let desc = match options.desc {
+        Some(desc) => desc.value(),
+        None => key.clone(),
+    };
+    let decode = quote!(::config_model::decode::DecodeConfig::decode);
+    let value = match options.default {
+        Some(default) => {
+            let default = match default {
+                Some(expr) => quote!(#expr),
+                None => quote!(::std::default::Default::default()),
+            };
+            quote!(path.table_property_or(#key, #desc, #default, #decode)?)
+        }
+        None if is_option(&field.ty) => {
+            quote!(path.optional_validated(#key, #desc, #decode)?)
+        }
+        None => quote!(#decode(&path.table_property(#key, #desc)?)?),
+    };
+    Ok(quote!(#ident: #value))
+}
+
+fn parse_options(field: &Field) -> syn::Result<Options> {
+    let mut options = Options::default();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("config")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("desc") {
+                options.desc = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("rename") {
+                options.rename = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("default") {
+                options.default = Some(if meta.input.peek(syn::Token![=]) {
+                    Some(meta.value()?.parse()?)
+                } else {
+                    None
+                });
+            } else {
+                return Err(meta.error("expected `desc`, `rename` or `default`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+/// Return true if the type is written as `Option<...>`, with or without a path.
+fn is_option(ty: &Type) -> bool {
+    match *ty {
+        Type::Path(ref p) if p.qself.is_none() => {
+            p.path.segments.last().is_some_and(|s| s.ident == "Option")
+        }
+        _ => false,
+    }
+}
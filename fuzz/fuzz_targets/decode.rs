@@ -0,0 +1,46 @@
+#![no_main]
+
+use arbitrary::Unstructured;
+use config_model::decode::Path;
+use config_model_fuzz::arbitrary_value;
+use libfuzzer_sys::fuzz_target;
+
+/// Call every getter on the path and on all of its children.
+fn decode_all(path: &Path) {
+    let _ = path.as_str();
+    let _ = path.as_integer();
+    let _ = path.as_float();
+    let _ = path.as_bool();
+    let _ = path.as_datetime();
+    let _ = path.as_integer_lenient();
+    let _ = path.as_float_lenient();
+    let _ = path.as_bool_lenient();
+    let _ = path.as_ratio();
+    let _ = path.as_identifier();
+    let _ = path.as_str_trimmed();
+    let _ = path.as_str_lowercased();
+    let _ = path.as_labeled_integer(&[("error", 0), ("warn", 1)]);
+    let _ = path.require_not_both("a", "b");
+    let _ = path.require_all_properties(&[("a", "first"), ("b", "second")]);
+    let _ = path.as_tagged::<()>("type", &[("a", &|_| Ok(()))]);
+    let _ = path.as_slice_of(|element| {
+        decode_all(element);
+        Ok(())
+    });
+    if let Ok(entries) = path.as_table_entries("entry") {
+        for (_, entry) in entries {
+            decode_all(&entry);
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    let value = match arbitrary_value(&mut u) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+    let root = Path::new(&value, "fuzzed config");
+    decode_all(&root);
+    decode_all(&root.clone().lenient());
+});
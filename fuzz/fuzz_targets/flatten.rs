@@ -0,0 +1,23 @@
+#![no_main]
+
+use arbitrary::Unstructured;
+use config_model::Value;
+use config_model_fuzz::arbitrary_value;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    let mut value = match arbitrary_value(&mut u) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+    let _ = value.to_string_map();
+    if let Ok(pointer) = u.arbitrary::<&str>() {
+        let _ = value.get_pointer(pointer);
+    }
+    value.walk_mut(|path, value| {
+        if path.len() % 2 == 1 {
+            *value = Value::Table(Default::default());
+        }
+    });
+});
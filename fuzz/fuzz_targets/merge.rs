@@ -0,0 +1,23 @@
+#![no_main]
+
+use arbitrary::Unstructured;
+use config_model::{MergeStrategy, Value};
+use config_model_fuzz::arbitrary_value;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    let (mut base, overlay) = match (arbitrary_value(&mut u), arbitrary_value(&mut u)) {
+        (Ok(base), Ok(overlay)) => (base, overlay),
+        _ => return,
+    };
+    let strategy = match u.int_in_range(0..=2u8) {
+        Ok(0) => MergeStrategy::ArrayReplace,
+        Ok(1) => MergeStrategy::ArrayConcat,
+        _ => MergeStrategy::ArrayUnion,
+    };
+    let _ = base.eq_numeric_loose(&overlay);
+    base.merge_with(overlay.clone(), strategy);
+    base.prune_empty();
+    let _: Value = config_model::SharedValue::from(base).into();
+});
@@ -0,0 +1,20 @@
+#![no_main]
+
+use arbitrary::Unstructured;
+use config_model::{serde_de, Value};
+use config_model_fuzz::arbitrary_value;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    let value = match arbitrary_value(&mut u) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+    let _ = value.to_pretty_string();
+    let _ = value.to_pretty_string_redacted(|key| key.len() % 2 == 0);
+    let _ = serde_de::from_value::<Value>(&value);
+    if let Ok(json) = serde_json::to_string(&value) {
+        let _ = serde_json::from_str::<Value>(&json);
+    }
+});
@@ -0,0 +1,40 @@
+//! Shared helpers for the fuzz targets.
+
+use arbitrary::{Result, Unstructured};
+use config_model::{Table, Value};
+
+/// Deepest nesting generated for arrays and tables.
+pub const MAX_DEPTH: usize = 32;
+
+/// Build an arbitrary value tree from fuzzer input.
+pub fn arbitrary_value(u: &mut Unstructured) -> Result<Value> {
+    value_at(u, MAX_DEPTH)
+}
+
+fn value_at(u: &mut Unstructured, depth: usize) -> Result<Value> {
+    let max_kind = if depth == 0 { 5 } else { 7 };
+    Ok(match u.int_in_range(0..=max_kind)? {
+        0 => Value::String(u.arbitrary()?),
+        1 => Value::Integer(u.arbitrary()?),
+        2 => Value::Float(u.arbitrary()?),
+        3 => Value::Boolean(u.arbitrary()?),
+        4 => Value::Datetime(u.arbitrary()?),
+        5 => Value::Null,
+        6 => {
+            let len = u.arbitrary_len::<u8>()?;
+            let mut array = Vec::with_capacity(len);
+            for _ in 0..len {
+                array.push(value_at(u, depth - 1)?);
+            }
+            Value::Array(array)
+        }
+        _ => {
+            let len = u.arbitrary_len::<u8>()?;
+            let mut table = Table::new();
+            for _ in 0..len {
+                table.insert(u.arbitrary()?, value_at(u, depth - 1)?);
+            }
+            Value::Table(table)
+        }
+    })
+}
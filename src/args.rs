@@ -0,0 +1,293 @@
+//! Overriding configuration values from command-line arguments.
+//!
+//! An argument like `--set server.port=8080` sets `server.port` to `8080`. Like the
+//! environment overlay, this is meant to be applied to the loaded config before decoding:
+//!
+//! ```
+//! # use config_model::args::ArgOverlay;
+//! # use config_model::Value;
+//! let mut value = Value::new_table();
+//! let args = vec!["serve", "--set", "server.port=8080", "--verbose"];
+//! let rest = ArgOverlay::new().apply_args(&mut value, args).unwrap();
+//! assert_eq!(rest, vec!["serve", "--verbose"]);
+//! assert_eq!(value.get_path("server.port").and_then(|v| v.as_integer()), Some(8080));
+//! ```
+
+use decode::{At, Error, Errors, Path};
+use env::parse_scalar;
+use schema::Schema;
+use value::{components_to_string, parse_dotted_path};
+use {Value, PathComponent, SetPathError};
+
+/// Mapping of `path=value` overrides onto the config tree.
+///
+/// Paths are written like in `Value::get_path`. Without a schema, values that parse as a
+/// boolean, an integer or a float become `Value::Boolean`, `Value::Integer` or
+/// `Value::Float`, anything else stays a string, the same as for `EnvOverlay`. With a
+/// schema, a value is kept a string where a string is expected, and is checked against
+/// the schema of its path.
+///
+/// Overrides are applied in order, so a later one for the same path wins. Tables on the
+/// way are created like with `Value::set_path`.
+#[derive(Debug, Clone)]
+pub struct ArgOverlay<'s> {
+    flag: String,
+    schema: Option<&'s Schema>,
+}
+
+impl<'s> Default for ArgOverlay<'s> {
+    fn default() -> ArgOverlay<'s> {
+        ArgOverlay {
+            flag: "--set".to_string(),
+            schema: None,
+        }
+    }
+}
+
+impl<'s> ArgOverlay<'s> {
+    /// Overlay reading the `--set` flag, inferring value types.
+    pub fn new() -> ArgOverlay<'s> {
+        ArgOverlay::default()
+    }
+
+    /// Use `flag` instead of `--set` in `apply_args`.
+    pub fn flag(self, flag: &str) -> ArgOverlay<'s> {
+        ArgOverlay { flag: flag.to_string(), ..self }
+    }
+
+    /// Convert and check values with `schema`, which also rejects unknown paths.
+    pub fn schema(self, schema: &'s Schema) -> ArgOverlay<'s> {
+        ArgOverlay { schema: Some(schema), ..self }
+    }
+
+    /// Apply the overrides found in `args` to `value`, returning the other arguments.
+    ///
+    /// Overrides are written `--set path=value` or `--set=path=value`. A flag at the end,
+    /// without an override, is an error.
+    pub fn apply_args<I>(&self, value: &mut Value, args: I) -> Result<Vec<String>, Errors>
+        where I: IntoIterator,
+              I::Item: Into<String>
+    {
+        let mut overrides = vec![];
+        let mut rest = vec![];
+        let mut args = args.into_iter().map(Into::into);
+        let inline = format!("{}=", self.flag);
+        while let Some(arg) = args.next() {
+            if arg == self.flag {
+                match args.next() {
+                    Some(next) => overrides.push(next),
+                    None => return Err(Errors(vec![malformed(&arg)])),
+                }
+            } else if let Some(o) = arg.strip_prefix(&inline[..]) {
+                overrides.push(o.to_string());
+            } else {
+                rest.push(arg);
+            }
+        }
+        self.apply_overrides(value, &overrides)?;
+        Ok(rest)
+    }
+
+    /// Apply overrides like `server.port=8080`, already taken from the arguments.
+    ///
+    /// Nothing is modified if there is an error, see `apply_pairs`.
+    pub fn apply_overrides<S: AsRef<str>>(&self,
+                                          value: &mut Value,
+                                          overrides: &[S])
+                                          -> Result<(), Errors> {
+        let mut pairs = vec![];
+        let mut errors = vec![];
+        for o in overrides {
+            match o.as_ref().split_once('=') {
+                Some((path, raw)) if !path.is_empty() => pairs.push((path, raw)),
+                _ => errors.push(malformed(o.as_ref())),
+            }
+        }
+        let result = if errors.is_empty() {
+            self.apply_pairs(value, pairs)
+        } else {
+            self.apply_pairs(&mut value.clone(), pairs)
+        };
+        match result {
+            Ok(()) if errors.is_empty() => Ok(()),
+            Ok(()) => Err(Errors(errors)),
+            Err(Errors(pair_errors)) => {
+                errors.extend(pair_errors);
+                Err(Errors(errors))
+            }
+        }
+    }
+
+    /// Apply `(path, value)` pairs to `value`.
+    ///
+    /// Every pair is checked, and all errors are returned. Nothing is modified if there
+    /// is an error.
+    pub fn apply_pairs<I, P, S>(&self, value: &mut Value, pairs: I) -> Result<(), Errors>
+        where I: IntoIterator<Item = (P, S)>,
+              P: AsRef<str>,
+              S: AsRef<str>
+    {
+        let mut result = value.clone();
+        let mut errors = vec![];
+        for (path, raw) in pairs {
+            if let Err(e) = self.apply_pair(&mut result, path.as_ref(), raw.as_ref()) {
+                errors.push(e);
+            }
+        }
+        if !errors.is_empty() {
+            return Err(Errors(errors));
+        }
+        *value = result;
+        Ok(())
+    }
+
+    fn apply_pair(&self, value: &mut Value, path: &str, raw: &str) -> Result<(), At<Error>> {
+        let components = match parse_dotted_path(path) {
+            Some(ref c) if !c.is_empty() => c.clone(),
+            _ => return Err(malformed(&format!("{}={}", path, raw))),
+        };
+        let new = match self.schema {
+            Some(schema) => {
+                match schema.lookup(&components)? {
+                    Some(element) => {
+                        let new = element.parse_str(raw);
+                        check(schema, element, &components, &new)?;
+                        new
+                    }
+                    None => parse_scalar(raw),
+                }
+            }
+            None => parse_scalar(raw),
+        };
+        let path = components_to_string(&components);
+        value.set_path(&path, new).map(|_| ()).map_err(|e| set_path_error(e, raw))
+    }
+}
+
+/// Validate `value` with `schema`, the schema of `root` at `components`, with errors at
+/// `components` described the way `Schema::validate` describes them.
+fn check(root: &Schema,
+         schema: &Schema,
+         components: &[PathComponent],
+         value: &Value)
+         -> Result<(), At<Error>> {
+    let path = Path::new_at_components(value, components, root.describe(components));
+    let mut errors = vec![];
+    schema.validate_at(&path, &mut errors);
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn malformed(arg: &str) -> At<Error> {
+    Error::IncorrectValue {
+            explanation: Some("expected an override like `server.port=8080`".to_string()),
+            value: Value::String(arg.to_string()),
+            possible_list: vec![],
+        }
+        .at(String::new())
+}
+
+fn set_path_error(e: SetPathError, raw: &str) -> At<Error> {
+    let (explanation, path) = match e {
+        SetPathError::InvalidPath => ("invalid path".to_string(), String::new()),
+        SetPathError::NotAContainer { path, found } => {
+            (format!("can not set a value inside a {}", found), path)
+        }
+        SetPathError::IndexOutOfRange { path, index, len } => {
+            (format!("index {} is out of range for an array of length {}", index, len), path)
+        }
+        SetPathError::NotFound { path } => {
+            ("no array to set an element of".to_string(), path)
+        }
+    };
+    Error::IncorrectValue {
+            explanation: Some(explanation),
+            value: Value::String(raw.to_string()),
+            possible_list: vec![],
+        }
+        .at(path)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use Value;
+    use decode::Errors;
+    use schema::Schema;
+    use super::ArgOverlay;
+
+    fn schema() -> Schema {
+        Schema::table()
+            .field("ports", Schema::array(Schema::integer().range(1..65536)).desc("listen ports"))
+            .field("name", Schema::string())
+            .field("servers", Schema::map(Schema::table().field("port", Schema::integer())))
+    }
+
+    fn messages(errors: Errors) -> Vec<String> {
+        errors.0.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn infers_types_without_a_schema() {
+        let mut value = value!{ server: { port: "80" } };
+        let rest = ArgOverlay::new()
+            .flag("-o")
+            .apply_args(&mut value, vec!["-o", "server.port=81", "-o=debug=true", "x"])
+            .unwrap();
+        assert_eq!(rest, ["x"]);
+        assert_eq!(value, value!{ server: { port: 81 }, debug: true });
+    }
+
+    #[test]
+    fn converts_and_checks_values_with_the_schema() {
+        let schema = schema();
+        let overlay = ArgOverlay::new().schema(&schema);
+        let mut value = value!{ ports: [80, 443] };
+        overlay.apply_overrides(&mut value, &["name=42", "ports[1]=8443"]).unwrap();
+        assert_eq!(value, value!{ ports: [80, 8443], name: "42" });
+        let e = overlay.apply_overrides(&mut value, &["ports[0]=0", "servers.web.port=x"])
+            .unwrap_err();
+        assert_eq!(messages(e),
+                   ["at 'ports[0]': element of listen ports must be at least 1 and less than \
+                     65536 (found 0)",
+                    "at 'servers.web.port': expected an integer for port"]);
+    }
+
+    #[test]
+    fn rejects_unknown_paths() {
+        let schema = schema();
+        let e = ArgOverlay::new()
+            .schema(&schema)
+            .apply_overrides(&mut value!{}, &["prots[0]=1", "name.first=a"])
+            .unwrap_err();
+        assert_eq!(e.0[0].path, "prots");
+        assert_eq!(e.0[0].error.code(), "E_UNEXPECTED_PROPERTY");
+        assert_eq!(e.0[1].path, "name");
+        assert_eq!(e.0[1].error.code(), "E_EXPECTED_TABLE");
+    }
+
+    #[test]
+    fn rejects_a_flag_without_an_override() {
+        let mut value = value!{};
+        let e = ArgOverlay::new().apply_args(&mut value, vec!["serve", "--set"]).unwrap_err();
+        assert_eq!(messages(e),
+                   ["expected an override like `server.port=8080` (found \"--set\")"]);
+        let e = ArgOverlay::new().apply_args(&mut value, vec!["--set", "port"]).unwrap_err();
+        assert_eq!(e.0.len(), 1);
+        assert_eq!(value, Value::new_table());
+    }
+
+    #[test]
+    fn apply_pairs_changes_nothing_on_error() {
+        let mut value = value!{ a: 1, b: "x" };
+        let e = ArgOverlay::new()
+            .apply_pairs(&mut value, vec![("a", "2"), ("b.c", "3"), ("d", "4")])
+            .unwrap_err();
+        assert_eq!(messages(e), ["at 'b': can not set a value inside a string (found \"3\")"]);
+        assert_eq!(value, value!{ a: 1, b: "x" });
+        ArgOverlay::new().apply_pairs(&mut value, vec![("a", "2"), ("a", "3")]).unwrap();
+        assert_eq!(value, value!{ a: 3, b: "x" });
+    }
+}
@@ -0,0 +1,91 @@
+//! Parsed datetimes, available with the `chrono` feature.
+//!
+//! `Value::Datetime` keeps the text of the datetime as it was written. `Datetime` is the
+//! parsed form, in one of the four kinds TOML knows: with an offset, local, date only
+//! and time only. Formatting a `Datetime` gives back text that parses to the same kind.
+
+use std::fmt;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat};
+use Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Datetime {
+    /// Date and time with an offset, like `1979-05-27T07:32:00-08:00`.
+    Offset(DateTime<FixedOffset>),
+    /// Date and time without an offset, like `1979-05-27T07:32:00`.
+    Local(NaiveDateTime),
+    /// Date only, like `1979-05-27`.
+    Date(NaiveDate),
+    /// Time only, like `07:32:00`.
+    Time(NaiveTime),
+}
+
+impl Datetime {
+    /// Parse an ISO 8601 datetime, `None` if it is not one.
+    ///
+    /// A space or a lowercase `t` is accepted instead of the `T` between date and time,
+    /// and seconds may have a fractional part.
+    pub fn parse(s: &str) -> Option<Datetime> {
+        let normalized;
+        let s = match s.as_bytes().get(10) {
+            Some(&b' ') | Some(&b't') if s.len() > 11 => {
+                normalized = format!("{}T{}", &s[..10], &s[11..]);
+                &normalized[..]
+            }
+            _ => s,
+        };
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(s) {
+            return Some(Datetime::Offset(datetime));
+        }
+        if let Ok(local) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f") {
+            return Some(Datetime::Local(local));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Some(Datetime::Date(date));
+        }
+        NaiveTime::parse_from_str(s, "%H:%M:%S%.f").ok().map(Datetime::Time)
+    }
+
+    pub fn kind_str(&self) -> &'static str {
+        match *self {
+            Datetime::Offset(..) => "offset datetime",
+            Datetime::Local(..) => "local datetime",
+            Datetime::Date(..) => "local date",
+            Datetime::Time(..) => "local time",
+        }
+    }
+}
+
+impl fmt::Display for Datetime {
+    /// Formats as TOML does, with `Z` for the UTC offset and fractional seconds only if
+    /// there are any.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Datetime::Offset(ref d) => {
+                f.write_str(&d.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+            Datetime::Local(ref d) => write!(f, "{}", d.format("%Y-%m-%dT%H:%M:%S%.f")),
+            Datetime::Date(ref d) => write!(f, "{}", d.format("%Y-%m-%d")),
+            Datetime::Time(ref t) => write!(f, "{}", t.format("%H:%M:%S%.f")),
+        }
+    }
+}
+
+impl From<Datetime> for Value {
+    fn from(datetime: Datetime) -> Value {
+        Value::Datetime(datetime.to_string())
+    }
+}
+
+impl Value {
+    /// Create a datetime value from `s`, `None` if `s` is not a valid datetime.
+    pub fn datetime(s: &str) -> Option<Value> {
+        Datetime::parse(s).map(|_| Value::Datetime(s.to_string()))
+    }
+
+    /// Extract and parse the datetime value, `None` if this is not a datetime or if it
+    /// can not be parsed.
+    pub fn as_parsed_datetime(&self) -> Option<Datetime> {
+        self.as_datetime().and_then(Datetime::parse)
+    }
+}
@@ -6,18 +6,46 @@
 //! - We don't know where it comes from (TOML yes, but it is not dependent on that);
 //! - We don't assume it will be human who reads it.
 
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::btree_map::{self, Entry};
+use std::cmp;
+use std::env;
+use std::error;
 use std::fmt;
+use std::iter;
+use std::num::IntErrorKind;
+use std::ops::{Bound, RangeBounds};
+use std::rc::Rc;
 use std::result;
+use std::slice;
+use std::sync::Arc;
+use std::time::Duration;
 use Value;
 use Table;
+#[cfg(feature = "indexmap")]
+use OrderedTable;
+use expand::{self, ExpandError};
+use units;
+use PathComponent;
+use value::components_to_string;
+#[cfg(feature = "unicode-normalization")]
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, FixedOffset};
+#[cfg(feature = "chrono")]
+use datetime::Datetime;
+#[cfg(feature = "regex")]
+use regex::Regex;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Property {
     pub name: String,
     pub desc: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Error {
     ExpectedTable {
         desc: String,
@@ -47,190 +75,3985 @@ pub enum Error {
     ExpectedProperty(Property),
     ExpectedProperties(Vec<Property>),
     ExpectedOneOfProperties(Vec<Property>),
+    ConflictingProperties {
+        paths: Vec<String>,
+    },
+    DuplicateKey {
+        name: String,
+    },
+    UnexpectedProperty {
+        name: String,
+        /// A known property with a similar name, if any.
+        suggestion: Option<String>,
+    },
+    AmbiguousProperty {
+        name: String,
+        matches: Vec<String>,
+    },
+    UndefinedVariable {
+        name: String,
+    },
+    /// References that lead back to the first path, which is also the last one.
+    ReferenceCycle {
+        paths: Vec<String>,
+    },
     IncorrectValue {
         explanation: Option<String>,
         value: Value,
         possible_list: Vec<Value>,
     },
+    /// A null value where a value of the `expected` type is required.
+    UnexpectedNull {
+        desc: String,
+        expected: &'static str,
+    },
+    /// A value is larger, or nested deeper, than `Limits` allow.
+    LimitExceeded {
+        limit: Limit,
+        max: usize,
+        found: usize,
+    },
 }
 
 impl Error {
+    /// Return a stable machine-readable code of this error.
+    ///
+    /// Codes are meant for external reference (documentation links, log filters), so
+    /// once assigned, a code is never changed or reused for a different variant.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Error::ExpectedTable { .. } => "E_EXPECTED_TABLE",
+            Error::ExpectedString { .. } => "E_EXPECTED_STRING",
+            Error::ExpectedInteger { .. } => "E_EXPECTED_INTEGER",
+            Error::ExpectedFloat { .. } => "E_EXPECTED_FLOAT",
+            Error::ExpectedBool { .. } => "E_EXPECTED_BOOL",
+            Error::ExpectedDatetime { .. } => "E_EXPECTED_DATETIME",
+            Error::ExpectedSlice { .. } => "E_EXPECTED_SLICE",
+            Error::ExpectedOneOfTypes { .. } => "E_EXPECTED_ONE_OF_TYPES",
+            Error::ExpectedProperty(..) => "E_EXPECTED_PROPERTY",
+            Error::ExpectedProperties(..) => "E_EXPECTED_PROPERTIES",
+            Error::ExpectedOneOfProperties(..) => "E_EXPECTED_ONE_OF_PROPERTIES",
+            Error::ConflictingProperties { .. } => "E_CONFLICTING_PROPERTIES",
+            Error::DuplicateKey { .. } => "E_DUPLICATE_KEY",
+            Error::UnexpectedProperty { .. } => "E_UNEXPECTED_PROPERTY",
+            Error::AmbiguousProperty { .. } => "E_AMBIGUOUS_PROPERTY",
+            Error::UndefinedVariable { .. } => "E_UNDEFINED_VARIABLE",
+            Error::UnexpectedNull { .. } => "E_UNEXPECTED_NULL",
+            Error::LimitExceeded { .. } => "E_LIMIT_EXCEEDED",
+            Error::ReferenceCycle { .. } => "E_REFERENCE_CYCLE",
+            Error::IncorrectValue { .. } => "E_INCORRECT_VALUE",
+        }
+    }
+
+    /// Suggest a concrete fix for the most common mistakes, for the config author.
+    ///
+    /// Returns `None` for errors without an obvious fix.
+    pub fn hint(&self) -> Option<String> {
+        let list = |items: Vec<String>| items.join(", ");
+        Some(match *self {
+            Error::ExpectedString { .. } => "put the value in quotes".to_string(),
+            Error::ExpectedInteger { .. } | Error::ExpectedFloat { .. } => {
+                "write the number without quotes, or enable lenient parsing".to_string()
+            }
+            Error::ExpectedBool { .. } => {
+                "use `true` or `false`, or enable lenient parsing to also accept `yes`, `no`, \
+                 `on`, `off`, `1` and `0`"
+                    .to_string()
+            }
+            Error::ExpectedProperty(ref p) => format!("add `{}` ({})", p.name, p.desc),
+            Error::ExpectedProperties(ref properties) => {
+                format!("add {}",
+                        list(properties.iter().map(|p| format!("`{}`", p.name)).collect()))
+            }
+            Error::ConflictingProperties { ref paths } => {
+                format!("keep only one of {}",
+                        list(paths.iter().map(|p| format!("`{}`", p)).collect()))
+            }
+            Error::DuplicateKey { ref name } => {
+                format!("remove or rename one of the `{}` entries", name)
+            }
+            Error::UnexpectedProperty { suggestion: Some(ref suggestion), .. } => {
+                format!("did you mean `{}`?", suggestion)
+            }
+            Error::UnexpectedProperty { ref name, .. } => {
+                format!("remove `{}` or check it for typos", name)
+            }
+            Error::AmbiguousProperty { ref matches, .. } => {
+                format!("keep only one of {}",
+                        list(matches.iter().map(|m| format!("`{}`", m)).collect()))
+            }
+            Error::UndefinedVariable { ref name } => {
+                format!("define `{}`, or write `$$` for a literal `$`", name)
+            }
+            Error::ReferenceCycle { .. } => {
+                "replace one of the references with a value".to_string()
+            }
+            Error::UnexpectedNull { .. } => {
+                "set a value, or remove the key if it is optional".to_string()
+            }
+            Error::LimitExceeded { limit, max, .. } => {
+                format!("reduce the {} to at most {}", limit, max)
+            }
+            Error::IncorrectValue { ref possible_list, .. } if !possible_list.is_empty() => {
+                format!("use one of {}",
+                        list(possible_list.iter().map(|v| v.to_pretty_string()).collect()))
+            }
+            _ => return None,
+        })
+    }
+
     pub fn at(self, path: String) -> At<Error> {
         At {
             error: self,
-            path: path,
+            path,
+            location: None,
         }
     }
 }
 
-#[derive(Debug)]
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let properties = |properties: &[Property]| {
+            properties.iter()
+                .map(|p| format!("'{}' ({})", p.name, p.desc))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match *self {
+            Error::ExpectedTable { ref desc } => write!(f, "expected a table for {}", desc),
+            Error::ExpectedString { ref desc } => write!(f, "expected a string for {}", desc),
+            Error::ExpectedInteger { ref desc } => write!(f, "expected an integer for {}", desc),
+            Error::ExpectedFloat { ref desc } => write!(f, "expected a float for {}", desc),
+            Error::ExpectedBool { ref desc } => write!(f, "expected a boolean for {}", desc),
+            Error::ExpectedDatetime { ref desc } => write!(f, "expected a datetime for {}", desc),
+            Error::ExpectedSlice { ref desc } => write!(f, "expected an array for {}", desc),
+            Error::UnexpectedNull { ref desc, expected } => {
+                write!(f, "expected {} for {}, found null", expected, desc)
+            }
+            Error::LimitExceeded { limit, max, found } => {
+                write!(f, "{} of {} exceeds the limit of {}", limit, found, max)
+            }
+            Error::ExpectedOneOfTypes { ref found_type, ref possible_list } => {
+                write!(f,
+                       "expected one of types {}, found {}",
+                       possible_list.join(", "),
+                       found_type)
+            }
+            Error::ExpectedProperty(ref p) => {
+                write!(f, "missing property '{}' ({})", p.name, p.desc)
+            }
+            Error::ExpectedProperties(ref p) => write!(f, "missing properties {}", properties(p)),
+            Error::ExpectedOneOfProperties(ref p) => {
+                write!(f, "expected one of properties {}", properties(p))
+            }
+            Error::ConflictingProperties { ref paths } => {
+                write!(f,
+                       "conflicting properties '{}', only one can be set",
+                       paths.join("', '"))
+            }
+            Error::DuplicateKey { ref name } => write!(f, "duplicate key '{}'", name),
+            Error::UnexpectedProperty { ref name, .. } => {
+                write!(f, "unexpected property '{}'", name)
+            }
+            Error::AmbiguousProperty { ref name, ref matches } => {
+                write!(f,
+                       "property '{}' is ambiguous, it matches '{}'",
+                       name,
+                       matches.join("', '"))
+            }
+            Error::UndefinedVariable { ref name } => write!(f, "undefined variable '{}'", name),
+            Error::ReferenceCycle { ref paths } => {
+                write!(f, "reference cycle '{}'", paths.join("' -> '"))
+            }
+            Error::IncorrectValue { ref explanation, ref value, ref possible_list } => {
+                write!(f,
+                       "{} (found {})",
+                       explanation.as_ref().map_or("incorrect value", |e| &e[..]),
+                       value.to_pretty_string())?;
+                if !possible_list.is_empty() {
+                    let possible: Vec<_> =
+                        possible_list.iter().map(|v| v.to_pretty_string()).collect();
+                    write!(f, ", expected one of {}", possible.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// What to do with references to variables that are not defined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndefinedVariables {
+    /// Report an error.
+    Reject,
+    /// Leave the reference in the string exactly as written.
+    KeepLiteral,
+}
+
+#[derive(Debug, Clone)]
 pub struct At<E: fmt::Debug> {
     pub error: E,
     pub path: String,
+    /// Location in the source file, set by `locate`.
+    pub location: Option<Box<Location>>,
 }
 
-#[derive(Debug, Clone)]
-pub struct Path<'a> {
-    path: Vec<&'a str>,
-    value: &'a Value,
-    desc: &'a str,
+impl<E: fmt::Debug> At<E> {
+    /// Prepend path components to the location of this error.
+    ///
+    /// Useful when a decoder ran on a value that is not at the root of the real tree.
+    pub fn rebase(self, prefix: &[&str]) -> At<E> {
+        let prefix = Path::path_as_string(prefix);
+        At {
+            path: match (prefix.is_empty(), self.path.is_empty()) {
+                (true, _) => self.path,
+                (false, true) => prefix,
+                (false, false) if self.path.starts_with('[') => {
+                    format!("{}{}", prefix, self.path)
+                }
+                (false, false) => format!("{}.{}", prefix, self.path),
+            },
+            error: self.error,
+            location: self.location,
+        }
+    }
+
+    /// Set the location of this error in the source file, if `source_map` has it.
+    ///
+    /// The location of the closest parent is used when the path itself is not in the
+    /// source, like for a missing property.
+    pub fn locate(self, source_map: &SourceMap) -> At<E> {
+        At {
+            location: source_map.location(&self.path).map(Box::new).or(self.location),
+            ..self
+        }
+    }
 }
 
-/// Path class here encapsulates decoding a value at specific path.
-///
-/// Additionaly this value contains description that will be used in errors.
-///
-/// There are methods like `table_property` to drill down deeper into this value and
-/// construct the documentation at the same time.
-///
-/// And in case the error occurs, the result will contain perfect information of what and
-/// where happened.
-impl<'a> Path<'a> {
-    /// Construct root value with specified description.
-    pub fn new<'r>(value: &'r Value, desc: &'r str) -> Path<'r> {
-        Path {
-            path: vec![],
-            value: value,
-            desc: desc,
+impl<E: fmt::Debug + fmt::Display> fmt::Display for At<E> {
+    /// Formats as `at 'server.port': expected an integer for port`, without the prefix
+    /// if the error is at the root. A known location is written first, like
+    /// `config.toml:14:3: at 'server.port': ...`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref location) = self.location {
+            write!(f, "{}: ", location)?;
+        }
+        if self.path.is_empty() {
+            write!(f, "{}", self.error)
+        } else {
+            write!(f, "at '{}': {}", self.path, self.error)
         }
     }
+}
 
-    /// Construct a value at specified path.
-    pub fn new_at<'r>(value: &'r Value, path: Vec<&'r str>, desc: &'r str) -> Path<'r> {
-        Path {
-            path: path,
-            value: value,
-            desc: desc,
+impl<E: fmt::Debug + fmt::Display> error::Error for At<E> {}
+
+/// A collection of errors found while decoding.
+#[derive(Debug, Clone)]
+pub struct Errors(pub Vec<At<Error>>);
+
+/// Group of errors with the same code at the same relative location.
+///
+/// Created by `Errors::deduplicate_by_kind`.
+#[derive(Debug, Clone)]
+pub struct ErrorGroup<'e> {
+    /// Code of every error in this group, see `Error::code`.
+    pub code: &'static str,
+    /// Path of the errors, with array indices replaced by `*`.
+    pub path_pattern: String,
+    /// All errors in this group, in the order they were found.
+    pub errors: Vec<&'e At<Error>>,
+}
+
+impl Errors {
+    /// Group repeated errors, so bulk validation output stays readable.
+    ///
+    /// Errors are grouped by `Error::code`, and by the path with every array index
+    /// replaced by `*`. This way "missing `id`" in each of 42 elements of `servers`
+    /// becomes a single group with path pattern `servers[*].id`. Groups are returned in
+    /// the order of their first error.
+    pub fn deduplicate_by_kind(&self) -> Vec<ErrorGroup<'_>> {
+        let mut groups: Vec<ErrorGroup> = vec![];
+        for error in &self.0 {
+            let code = error.error.code();
+            let path_pattern = error.path
+                .split('[')
+                .enumerate()
+                .map(|(i, c)| {
+                    let len = c.find(']').unwrap_or(0);
+                    if i > 0 && len > 0 && c[..len].bytes().all(|b| b.is_ascii_digit()) {
+                        format!("*{}", &c[len..])
+                    } else {
+                        c.to_string()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("[");
+            match groups.iter_mut().find(|g| g.code == code && g.path_pattern == path_pattern) {
+                Some(group) => group.errors.push(error),
+                None => {
+                    groups.push(ErrorGroup {
+                        code,
+                        path_pattern,
+                        errors: vec![error],
+                    })
+                }
+            }
         }
+        groups
     }
+}
 
-    /// Clone into a new Path with specified value.
-    pub fn clone_with(&'a self, value: &'a Value) -> Path<'a> {
-        Path::<'a> {
-            value: value,
-            path: self.path.clone(),
-            desc: self.desc,
+impl fmt::Display for Errors {
+    /// Formats every error on its own line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
         }
+        Ok(())
     }
+}
 
-    /// Return path to this configuration.
-    pub fn components(&self) -> &[&str] {
-        &self.path
+impl error::Error for Errors {}
+
+impl Errors {
+    /// Set the source location of every error, see `At::locate`.
+    pub fn locate(self, source_map: &SourceMap) -> Errors {
+        Errors(self.0.into_iter().map(|e| e.locate(source_map)).collect())
     }
+}
 
-    /// Return raw value of this configuration.
-    pub fn value(&self) -> &Value {
-        &self.value
+/// Iterator over the elements of an array value, see `Path::elements`.
+pub struct Elements<'a> {
+    path: &'a Path<'a>,
+    values: iter::Enumerate<slice::Iter<'a, Value>>,
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = Path<'a>;
+
+    fn next(&mut self) -> Option<Path<'a>> {
+        let (index, value) = self.values.next()?;
+        let desc = format!("element {} of {}", index, self.path.desc);
+        self.path.record_child("[]", &desc);
+        Some(Path {
+            value,
+            path: self.path.path.index(index),
+            desc: Cow::Owned(desc),
+            lenient: self.path.lenient,
+            secret: self.path.secret,
+            warnings: self.path.warnings,
+            schema: self.path.schema,
+            limits: self.path.limits,
+        })
     }
 
-    /// Return description of the configuration at this path.
-    pub fn description(&self) -> &str {
-        &self.desc
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.values.size_hint()
+    }
+}
+
+/// Error-collecting access to a path, see `Path::collect`.
+pub struct Collector<'a> {
+    path: &'a Path<'a>,
+    errors: RefCell<Vec<At<Error>>>,
+}
+
+impl<'a> Collector<'a> {
+    /// Return the path being collected from.
+    pub fn path(&self) -> &'a Path<'a> {
+        self.path
     }
 
-    /// Join decode path component that must be a property of this table value.
+    /// Return the value of a successful result, or record the error and return `None`.
+    pub fn check<T>(&self, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.errors.borrow_mut().push(error);
+                None
+            }
+        }
+    }
+
+    /// Decode the property of the table value with `f`, recording any error.
     ///
-    /// Will return error if the value is not a table, or the table does not have
-    /// specified property.
-    pub fn table_property(&'a self,
-                          property_name: &'a str,
-                          property_desc: &'a str)
-                          -> Result<Path<'a>> {
-        let mut path = self.path.clone();
-        path.push(property_name);
-        Ok(Path::<'a> {
-            value: match self.as_table()?.get(property_name) {
-                Some(value) => value,
-                None => {
-                    return Err(Error::ExpectedProperty(Property {
-                        name: property_name.to_string(),
-                        desc: property_desc.to_string(),
-                    })
-                        .at(Self::path_as_string(&path)))
-                }
-            },
-            path: path,
-            desc: property_desc,
-        })
+    /// Returns `None` if the property is missing or `f` fails.
+    pub fn table_property<D, T, F>(&self,
+                                   property_name: &'a str,
+                                   property_desc: D,
+                                   f: F)
+                                   -> Option<T>
+        where D: Into<Cow<'a, str>>,
+              F: FnOnce(&Path) -> Result<T>
+    {
+        self.check(self.path.table_property(property_name, property_desc).and_then(|p| f(&p)))
     }
 
-    /// Join decode path component and use specified value as if it was the child.
-    pub fn join(&'a self,
-                value: &'a Value,
-                property_name: &'a str,
-                property_desc: &'a str)
-                -> Path<'a> {
-        let mut path = self.path.clone();
-        path.push(property_name);
-        Path::<'a> {
-            value: value,
-            path: path,
-            desc: property_desc,
+    /// Decode the property of the table value with `f` if it is present, recording any
+    /// error, see `Path::optional_validated`.
+    ///
+    /// Returns `None` if the property is missing or `f` fails.
+    pub fn optional_table_property<D, T, F>(&self,
+                                            property_name: &'a str,
+                                            property_desc: D,
+                                            f: F)
+                                            -> Option<T>
+        where D: Into<Cow<'a, str>>,
+              F: FnOnce(&Path) -> Result<T>
+    {
+        self.check(self.path.optional_validated(property_name, property_desc, f)).and_then(|v| v)
+    }
+
+    /// Decode the table property with `f` using a nested collector, so errors inside the
+    /// property are collected too instead of stopping at the first one.
+    ///
+    /// Returns `None` if the property is missing, or anything was recorded inside it.
+    pub fn nested<D, T, F>(&self, property_name: &'a str, property_desc: D, f: F) -> Option<T>
+        where D: Into<Cow<'a, str>>,
+              F: FnOnce(&Collector) -> T
+    {
+        let path = self.check(self.path.table_property(property_name, property_desc))?;
+        let nested = Collector {
+            path: &path,
+            errors: RefCell::new(vec![]),
+        };
+        let value = f(&nested);
+        let errors = nested.errors.into_inner();
+        if errors.is_empty() {
+            return Some(value);
         }
+        self.errors.borrow_mut().extend(errors);
+        None
     }
+}
 
-    pub fn as_str(&self) -> Result<&str> {
-        self.value
-            .as_str()
-            .ok_or_else(|| Error::ExpectedTable { desc: self.desc.into() }.at(self.to_string()))
+impl<'e> fmt::Display for ErrorGroup<'e> {
+    /// Formats as `42 x E_EXPECTED_PROPERTY at 'servers[*].id'`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} x {} at '{}'", self.errors.len(), self.code, self.path_pattern)
     }
+}
 
-    pub fn as_integer(&self) -> Result<i64> {
-        self.value
-            .as_integer()
-            .ok_or_else(|| Error::ExpectedInteger { desc: self.desc.into() }.at(self.to_string()))
+/// A problem in the config that does not prevent decoding it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// A deprecated property was used instead of its replacement.
+    Deprecated {
+        name: String,
+        replacement: String,
+    },
+    /// An array element replaced an earlier element with the same key at `previous`.
+    Overridden {
+        key: String,
+        previous: String,
+    },
+    /// An integer outside of the allowed range was replaced by the nearest bound.
+    Clamped {
+        value: i64,
+        clamped: i64,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::Deprecated { ref name, ref replacement } => {
+                write!(f, "key '{}' is deprecated, use '{}'", name, replacement)
+            }
+            Warning::Overridden { ref key, ref previous } => {
+                write!(f, "'{}' overrides the earlier element at '{}'", key, previous)
+            }
+            Warning::Clamped { value, clamped } => {
+                write!(f, "value {} is out of range, using {}", value, clamped)
+            }
+        }
     }
+}
 
-    pub fn as_float(&self) -> Result<f64> {
-        self.value
-            .as_float()
-            .ok_or_else(|| Error::ExpectedFloat { desc: self.desc.into() }.at(self.to_string()))
+/// Sink collecting warnings found while decoding.
+///
+/// Attach it to the root path with `Path::with_warnings`; every path derived from the
+/// root records its warnings here.
+#[derive(Debug, Default)]
+pub struct Warnings(RefCell<Vec<At<Warning>>>);
+
+impl Warnings {
+    pub fn new() -> Warnings {
+        Warnings::default()
     }
 
-    pub fn as_bool(&self) -> Result<bool> {
-        self.value
-            .as_bool()
-            .ok_or_else(|| Error::ExpectedBool { desc: self.desc.into() }.at(self.to_string()))
+    /// Return true if no warning was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
     }
 
-    pub fn as_datetime(&self) -> Result<&str> {
-        self.value
-            .as_datetime()
-            .ok_or_else(|| Error::ExpectedDatetime { desc: self.desc.into() }.at(self.to_string()))
+    /// Take the recorded warnings, in the order they were found.
+    pub fn into_vec(self) -> Vec<At<Warning>> {
+        self.0.into_inner()
     }
 
-    pub fn as_slice(&self) -> Result<&[Value]> {
-        self.value
-            .as_slice()
-            .ok_or_else(|| Error::ExpectedSlice { desc: self.desc.into() }.at(self.to_string()))
+    fn push(&self, warning: At<Warning>) {
+        self.0.borrow_mut().push(warning)
     }
+}
 
-    pub fn as_table(&self) -> Result<&Table> {
-        self.value
-            .as_table()
-            .ok_or_else(|| Error::ExpectedTable { desc: self.desc.into() }.at(self.to_string()))
+/// Description of the properties read by a decoder, for generating config reference
+/// documentation.
+///
+/// Attach it to the root path with `Path::with_schema` and run the decoder once, for
+/// example against a sample config. Every property looked up by name is recorded with
+/// its description, even if it is missing, and gets the type of the first getter called
+/// on it, like `integer` for `as_integer`. Elements of all arrays are recorded as a single
+/// `[]` entry, and tables with user-chosen keys get a `*` entry. Entries are kept in the
+/// order they were first read.
+///
+/// A decoder stops at the first missing required property, so to document everything
+/// from an incomplete sample, such as an empty table, read the properties through
+/// `Path::collect`, which goes on after errors.
+#[derive(Debug, Default)]
+pub struct Schema(RefCell<Vec<SchemaEntry>>);
+
+/// A property recorded by `Schema`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaEntry {
+    /// Property name, or `[]` for array elements.
+    pub name: String,
+    pub desc: String,
+    /// Type expected by the decoder, `None` if no getter was called on the property.
+    pub kind: Option<&'static str>,
+    /// Value found for the property when it was read, unless it is a table.
+    pub example: Option<Value>,
+    /// Values accepted by the decoder, if it only accepts a fixed set, like the strings
+    /// passed to `Path::one_of_str`.
+    pub allowed: Vec<Value>,
+    pub children: Vec<SchemaEntry>,
+}
+
+impl Schema {
+    pub fn new() -> Schema {
+        Schema::default()
+    }
+
+    /// Schema with `entries` as its top-level properties, to render entries that were
+    /// not recorded from a decoder.
+    pub fn from_entries(entries: Vec<SchemaEntry>) -> Schema {
+        Schema(RefCell::new(entries))
+    }
+
+    /// Return the recorded top-level properties.
+    pub fn entries(&self) -> Vec<SchemaEntry> {
+        self.0.borrow().clone()
     }
 
-    fn path_as_string(path: &Vec<&str>) -> String {
+    /// Render the recorded properties as a nested markdown list, like
+    /// ``- `port` (integer): port to listen on``.
+    pub fn to_markdown(&self) -> String {
         let mut result = String::new();
-        let mut first = true;
-        for v in path {
-            if first {
-                result.push_str(v);
-                first = false;
-                continue;
+        write_entries(&self.0.borrow(), 0, "- `", "`", &mut result);
+        result
+    }
+
+    /// Render the recorded properties as indented plain text, like
+    /// `port (integer): port to listen on`.
+    pub fn to_text(&self) -> String {
+        let mut result = String::new();
+        write_entries(&self.0.borrow(), 0, "", "", &mut result);
+        result
+    }
+
+    /// Render the recorded properties as a commented example TOML document.
+    ///
+    /// Every property is preceded by a comment with its description and type, and has the
+    /// value it had in the decoded config. Properties that were missing there are written
+    /// commented out, with their first allowed value or a placeholder value of their type.
+    /// Tables become `[section]`s and arrays of tables `[[section]]`s with a single element.
+    pub fn to_example_toml(&self) -> String {
+        let mut result = String::new();
+        write_example(&self.0.borrow(), &[], &mut result);
+        result.trim_start().to_string()
+    }
+
+    /// Build a sample config with every recorded property, as a starting point for users.
+    ///
+    /// Scalars have the value found in the decoded config, or else their first allowed
+    /// value, or else a placeholder of their type, like `""` or `0`. Properties read
+    /// without a getter are left out, and arrays of tables get a single element. Render
+    /// it with `Value::to_pretty_string`, or `to_toml_string` to write a config file.
+    pub fn sample_value(&self) -> Value {
+        sample_table(&self.0.borrow())
+    }
+
+    /// Report every property of `value` that the decoder did not look up, as
+    /// `UnexpectedProperty` errors with a "did you mean" suggestion.
+    ///
+    /// Run the decoder with this schema on `value` first. Only tables whose properties
+    /// were looked up by name are checked, and so are the elements of arrays decoded
+    /// element by element. Tables read with `Path::as_table_of` or `as_table_entries`
+    /// accept any key, but their entries are checked too. Errors are in key order.
+    pub fn unknown_properties(&self, value: &Value) -> result::Result<(), Errors> {
+        let mut errors = vec![];
+        find_unknown(&self.0.borrow(), value, &mut vec![], &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Errors(errors))
+        }
+    }
+
+    fn record<S: AsRef<str>>(&self,
+                             path: &[S],
+                             desc: &str,
+                             kind: Option<&'static str>,
+                             example: Option<&Value>) {
+        if !path.is_empty() {
+            record_entry(&mut self.0.borrow_mut(), path, desc, kind, example);
+        }
+    }
+
+    fn record_allowed<S: AsRef<str>>(&self, path: &[S], desc: &str, allowed: Vec<Value>) {
+        if !path.is_empty() {
+            let mut entries = self.0.borrow_mut();
+            let entry = record_entry(&mut entries, path, desc, None, None);
+            if entry.allowed.is_empty() {
+                entry.allowed = allowed;
             }
-            result.push('.');
-            result.push_str(v);
         }
-        result
     }
 }
 
-impl<'a> fmt::Display for Path<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Self::path_as_string(&self.path).fmt(f)
+fn record_entry<'e, S: AsRef<str>>(entries: &'e mut Vec<SchemaEntry>,
+                                   path: &[S],
+                                   desc: &str,
+                                   kind: Option<&'static str>,
+                                   example: Option<&Value>)
+                                   -> &'e mut SchemaEntry {
+    let component = path[0].as_ref();
+    let name = if is_index_component(component) {
+        "[]"
+    } else {
+        component
+    };
+    let position = match entries.iter().position(|e| e.name == name) {
+        Some(position) => position,
+        None => {
+            entries.push(SchemaEntry {
+                name: name.to_string(),
+                desc: String::new(),
+                kind: None,
+                example: None,
+                allowed: vec![],
+                children: vec![],
+            });
+            entries.len() - 1
+        }
+    };
+    let entry = &mut entries[position];
+    if path.len() > 1 {
+        return record_entry(&mut entry.children, &path[1..], desc, kind, example);
+    }
+    if entry.desc.is_empty() {
+        entry.desc = desc.to_string();
     }
+    if entry.kind.is_none() {
+        entry.kind = kind;
+    }
+    if entry.example.is_none() {
+        entry.example = example.filter(|v| v.as_table().is_none()).cloned();
+    }
+    entry
 }
 
-pub type Result<T> = result::Result<T, At<Error>>;
+fn find_unknown(entries: &[SchemaEntry],
+                value: &Value,
+                path: &mut Vec<PathComponent>,
+                errors: &mut Vec<At<Error>>) {
+    let known = |name: &str| entries.iter().find(|e| e.name == name);
+    match *value {
+        Value::Table(ref t) => {
+            for (key, value) in t {
+                path.push(PathComponent::Key(key.clone()));
+                match known(key) {
+                    Some(entry) => find_unknown(&entry.children, value, path, errors),
+                    None if known("*").is_some() => (),
+                    None => {
+                        let names = entries.iter()
+                            .map(|e| &e.name[..])
+                            .filter(|&name| name != "*" && name != "[]");
+                        errors.push(Error::UnexpectedProperty {
+                                name: key.clone(),
+                                suggestion: closest_name(key, names),
+                            }
+                            .at(components_to_string(path)));
+                    }
+                }
+                path.pop();
+            }
+        }
+        Value::Array(ref a) => {
+            if let Some(element) = known("[]") {
+                for (index, value) in a.iter().enumerate() {
+                    path.push(PathComponent::Index(index));
+                    find_unknown(&element.children, value, path, errors);
+                    path.pop();
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Return the name most similar to `name`, if it is close enough to be a likely typo.
+pub(crate) fn closest_name<'n, I>(name: &str, names: I) -> Option<String>
+    where I: IntoIterator<Item = &'n str>
+{
+    let max_distance = cmp::max(1, name.chars().count() / 3);
+    names.into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Edit distance between `a` and `b` in characters, counting insertions, deletions,
+/// substitutions and swaps of adjacent characters, so `prot` is 1 away from `port`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..b.len() + 1).collect();
+    for i in 1..a.len() + 1 {
+        for j in 1..b.len() + 1 {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = cmp::min(rows[i - 1][j - 1] + cost,
+                                        cmp::min(rows[i - 1][j], rows[i][j - 1]) + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = cmp::min(distance, rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+fn write_entries(entries: &[SchemaEntry],
+                 depth: usize,
+                 before_name: &str,
+                 after_name: &str,
+                 out: &mut String) {
+    for entry in entries {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(&format!("{}{}{}", before_name, entry.name, after_name));
+        if let Some(kind) = entry.kind {
+            out.push_str(&format!(" ({})", kind));
+        }
+        if !entry.desc.is_empty() {
+            out.push_str(&format!(": {}", entry.desc));
+        }
+        out.push('\n');
+        write_entries(&entry.children, depth + 1, before_name, after_name, out);
+    }
+}
+
+fn write_example(entries: &[SchemaEntry], section: &[String], out: &mut String) {
+    let element = |entry: &SchemaEntry| {
+        entry.children.iter().find(|c| c.name == "[]" && !c.children.is_empty()).cloned()
+    };
+    let is_section = |entry: &SchemaEntry| {
+        element(entry).is_some() ||
+        (!entry.children.is_empty() && entry.children.iter().all(|c| c.name != "[]"))
+    };
+    for entry in entries.iter().filter(|e| !is_section(e) && e.name != "*") {
+        write_example_comment(entry, out);
+        let value = match entry.example {
+            Some(ref value) => toml_inline(value),
+            None => {
+                out.push_str("# ");
+                placeholder(entry).map_or_else(|| "...".to_string(), |v| toml_inline(&v))
+            }
+        };
+        out.push_str(&format!("{} = {}\n", toml_key(&entry.name), value));
+    }
+    for entry in entries.iter().filter(|e| is_section(e)) {
+        let mut path = section.to_vec();
+        path.push(toml_key(&entry.name));
+        out.push('\n');
+        write_example_comment(entry, out);
+        match element(entry) {
+            Some(element) => {
+                out.push_str(&format!("[[{}]]\n", path.join(".")));
+                write_example(&element.children, &path, out);
+            }
+            None => {
+                out.push_str(&format!("[{}]\n", path.join(".")));
+                write_example(&entry.children, &path, out);
+            }
+        }
+    }
+}
+
+/// Value to show for `entry` if there is no example, the first allowed value if any.
+fn placeholder(entry: &SchemaEntry) -> Option<Value> {
+    if let Some(value) = entry.allowed.first() {
+        return Some(value.clone());
+    }
+    Some(match entry.kind? {
+        "string" => Value::String(String::new()),
+        "integer" | "number" => Value::Integer(0),
+        "float" => Value::Float(0.0),
+        "boolean" => Value::Boolean(false),
+        "datetime" => Value::Datetime("1979-05-27T07:32:00Z".to_string()),
+        "array" => Value::Array(vec![]),
+        _ => return None,
+    })
+}
+
+fn sample_table(entries: &[SchemaEntry]) -> Value {
+    let mut table = Table::new();
+    for entry in entries.iter().filter(|e| e.name != "*") {
+        if let Some(value) = sample_entry(entry) {
+            table.insert(entry.name.clone(), value);
+        }
+    }
+    Value::Table(table)
+}
+
+fn sample_entry(entry: &SchemaEntry) -> Option<Value> {
+    match entry.children.iter().find(|c| c.name == "[]") {
+        Some(element) if !element.children.is_empty() => {
+            Some(Value::Array(vec![sample_table(&element.children)]))
+        }
+        None if !entry.children.is_empty() || entry.kind == Some("table") => {
+            Some(sample_table(&entry.children))
+        }
+        _ => entry.example.clone().or_else(|| placeholder(entry)),
+    }
+}
+
+fn write_example_comment(entry: &SchemaEntry, out: &mut String) {
+    match (entry.desc.is_empty(), entry.kind) {
+        (false, Some(kind)) => out.push_str(&format!("# {} ({})\n", entry.desc, kind)),
+        (false, None) => out.push_str(&format!("# {}\n", entry.desc)),
+        (true, Some(kind)) => out.push_str(&format!("# ({})\n", kind)),
+        (true, None) => (),
+    }
+}
+
+/// Write a key bare if TOML allows it, quoted otherwise.
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty() &&
+               key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+    if bare {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+fn toml_string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04X}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Render a value as an inline TOML value, like `[1, 2]` or `{ a = 1 }`.
+fn toml_inline(value: &Value) -> String {
+    match *value {
+        Value::String(ref s) => toml_string(s),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) if f.is_nan() => "nan".to_string(),
+        Value::Float(f) if f.is_infinite() => {
+            if f > 0.0 { "inf" } else { "-inf" }.to_string()
+        }
+        Value::Float(f) => format!("{:?}", f),
+        Value::Boolean(b) => b.to_string(),
+        Value::Datetime(ref s) => s.clone(),
+        Value::Null => "null".to_string(),
+        Value::Array(ref a) => {
+            format!("[{}]", a.iter().map(toml_inline).collect::<Vec<_>>().join(", "))
+        }
+        Value::Table(ref t) if t.is_empty() => "{}".to_string(),
+        Value::Table(ref t) => {
+            format!("{{ {} }}",
+                    t.iter()
+                        .map(|(k, v)| format!("{} = {}", toml_key(k), toml_inline(v)))
+                        .collect::<Vec<_>>()
+                        .join(", "))
+        }
+    }
+}
+
+/// Location of a value in the source text, as byte offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Position of a value in a named source file, like `config.toml:14:3`.
+///
+/// Lines and columns start at 1, columns are counted in characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Locations of values in the file they were parsed from, by path.
+///
+/// Paths are written like `At::path`, for example `servers[2].host`. A loader that
+/// knows where values come from fills the map while parsing (see
+/// `TomlFormat::parse_with_source_map`), and errors found later are given locations
+/// with `At::locate` or `Errors::locate`.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    file: String,
+    positions: BTreeMap<String, (usize, usize)>,
+}
+
+impl SourceMap {
+    /// Empty map for the source file named `file`.
+    pub fn new(file: &str) -> SourceMap {
+        SourceMap {
+            file: file.to_string(),
+            positions: BTreeMap::new(),
+        }
+    }
+
+    /// Return the name of the source file.
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// Record that the value at `path` starts at `line` and `column`.
+    pub fn insert(&mut self, path: String, line: usize, column: usize) {
+        self.positions.insert(path, (line, column));
+    }
+
+    /// Record that the value at `path` starts at byte offset `span.start` of `source`.
+    pub fn insert_span(&mut self, path: String, source: &str, span: Span) {
+        let mut start = cmp::min(span.start, source.len());
+        while !source.is_char_boundary(start) {
+            start -= 1;
+        }
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line = source[..line_start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        self.insert(path, line, column);
+    }
+
+    /// Return the location of the value at `path`, or of its closest parent.
+    pub fn location(&self, path: &str) -> Option<Location> {
+        let mut path = path;
+        loop {
+            if let Some(&(line, column)) = self.positions.get(path) {
+                return Some(Location {
+                    file: self.file.clone(),
+                    line,
+                    column,
+                });
+            }
+            if path.is_empty() {
+                return None;
+            }
+            path = parent_path(path);
+        }
+    }
+
+    /// Return the keys of `table`, the table at `path`, in the order they are written in
+    /// the source.
+    ///
+    /// `Table` keeps its keys sorted, so this is the way to list them as the author did,
+    /// for example when writing the config back. Keys without a recorded location, like
+    /// ones added after parsing, come last in key order.
+    pub fn keys_in_source_order<'t>(&self, path: &str, table: &'t Table) -> Vec<&'t str> {
+        let mut keys: Vec<(Option<(usize, usize)>, &str)> = table.keys()
+            .map(|key| {
+                let mut child = path.to_string();
+                push_key(&mut child, key);
+                (self.positions.get(&child).cloned(), &key[..])
+            })
+            .collect();
+        keys.sort_by_key(|&(position, _)| (position.is_none(), position));
+        keys.into_iter().map(|(_, key)| key).collect()
+    }
+
+    /// Copy `table`, the table at `path`, into an `OrderedTable` with its entries in the
+    /// order of `keys_in_source_order`.
+    #[cfg(feature = "indexmap")]
+    pub fn ordered_table(&self, path: &str, table: &Table) -> OrderedTable {
+        self.keys_in_source_order(path, table)
+            .into_iter()
+            .map(|key| (key.to_string(), table[key].clone()))
+            .collect()
+    }
+}
+
+/// Remove the last component of a path written by `Path::path_as_string`.
+fn parent_path(path: &str) -> &str {
+    let end = match path.rfind(['.', '[']) {
+        Some(i) => i,
+        None => return "",
+    };
+    // A quoted key may contain `.` and `[`, find where its brackets open.
+    if path.ends_with("\"]") {
+        if let Some(i) = path.rfind("[\"") {
+            return &path[..i];
+        }
+    }
+    &path[..end]
+}
+
+const TAB_WIDTH: usize = 4;
+
+impl At<Error> {
+    /// Render this error together with the source line it points at, like a compiler.
+    ///
+    /// The `span` is the location of the offending value in `source`. Columns are counted
+    /// in characters, and tabs are expanded to 4-column stops, so the caret lines up with
+    /// the displayed line.
+    pub fn render_with_source(&self, source: &str, span: Span) -> String {
+        let mut start = cmp::min(span.start, source.len());
+        while !source.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut end = cmp::min(cmp::max(span.end, start), source.len());
+        while !source.is_char_boundary(end) {
+            end -= 1;
+        }
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+        let line = source[line_start..line_end].trim_end_matches('\r');
+        let line_number = source[..line_start].matches('\n').count() + 1;
+
+        let mut text = String::new();
+        let mut column = 0;
+        let mut caret_start = None;
+        let mut caret_end = None;
+        for (offset, c) in line.char_indices() {
+            if line_start + offset == start {
+                caret_start = Some(column);
+            }
+            if line_start + offset == end {
+                caret_end = Some(column);
+            }
+            if c == '\t' {
+                let spaces = TAB_WIDTH - column % TAB_WIDTH;
+                text.push_str(&" ".repeat(spaces));
+                column += spaces;
+            } else {
+                text.push(c);
+                column += 1;
+            }
+        }
+        let caret_start = caret_start.unwrap_or(column);
+        let caret_end = cmp::max(caret_end.unwrap_or(column), caret_start + 1);
+
+        let number = line_number.to_string();
+        let gutter = " ".repeat(number.len());
+        format!("{} {}\n{}--> line {}, column {}\n{} |\n{} | {}\n{} | {}{}\n",
+                self.error.code(),
+                self,
+                gutter,
+                line_number,
+                caret_start + 1,
+                gutter,
+                number,
+                text,
+                gutter,
+                " ".repeat(caret_start),
+                "^".repeat(caret_end - caret_start))
+    }
+}
+
+/// A decoded unit-bearing value together with the text it was decoded from.
+///
+/// Returned by the `_measured` variants of the byte size and duration getters, so that
+/// both can be logged, like "buffer=10MB (10485760 bytes)".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measured<T> {
+    /// The canonical value: a number of bytes, or a `Duration`.
+    pub value: T,
+    /// The value as written in the config, like `"10MB"`, or the plain integer as a string.
+    pub original: String,
+}
+
+/// Components of a decode path, shared between a path and its children.
+///
+/// Every child adds a single node pointing at the components of its parent, so drilling
+/// down does not copy the path, and the string is only built when it is needed.
+#[derive(Debug, Clone, Default)]
+struct Components<'a>(Option<Rc<Node<'a>>>);
+
+#[derive(Debug)]
+struct Node<'a> {
+    parent: Components<'a>,
+    component: Component<'a>,
+}
+
+/// A single path component, either a table key or an array index written like `[2]`.
+///
+/// The kind is kept next to the name, so a key that reads `[2]` is still quoted when the
+/// path is written out.
+#[derive(Debug, Clone)]
+struct Component<'a> {
+    name: Cow<'a, str>,
+    index: Option<usize>,
+}
+
+impl<'a> Component<'a> {
+    fn into_owned(self) -> Component<'static> {
+        Component {
+            name: Cow::Owned(self.name.into_owned()),
+            index: self.index,
+        }
+    }
+
+    fn push_to(&self, result: &mut String) {
+        if self.index.is_some() {
+            result.push_str(&self.name);
+        } else {
+            push_key(result, &self.name);
+        }
+    }
+}
+
+impl<'a> Components<'a> {
+    fn child(&self, component: Cow<'a, str>) -> Components<'a> {
+        self.push(Component {
+            name: component,
+            index: None,
+        })
+    }
+
+    fn index(&self, index: usize) -> Components<'a> {
+        self.push(Component {
+            name: Cow::Owned(format!("[{}]", index)),
+            index: Some(index),
+        })
+    }
+
+    fn push(&self, component: Component<'a>) -> Components<'a> {
+        Components(Some(Rc::new(Node {
+            parent: self.clone(),
+            component,
+        })))
+    }
+
+    fn nodes(&self) -> Vec<&Component<'a>> {
+        let mut components = vec![];
+        let mut node = self.0.as_ref();
+        while let Some(n) = node {
+            components.push(&n.component);
+            node = n.parent.0.as_ref();
+        }
+        components.reverse();
+        components
+    }
+
+    fn to_vec(&self) -> Vec<&str> {
+        self.nodes().into_iter().map(|c| &c.name[..]).collect()
+    }
+
+    fn to_path_components(&self) -> Vec<PathComponent> {
+        self.nodes()
+            .into_iter()
+            .map(|c| match c.index {
+                Some(index) => PathComponent::Index(index),
+                None => PathComponent::Key(c.name.to_string()),
+            })
+            .collect()
+    }
+}
+
+impl<'a> fmt::Display for Components<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        components_as_string(self.nodes()).fmt(f)
+    }
+}
+
+fn components_as_string<'c, 'a: 'c, I>(components: I) -> String
+    where I: IntoIterator<Item = &'c Component<'a>>
+{
+    let mut result = String::new();
+    for component in components {
+        component.push_to(&mut result);
+    }
+    result
+}
+
+/// Append a table key to a path, quoted in brackets if it can not be written plainly.
+pub(crate) fn push_key(result: &mut String, key: &str) {
+    if key.contains(&['.', '[', ']'][..]) {
+        result.push_str(&format!("[{:?}]", key));
+    } else {
+        if !result.is_empty() {
+            result.push('.');
+        }
+        result.push_str(key);
+    }
+}
+
+/// A size checked by `Limits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Depth,
+    TableEntries,
+    ArrayLength,
+    StringLength,
+    Nodes,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Limit::Depth => "nesting depth",
+            Limit::TableEntries => "table size",
+            Limit::ArrayLength => "array length",
+            Limit::StringLength => "string length",
+            Limit::Nodes => "value count",
+        })
+    }
+}
+
+/// Maximum sizes of values, for configuration from untrusted sources.
+///
+/// There are no limits by default. Limits are checked on a whole value with `check`,
+/// while parsing with `Format::parse_limited`, on the sources and the result of a
+/// `ConfigBuilder` with `ConfigBuilder::limits`, on merges with
+/// `Value::merge_with_limits`, and while decoding with `Path::with_limits`.
+/// Exceeding a limit is a `LimitExceeded` error at the path of the offending value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    depth: Option<usize>,
+    table_entries: Option<usize>,
+    array_length: Option<usize>,
+    string_length: Option<usize>,
+    nodes: Option<usize>,
+}
+
+impl Limits {
+    pub fn new() -> Limits {
+        Limits::default()
+    }
+
+    /// Allow tables and arrays at paths of at most `depth` components, so with a depth
+    /// of 1, `[server]` is fine but `[server.tls]` is not. Scalars are not counted.
+    pub fn max_depth(self, depth: usize) -> Limits {
+        Limits { depth: Some(depth), ..self }
+    }
+
+    pub fn max_table_entries(self, entries: usize) -> Limits {
+        Limits { table_entries: Some(entries), ..self }
+    }
+
+    pub fn max_array_length(self, length: usize) -> Limits {
+        Limits { array_length: Some(length), ..self }
+    }
+
+    /// Allow strings and table keys of at most `length` bytes.
+    pub fn max_string_length(self, length: usize) -> Limits {
+        Limits { string_length: Some(length), ..self }
+    }
+
+    /// Allow at most `nodes` values in total, counting tables, arrays and scalars, but
+    /// not table keys.
+    ///
+    /// This is the limit that keeps YAML aliases from expanding into a huge tree: the
+    /// YAML format counts values while it parses, see `Format::parse_limited`.
+    pub fn max_nodes(self, nodes: usize) -> Limits {
+        Limits { nodes: Some(nodes), ..self }
+    }
+
+    /// Check `value` and all nested values, returning the first limit exceeded.
+    ///
+    /// The tree is walked without recursion, so a deeply nested value can not overflow
+    /// the stack here.
+    pub fn check(&self, value: &Value) -> Result<()> {
+        enum Children<'v> {
+            Table(btree_map::Iter<'v, String, Value>),
+            Array(iter::Enumerate<slice::Iter<'v, Value>>),
+        }
+        fn children(value: &Value) -> Option<Children<'_>> {
+            match *value {
+                Value::Table(ref t) => Some(Children::Table(t.iter())),
+                Value::Array(ref a) => Some(Children::Array(a.iter().enumerate())),
+                _ => None,
+            }
+        }
+        let mut path: Vec<PathComponent> = vec![];
+        let mut stack: Vec<Children> = vec![];
+        let mut nodes = 1;
+        self.check_nodes(nodes, &[])?;
+        self.check_value(value, &[])?;
+        stack.extend(children(value));
+        while let Some(top) = stack.last_mut() {
+            let child = match *top {
+                Children::Table(ref mut entries) => {
+                    entries.next().map(|(key, child)| (PathComponent::Key(key.clone()), child))
+                }
+                Children::Array(ref mut elements) => {
+                    elements.next().map(|(index, child)| (PathComponent::Index(index), child))
+                }
+            };
+            match child {
+                Some((component, child)) => {
+                    path.push(component);
+                    nodes += 1;
+                    self.check_nodes(nodes, &path)?;
+                    self.check_value(child, &path)?;
+                    match children(child) {
+                        Some(grandchildren) => stack.push(grandchildren),
+                        None => {
+                            path.pop();
+                        }
+                    }
+                }
+                None => {
+                    stack.pop();
+                    path.pop();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that `nodes` values, the last one at `path`, are within `max_nodes`.
+    pub(crate) fn check_nodes(&self, nodes: usize, path: &[PathComponent]) -> Result<()> {
+        match self.nodes {
+            Some(max) if nodes > max => {
+                Err(Error::LimitExceeded {
+                        limit: Limit::Nodes,
+                        max,
+                        found: nodes,
+                    }
+                    .at(components_to_string(path)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the size of `value` itself, and the lengths of its keys if it is a table.
+    fn check_value(&self, value: &Value, path: &[PathComponent]) -> Result<()> {
+        let depth = path.len();
+        let (limit, max, found) = match *value {
+            Value::Table(ref t) => {
+                let long_key = self.string_length
+                    .and_then(|max| t.keys().find(|k| k.len() > max).map(|k| (max, k)));
+                if let Some((max, key)) = long_key {
+                    let mut key_path = path.to_vec();
+                    key_path.push(PathComponent::Key(key.clone()));
+                    return Err(Error::LimitExceeded {
+                            limit: Limit::StringLength,
+                            max,
+                            found: key.len(),
+                        }
+                        .at(components_to_string(&key_path)));
+                }
+                match (self.depth, self.table_entries) {
+                    (Some(max), _) if depth > max => (Limit::Depth, max, depth),
+                    (_, Some(max)) if t.len() > max => (Limit::TableEntries, max, t.len()),
+                    _ => return Ok(()),
+                }
+            }
+            Value::Array(ref a) => {
+                match (self.depth, self.array_length) {
+                    (Some(max), _) if depth > max => (Limit::Depth, max, depth),
+                    (_, Some(max)) if a.len() > max => (Limit::ArrayLength, max, a.len()),
+                    _ => return Ok(()),
+                }
+            }
+            Value::String(ref s) => {
+                match self.string_length {
+                    Some(max) if s.len() > max => (Limit::StringLength, max, s.len()),
+                    _ => return Ok(()),
+                }
+            }
+            _ => return Ok(()),
+        };
+        Err(Error::LimitExceeded { limit, max, found }.at(components_to_string(path)))
+    }
+}
+
+/// Text shown instead of secret values, see `Path::secret`.
+pub const REDACTED: &str = "***";
+
+#[derive(Clone)]
+pub struct Path<'a> {
+    path: Components<'a>,
+    value: &'a Value,
+    desc: Cow<'a, str>,
+    lenient: bool,
+    secret: bool,
+    warnings: Option<&'a Warnings>,
+    schema: Option<&'a Schema>,
+    limits: Option<&'a Limits>,
+}
+
+impl<'a> fmt::Debug for Path<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value: &dyn fmt::Debug = if self.secret { &REDACTED } else { self.value };
+        f.debug_struct("Path")
+            .field("path", &self.path)
+            .field("value", value)
+            .field("desc", &self.desc)
+            .field("lenient", &self.lenient)
+            .field("secret", &self.secret)
+            .field("warnings", &self.warnings)
+            .field("schema", &self.schema)
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+/// Path class here encapsulates decoding a value at specific path.
+///
+/// Additionaly this value contains description that will be used in errors.
+///
+/// There are methods like `table_property` to drill down deeper into this value and
+/// construct the documentation at the same time.
+///
+/// And in case the error occurs, the result will contain perfect information of what and
+/// where happened.
+impl<'a> Path<'a> {
+    /// Construct root value with specified description.
+    ///
+    /// The description can be borrowed or owned, so generated descriptions work too.
+    pub fn new<'r, D>(value: &'r Value, desc: D) -> Path<'r>
+        where D: Into<Cow<'r, str>>
+    {
+        Path {
+            path: Components::default(),
+            value,
+            desc: desc.into(),
+            lenient: false,
+            secret: false,
+            warnings: None,
+            schema: None,
+            limits: None,
+        }
+    }
+
+    /// Construct a value at specified path.
+    pub fn new_at<'r, D>(value: &'r Value, path: Vec<&'r str>, desc: D) -> Path<'r>
+        where D: Into<Cow<'r, str>>
+    {
+        Path {
+            path: path.into_iter().fold(Components::default(), |path, c| {
+                path.push(Component {
+                    name: Cow::Borrowed(c),
+                    index: index_component(c),
+                })
+            }),
+            value,
+            desc: desc.into(),
+            lenient: false,
+            secret: false,
+            warnings: None,
+            schema: None,
+            limits: None,
+        }
+    }
+
+    /// Construct a value at the path given by `components`, like `Path::new_at`.
+    pub(crate) fn new_at_components<'r, D>(value: &'r Value,
+                                           components: &'r [PathComponent],
+                                           desc: D)
+                                           -> Path<'r>
+        where D: Into<Cow<'r, str>>
+    {
+        let path = components.iter().fold(Components::default(), |path, c| match *c {
+            PathComponent::Key(ref key) => path.child(Cow::Borrowed(key)),
+            PathComponent::Index(index) => path.index(index),
+        });
+        Path { path, ..Path::new(value, desc) }
+    }
+
+    /// Switch this path and all paths derived from it to lenient mode.
+    ///
+    /// Intended to be set once at the root for sources with poor type fidelity, such as
+    /// environment variables. The mode is passed on to every child created by
+    /// `table_property`, `join` and the other drill-down methods. In lenient mode:
+    ///
+    /// - `as_integer` also accepts strings containing an integer and floats without a
+    ///   fractional part, like `as_integer_lenient`;
+    /// - `as_float` also accepts integers and strings containing a number, like
+    ///   `as_float_lenient`;
+    /// - `as_bool` also accepts the strings and integers listed in `as_bool_lenient`.
+    ///
+    /// Other getters behave the same as in strict mode.
+    pub fn lenient(self) -> Path<'a> {
+        Path { lenient: true, ..self }
+    }
+
+    /// Return true if this path is in lenient mode.
+    pub fn is_lenient(&self) -> bool {
+        self.lenient
+    }
+
+    /// Mark this path and all paths derived from it as holding a secret, like a password.
+    ///
+    /// Errors at secret paths carry `REDACTED` instead of the value, and `Debug` output
+    /// of the path hides the value too. Messages written by validation closures are not
+    /// checked, so they should not include the value.
+    pub fn secret(self) -> Path<'a> {
+        Path { secret: true, ..self }
+    }
+
+    /// Return true if this path holds a secret.
+    pub fn is_secret(&self) -> bool {
+        self.secret
+    }
+
+    /// Record warnings of this path and all paths derived from it in `warnings`.
+    ///
+    /// Without a sink, warnings are discarded.
+    pub fn with_warnings(self, warnings: &'a Warnings) -> Path<'a> {
+        Path { warnings: Some(warnings), ..self }
+    }
+
+    /// Check `limits` in the getters of this path and all paths derived from it.
+    ///
+    /// `as_str`, `as_slice` and `as_table`, and the getters using them, return a
+    /// `LimitExceeded` error for a value exceeding the limits, so a decoder never walks
+    /// into one.
+    pub fn with_limits(self, limits: &'a Limits) -> Path<'a> {
+        Path { limits: Some(limits), ..self }
+    }
+
+    /// Record the properties read through this path and all paths derived from it in
+    /// `schema`.
+    pub fn with_schema(self, schema: &'a Schema) -> Path<'a> {
+        Path { schema: Some(schema), ..self }
+    }
+
+    /// Clone into a new Path with specified value.
+    pub fn clone_with(&'a self, value: &'a Value) -> Path<'a> {
+        Path::<'a> {
+            value,
+            path: self.path.clone(),
+            desc: self.desc.clone(),
+            lenient: self.lenient,
+            secret: self.secret,
+            warnings: self.warnings,
+            schema: self.schema,
+            limits: self.limits,
+        }
+    }
+
+    /// Return path to this configuration.
+    ///
+    /// Array element components are written with brackets, like `[2]`. This used to return
+    /// `&[&str]`, but paths no longer keep their components in a slice, so they are
+    /// collected into a `Vec`. Use `iter_components` to only borrow them.
+    pub fn components(&self) -> Vec<&str> {
+        self.path.to_vec()
+    }
+
+    /// Iterate over the path to this configuration, from the root, like `components`.
+    pub fn iter_components(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator {
+        self.path.nodes().into_iter().map(|c| &c.name[..])
+    }
+
+    /// Return raw value of this configuration.
+    pub fn value(&self) -> &Value {
+        self.value
+    }
+
+    /// Return true if the value is `Value::Null`.
+    pub fn is_null(&self) -> bool {
+        self.value.is_null()
+    }
+
+    /// Return description of the configuration at this path.
+    pub fn description(&self) -> &str {
+        &self.desc
+    }
+
+    /// Decode with `f`, collecting every error instead of stopping at the first one.
+    ///
+    /// The `Collector` accessors record failures and return `None`, so `f` can go on
+    /// reading the other properties. If anything was recorded, all errors are returned
+    /// in the order they were found, and the value produced by `f` is dropped.
+    pub fn collect<T, F>(&'a self, f: F) -> result::Result<T, Errors>
+        where F: FnOnce(&Collector<'a>) -> T
+    {
+        let collector = Collector {
+            path: self,
+            errors: RefCell::new(vec![]),
+        };
+        let value = f(&collector);
+        let errors = collector.errors.into_inner();
+        if errors.is_empty() {
+            Ok(value)
+        } else {
+            Err(Errors(errors))
+        }
+    }
+
+    /// Decode this value into `T` using its `DecodeConfig` implementation.
+    pub fn decode<T: DecodeConfig>(&self) -> Result<T> {
+        T::decode(self)
+    }
+
+    /// Join decode path component that must be a property of this table value.
+    ///
+    /// Will return error if the value is not a table, or the table does not have
+    /// specified property.
+    pub fn table_property<D>(&'a self,
+                             property_name: &'a str,
+                             property_desc: D)
+                             -> Result<Path<'a>>
+        where D: Into<Cow<'a, str>>
+    {
+        let property_desc = property_desc.into();
+        let path = self.path.child(Cow::Borrowed(property_name));
+        self.record_child(property_name, &property_desc);
+        Ok(Path::<'a> {
+            value: match self.as_table()?.get(property_name) {
+                Some(value) => value,
+                None => {
+                    return Err(Error::ExpectedProperty(Property {
+                        name: property_name.to_string(),
+                        desc: property_desc.to_string(),
+                    })
+                        .at(path.to_string()))
+                }
+            },
+            path,
+            desc: property_desc,
+            lenient: self.lenient,
+            secret: self.secret,
+            warnings: self.warnings,
+            schema: self.schema,
+            limits: self.limits,
+        })
+    }
+
+    /// Same as `table_property`, marking the property as a secret, see `secret`.
+    pub fn secret_table_property<D>(&'a self,
+                                    property_name: &'a str,
+                                    property_desc: D)
+                                    -> Result<Path<'a>>
+        where D: Into<Cow<'a, str>>
+    {
+        self.table_property(property_name, property_desc).map(Path::secret)
+    }
+
+    /// Join decode path component that must be a property of this table value, matching
+    /// the property name ignoring ASCII case.
+    ///
+    /// The path of the result uses the key as written in the table. Will return error if
+    /// the value is not a table, if no key matches, or `AmbiguousProperty` error if
+    /// several keys match, like both `Port` and `port`.
+    pub fn table_property_ci<D>(&'a self,
+                                property_name: &'a str,
+                                property_desc: D)
+                                -> Result<Path<'a>>
+        where D: Into<Cow<'a, str>>
+    {
+        let matches: Vec<_> = self.as_table()?
+            .iter()
+            .filter(|&(key, _)| key.eq_ignore_ascii_case(property_name))
+            .collect();
+        match matches.len() {
+            0 => self.table_property(property_name, property_desc),
+            1 => Ok(self.join(matches[0].1, matches[0].0, property_desc)),
+            _ => {
+                Err(Error::AmbiguousProperty {
+                        name: property_name.to_string(),
+                        matches: matches.iter().map(|&(key, _)| key.clone()).collect(),
+                    }
+                    .at(self.child_path_string(property_name)))
+            }
+        }
+    }
+
+    /// Join decode path component that may be a property of this table value.
+    ///
+    /// Returns `None` if the table does not have the property, or if it is null. A
+    /// property of any other type is returned, so the getter called on it reports a
+    /// precise type error. Will return error if the value is not a table.
+    pub fn optional_table_property<D>(&'a self,
+                                      property_name: &'a str,
+                                      property_desc: D)
+                                      -> Result<Option<Path<'a>>>
+        where D: Into<Cow<'a, str>>
+    {
+        let property_desc = property_desc.into();
+        let table = self.as_table()?;
+        self.record_child(property_name, &property_desc);
+        Ok(table.get(property_name)
+            .filter(|value| !value.is_null())
+            .map(|value| self.join(value, property_name, property_desc)))
+    }
+
+    /// Decode the property of this table value with `f`, if the property is present.
+    ///
+    /// This is the recommended way to read an optional property that must be valid when
+    /// it is set: a missing property is `None`, a present one must pass `f`, and a value
+    /// that is not a table is an error.
+    pub fn optional_validated<D, T, F>(&'a self,
+                                       property_name: &'a str,
+                                       property_desc: D,
+                                       f: F)
+                                       -> Result<Option<T>>
+        where D: Into<Cow<'a, str>>,
+              F: FnOnce(&Path) -> Result<T>
+    {
+        match self.optional_table_property(property_name, property_desc)? {
+            Some(property) => f(&property).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Decode the property of this table value with `f`, or return `default` if the
+    /// property is missing.
+    ///
+    /// A property that is present but fails `f`, for example because of the wrong type,
+    /// is still an error at the path of the property.
+    pub fn table_property_or<D, T, F>(&'a self,
+                                      property_name: &'a str,
+                                      property_desc: D,
+                                      default: T,
+                                      f: F)
+                                      -> Result<T>
+        where D: Into<Cow<'a, str>>,
+              F: FnOnce(&Path) -> Result<T>
+    {
+        self.optional_validated(property_name, property_desc, f)
+            .map(|value| value.unwrap_or(default))
+    }
+
+    /// Same as `table_property_or`, using `T::default()` for a missing property.
+    pub fn table_property_or_default<D, T, F>(&'a self,
+                                              property_name: &'a str,
+                                              property_desc: D,
+                                              f: F)
+                                              -> Result<T>
+        where D: Into<Cow<'a, str>>,
+              T: Default,
+              F: FnOnce(&Path) -> Result<T>
+    {
+        self.table_property_or(property_name, property_desc, T::default(), f)
+    }
+
+    /// Join decode path component of this table value, falling back to the same property
+    /// of `ref_root`, for example a shared `defaults` table.
+    ///
+    /// The local property wins if both have it. If neither has it, `ExpectedProperty`
+    /// error is reported at the local path. Will return error if either value is not
+    /// a table.
+    pub fn table_property_or_ref<D>(&'a self,
+                                    property_name: &'a str,
+                                    ref_root: &'a Path<'a>,
+                                    property_desc: D)
+                                    -> Result<Path<'a>>
+        where D: Into<Cow<'a, str>>
+    {
+        if self.as_table()?.contains_key(property_name) ||
+           !ref_root.as_table()?.contains_key(property_name) {
+            return self.table_property(property_name, property_desc);
+        }
+        ref_root.table_property(property_name, property_desc)
+    }
+
+    /// Join the deprecated property `old` of this table value, if it is present.
+    ///
+    /// If the property exists, a `Warning::Deprecated` naming `new` as the replacement is
+    /// recorded at its path. Returns `None` if the property is missing, and an error if
+    /// the value is not a table.
+    pub fn deprecated_property<D>(&'a self,
+                                  old: &'a str,
+                                  new: &str,
+                                  property_desc: D)
+                                  -> Result<Option<Path<'a>>>
+        where D: Into<Cow<'a, str>>
+    {
+        let value = match self.as_table()?.get(old) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let path = self.join(value, old, property_desc);
+        path.warn(Warning::Deprecated {
+            name: path.to_string(),
+            replacement: new.to_string(),
+        });
+        Ok(Some(path))
+    }
+
+    /// Join the property `name` of this table value, falling back to its old `aliases`.
+    ///
+    /// The first alias present is used only when `name` is missing, and is reported as
+    /// `Warning::Deprecated` naming the path of `name` as the replacement. If neither is
+    /// present, `ExpectedProperty` error is reported for `name`.
+    pub fn table_property_with_aliases<D>(&'a self,
+                                          name: &'a str,
+                                          aliases: &[&'a str],
+                                          property_desc: D)
+                                          -> Result<Path<'a>>
+        where D: Into<Cow<'a, str>>
+    {
+        let property_desc = property_desc.into();
+        if !self.as_table()?.contains_key(name) {
+            let replacement = self.child_path_string(name);
+            for alias in aliases {
+                if let Some(path) = self.deprecated_property(alias,
+                                                             &replacement,
+                                                             property_desc.clone())? {
+                    return Ok(path);
+                }
+            }
+        }
+        self.table_property(name, property_desc)
+    }
+
+    /// Return true if this table value has the property `name`, whatever its value.
+    ///
+    /// This is for flags where presence means on, like `FEATURE_X=` in the environment.
+    /// Even an explicit `false` counts as present, so use `as_bool_lenient` on the
+    /// property when its value should decide instead. Returns false if this value is not
+    /// a table.
+    pub fn as_flag_presence(&self, name: &str) -> bool {
+        self.value.as_table().is_some_and(|t| t.contains_key(name))
+    }
+
+    /// Check that this table value has no properties other than `consumed`.
+    ///
+    /// Call it after reading the known properties to catch typos like `tiemout = 30`.
+    /// Only the keys of this table are checked, so nested tables need their own call.
+    /// The first unknown key in key order is reported as `UnexpectedProperty` at its
+    /// own path, suggesting the most similar consumed key. Will return error if the value
+    /// is not a table.
+    pub fn deny_unknown_keys(&self, consumed: &[&str]) -> Result<()> {
+        match self.as_table()?.keys().find(|k| !consumed.contains(&&k[..])) {
+            Some(name) => {
+                Err(Error::UnexpectedProperty {
+                        name: name.clone(),
+                        suggestion: closest_name(name, consumed.iter().cloned()),
+                    }
+                    .at(self.child_path_string(name)))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Check that properties `a` and `b` of this table value are not both present.
+    ///
+    /// Having only one of them, or none at all, is fine. Will return error if the
+    /// value is not a table, or if both properties exist.
+    pub fn require_not_both(&self, a: &str, b: &str) -> Result<()> {
+        let table = self.as_table()?;
+        if table.contains_key(a) && table.contains_key(b) {
+            return Err(Error::ConflictingProperties {
+                    paths: vec![self.child_path_string(a), self.child_path_string(b)],
+                }
+                .at(self.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Check that all listed properties of this table value are present.
+    ///
+    /// Properties are given as `(name, description)` pairs. Every missing property is
+    /// reported in a single `ExpectedProperties` error, so an empty table lists them all.
+    pub fn require_all_properties(&self, properties: &[(&str, &str)]) -> Result<()> {
+        let table = self.as_table()?;
+        let missing: Vec<Property> = properties.iter()
+            .filter(|&&(name, _)| !table.contains_key(name))
+            .map(|&(name, desc)| {
+                Property {
+                    name: name.to_string(),
+                    desc: desc.to_string(),
+                }
+            })
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::ExpectedProperties(missing).at(self.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Return a child path for every property of this table value, in key order.
+    ///
+    /// An empty table yields no entries.
+    pub fn as_table_entries<D>(&'a self, value_desc: D) -> Result<Vec<(&'a str, Path<'a>)>>
+        where D: Into<Cow<'a, str>>
+    {
+        let value_desc = value_desc.into();
+        self.record_child("*", &value_desc);
+        Ok(self.as_table()?
+            .iter()
+            .map(|(name, value)| (&name[..], self.join(value, name, value_desc.clone())))
+            .collect())
+    }
+
+    /// Decode every property of this table value with `f`, into a map with the same keys.
+    ///
+    /// This is for tables whose keys are chosen by the user, like `[databases.analytics]`.
+    /// Each value gets a child path with its key, so errors are reported at paths like
+    /// `databases.analytics.url`, and a description like "analytics in databases".
+    pub fn as_table_of<T, F>(&self, mut f: F) -> Result<BTreeMap<String, T>>
+        where F: FnMut(&Path) -> Result<T>
+    {
+        self.record_child("*", &format!("any key in {}", self.desc));
+        let mut map = BTreeMap::new();
+        for (key, value) in self.as_table()? {
+            let desc = format!("{} in {}", key, self.desc);
+            map.insert(key.clone(), f(&self.join(value, key, desc))?);
+        }
+        Ok(map)
+    }
+
+    /// Same as `as_table_entries`, but every key is first checked with `key_check`, which
+    /// returns an error message on failure.
+    ///
+    /// An invalid key is reported as `IncorrectValue` holding the key, at the path of its
+    /// entry.
+    pub fn as_table_entries_validated<F, D>(&'a self,
+                                            key_check: F,
+                                            value_desc: D)
+                                            -> Result<Vec<(&'a str, Path<'a>)>>
+        where F: Fn(&str) -> result::Result<(), String>,
+              D: Into<Cow<'a, str>>
+    {
+        for name in self.as_table()?.keys() {
+            if let Err(message) = key_check(name) {
+                return Err(Error::IncorrectValue {
+                        explanation: Some(format!("key {:?} of {} is invalid: {}",
+                                                  name,
+                                                  self.desc,
+                                                  message)),
+                        value: Value::String(name.clone()),
+                        possible_list: vec![],
+                    }
+                    .at(self.child_path_string(name)));
+            }
+        }
+        self.as_table_entries(value_desc)
+    }
+
+    /// Return a child path for every element of this array value.
+    ///
+    /// Children get the index in brackets as the last path component, so errors are
+    /// reported at paths like `servers[2].host`.
+    pub fn array_elements<D>(&'a self, element_desc: D) -> Result<Vec<Path<'a>>>
+        where D: Into<Cow<'a, str>>
+    {
+        let element_desc = element_desc.into();
+        self.record_child("[]", &element_desc);
+        Ok(self.as_slice()?
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                Path {
+                    value,
+                    path: self.path.index(index),
+                    desc: element_desc.clone(),
+                    lenient: self.lenient,
+                    secret: self.secret,
+                    warnings: self.warnings,
+                    schema: self.schema,
+                    limits: self.limits,
+                }
+            })
+            .collect())
+    }
+
+    /// Iterate over child paths of the elements of this array value.
+    ///
+    /// Like in `as_slice_of`, children get the index in brackets as the last path
+    /// component and a description like "element 2 of servers". Will return error if the
+    /// value is not an array.
+    pub fn elements(&'a self) -> Result<Elements<'a>> {
+        Ok(Elements {
+            path: self,
+            values: self.as_slice()?.iter().enumerate(),
+        })
+    }
+
+    /// Join decode path component and use specified value as if it was the child.
+    pub fn join<D>(&'a self,
+                   value: &'a Value,
+                   property_name: &'a str,
+                   property_desc: D)
+                   -> Path<'a>
+        where D: Into<Cow<'a, str>>
+    {
+        Path::<'a> {
+            value,
+            path: self.path.child(Cow::Borrowed(property_name)),
+            desc: property_desc.into(),
+            lenient: self.lenient,
+            secret: self.secret,
+            warnings: self.warnings,
+            schema: self.schema,
+            limits: self.limits,
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str> {
+        self.record("string");
+        self.check_limits()?;
+        self.value
+            .as_str()
+            .ok_or_else(|| {
+                self.type_error(Error::ExpectedString { desc: self.desc.to_string() })
+            })
+    }
+
+    pub fn as_integer(&self) -> Result<i64> {
+        self.record("integer");
+        if self.lenient {
+            return self.as_integer_lenient();
+        }
+        self.value
+            .as_integer()
+            .ok_or_else(|| {
+                self.type_error(Error::ExpectedInteger { desc: self.desc.to_string() })
+            })
+    }
+
+    /// Read a float, like `ratio = 0.5`.
+    ///
+    /// An integer like `timeout = 5` is an `ExpectedFloat` error outside of lenient mode,
+    /// so that the type stays exact; use `as_number` for values that may be written
+    /// either way.
+    pub fn as_float(&self) -> Result<f64> {
+        self.record("float");
+        if self.lenient {
+            return self.as_float_lenient();
+        }
+        self.value
+            .as_float()
+            .ok_or_else(|| {
+                self.type_error(Error::ExpectedFloat { desc: self.desc.to_string() })
+            })
+    }
+
+    /// Read a number, accepting both floats and integers, like `timeout = 5`.
+    ///
+    /// Unlike `as_float`, which accepts only floats unless in lenient mode.
+    pub fn as_number(&self) -> Result<f64> {
+        self.record("number");
+        match *self.value {
+            Value::Integer(i) => Ok(i as f64),
+            _ => self.as_float(),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool> {
+        self.record("boolean");
+        if self.lenient {
+            return self.as_bool_lenient();
+        }
+        self.value
+            .as_bool()
+            .ok_or_else(|| self.type_error(Error::ExpectedBool { desc: self.desc.to_string() }))
+    }
+
+    pub fn as_datetime(&self) -> Result<&str> {
+        self.record("datetime");
+        self.value
+            .as_datetime()
+            .ok_or_else(|| {
+                self.type_error(Error::ExpectedDatetime { desc: self.desc.to_string() })
+            })
+    }
+
+    /// Extract the datetime value and parse it, keeping its kind: with an offset, local,
+    /// date only or time only.
+    ///
+    /// Will return `ExpectedDatetime` error if the value is not a datetime, and
+    /// `IncorrectValue` error if the string is not a valid datetime.
+    #[cfg(feature = "chrono")]
+    pub fn as_parsed_datetime(&self) -> Result<Datetime> {
+        let s = self.as_datetime()?;
+        Datetime::parse(s).ok_or_else(|| {
+            self.incorrect_value(format!("{} is not a valid datetime", self.desc))
+        })
+    }
+
+    /// Extract the datetime value and parse it as RFC 3339, like `1979-05-27T07:32:00Z`.
+    ///
+    /// A space is accepted instead of the `T`. A local datetime without an offset is
+    /// taken as UTC. Will return `ExpectedDatetime` error if the value is not a datetime,
+    /// or if the string can not be parsed or has no time of day.
+    #[cfg(feature = "chrono")]
+    pub fn as_chrono_datetime(&self) -> Result<DateTime<FixedOffset>> {
+        match Datetime::parse(self.as_datetime()?) {
+            Some(Datetime::Offset(datetime)) => Ok(datetime),
+            Some(Datetime::Local(local)) => Ok(local.and_utc().fixed_offset()),
+            _ => Err(self.type_error(Error::ExpectedDatetime { desc: self.desc.to_string() })),
+        }
+    }
+
+    pub fn as_slice(&self) -> Result<&[Value]> {
+        self.record("array");
+        self.check_limits()?;
+        self.value
+            .as_slice()
+            .ok_or_else(|| {
+                self.type_error(Error::ExpectedSlice { desc: self.desc.to_string() })
+            })
+    }
+
+    /// Return a copy of the elements of this array value.
+    ///
+    /// This clones every element, including nested tables and arrays, so prefer the
+    /// borrowing `as_slice` unless the elements must outlive the configuration.
+    pub fn as_slice_cloned(&self) -> Result<Vec<Value>> {
+        self.as_slice().map(|s| s.to_vec())
+    }
+
+    /// Read the array, checking that it has at least `min` distinct elements.
+    ///
+    /// Elements are compared with `==`, so `1` and `1.0` are distinct, and a NaN float is
+    /// distinct from every element, including other NaNs.
+    pub fn as_slice_min_distinct(&self, min: usize) -> Result<&[Value]> {
+        let slice = self.as_slice()?;
+        let distinct = slice.iter()
+            .enumerate()
+            .filter(|&(index, value)| !slice[..index].contains(value))
+            .count();
+        if distinct < min {
+            return Err(self.incorrect_value(format!("{} must have at least {} distinct \
+                                                     elements, but has {}",
+                                                    self.desc,
+                                                    min,
+                                                    distinct)));
+        }
+        Ok(slice)
+    }
+
+    /// Read the array, checking that it has at least one element.
+    pub fn as_nonempty_slice(&self) -> Result<&[Value]> {
+        let slice = self.as_slice()?;
+        if slice.is_empty() {
+            return Err(self.incorrect_value(format!("{} must not be empty", self.desc)));
+        }
+        Ok(slice)
+    }
+
+    pub fn as_table(&self) -> Result<&Table> {
+        self.record("table");
+        self.check_limits()?;
+        self.value
+            .as_table()
+            .ok_or_else(|| {
+                self.type_error(Error::ExpectedTable { desc: self.desc.to_string() })
+            })
+    }
+
+    /// Decode every element of this array value with `f`.
+    ///
+    /// Each element gets a child path with its index appended in brackets, like `servers[2]`,
+    /// and a description like "element 2 of servers", so errors deep inside an element point
+    /// at the element.
+    pub fn as_slice_of<T, F>(&self, f: F) -> Result<Vec<T>>
+        where F: FnMut(&Path) -> Result<T>
+    {
+        self.as_slice_of_with_desc(|index, desc| format!("element {} of {}", index, desc), f)
+    }
+
+    /// Same as `as_slice_of`, but element descriptions are produced by `element_desc`.
+    ///
+    /// The hook receives the element index and the description of this array value.
+    pub fn as_slice_of_with_desc<T, D, F>(&self, element_desc: D, mut f: F) -> Result<Vec<T>>
+        where D: Fn(usize, &str) -> String,
+              F: FnMut(&Path) -> Result<T>
+    {
+        let slice = self.as_slice()?;
+        let mut result = Vec::with_capacity(slice.len());
+        for (index, value) in slice.iter().enumerate() {
+            let desc = element_desc(index, &self.desc);
+            self.record_child("[]", &desc);
+            result.push(f(&Path {
+                value,
+                path: self.path.index(index),
+                desc: Cow::Owned(desc),
+                lenient: self.lenient,
+                secret: self.secret,
+                warnings: self.warnings,
+                schema: self.schema,
+                limits: self.limits,
+            })?);
+        }
+        Ok(result)
+    }
+
+    /// Same as `as_slice_of`, but every element gets the description `element_desc`.
+    ///
+    /// Stops at the first element that fails, the error path includes its index, like
+    /// `items[3]`.
+    pub fn decode_array<T, F>(&self, element_desc: &str, f: F) -> Result<Vec<T>>
+        where F: Fn(&Path) -> Result<T>
+    {
+        self.as_slice_of_with_desc(|_, _| element_desc.to_string(), f)
+    }
+
+    /// Decode every element of this array of tables into a map keyed by a string field.
+    ///
+    /// Each element is decoded with `f`, and stored under the value of its `key_field`
+    /// property. Two elements with the same key produce `DuplicateKey` error at the
+    /// second element.
+    pub fn as_slice_of_keyed<T, F>(&self,
+                                   key_field: &str,
+                                   mut f: F)
+                                   -> Result<BTreeMap<String, T>>
+        where F: FnMut(&Path) -> Result<T>
+    {
+        let mut result = BTreeMap::new();
+        self.as_slice_of(|element| {
+                let key_path = element.table_property(key_field,
+                                                      format!("name of {}", element.desc))?;
+                let key = key_path.as_str()?;
+                if result.contains_key(key) {
+                    return Err(Error::DuplicateKey { name: key.to_string() }
+                        .at(element.to_string()));
+                }
+                let value = f(element)?;
+                result.insert(key.to_string(), value);
+                Ok(())
+            })?;
+        Ok(result)
+    }
+
+    /// Decode every element of this array of tables, keeping only the last element of
+    /// those with the same string `key_field` property.
+    ///
+    /// A later element takes the place of the earlier one in the result, so the order is
+    /// the order in which the keys first appear. Every replaced element is recorded as
+    /// `Warning::Overridden` at the path of the replacing element, see `with_warnings`.
+    pub fn as_slice_of_dedup_by<T, F>(&self, key_field: &str, mut f: F) -> Result<Vec<T>>
+        where F: FnMut(&Path) -> Result<T>
+    {
+        let mut keys: Vec<(String, String)> = vec![];
+        let mut result = vec![];
+        self.as_slice_of(|element| {
+                let key_path = element.table_property(key_field,
+                                                      format!("name of {}", element.desc))?;
+                let key = key_path.as_str()?;
+                let value = f(element)?;
+                match keys.iter().position(|k| k.0 == key) {
+                    Some(index) => {
+                        element.warn(Warning::Overridden {
+                            key: key.to_string(),
+                            previous: keys[index].1.clone(),
+                        });
+                        keys[index].1 = element.to_string();
+                        result[index] = value;
+                    }
+                    None => {
+                        keys.push((key.to_string(), element.to_string()));
+                        result.push(value);
+                    }
+                }
+                Ok(())
+            })?;
+        Ok(result)
+    }
+
+    /// Decode an internally tagged table, like `{ type = "s3", bucket = "..." }`.
+    ///
+    /// Reads the string property `tag_key`, and runs the decoder registered for that tag
+    /// on this whole table. Unknown tags produce `IncorrectValue` listing the known tags.
+    pub fn as_tagged<T>(&self,
+                        tag_key: &str,
+                        decoders: &[(&str, Decoder<T>)])
+                        -> Result<T> {
+        let tag_path = self.table_property(tag_key, format!("kind of {}", self.desc))?;
+        let tag = tag_path.as_str()?;
+        match decoders.iter().find(|&&(name, _)| name == tag) {
+            Some(&(_, decoder)) => decoder(self),
+            None => {
+                Err(Error::IncorrectValue {
+                        explanation: Some(format!("unknown {}", tag_path.desc)),
+                        value: tag_path.found_value(),
+                        possible_list: decoders.iter()
+                            .map(|&(name, _)| Value::String(name.to_string()))
+                            .collect(),
+                    }
+                    .at(tag_path.to_string()))
+            }
+        }
+    }
+
+    /// Read an integer, also accepting strings that contain an integer, like `"8080"`.
+    ///
+    /// Besides decimal, strings can use the `0x` (hexadecimal), `0o` (octal) and `0b`
+    /// (binary) prefixes, like `"0xFF"` or `"-0o755"`. A string that does not fit `i64` is
+    /// reported as too large (or too small), separately from a string that is not a
+    /// number at all.
+    ///
+    /// Floats without a fractional part that fit `i64` are accepted too, like `5.0`,
+    /// which some formats produce for every number.
+    pub fn as_integer_lenient(&self) -> Result<i64> {
+        let s = match *self.value {
+            Value::Integer(i) => return Ok(i),
+            Value::Float(f) => {
+                return if f.is_nan() || (f.is_finite() && f.fract() != 0.0) {
+                    Err(self.incorrect_value(format!("{} must be an integer, but the number has \
+                                                      a fractional part",
+                                                     self.desc)))
+                } else if f >= i64::MAX as f64 {
+                    Err(self.incorrect_value(format!("{} is an integer too large, the maximum \
+                                                      is {}",
+                                                     self.desc,
+                                                     i64::MAX)))
+                } else if f < i64::MIN as f64 {
+                    Err(self.incorrect_value(format!("{} is an integer too small, the minimum \
+                                                      is {}",
+                                                     self.desc,
+                                                     i64::MIN)))
+                } else {
+                    Ok(f as i64)
+                };
+            }
+            Value::String(ref s) => s.trim(),
+            _ => {
+                return Err(self.type_error(Error::ExpectedInteger { desc: self.desc.to_string() }))
+            }
+        };
+        let (sign, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (radix, name, digits) = match unsigned.get(..2) {
+            Some("0x") | Some("0X") => (16, "hexadecimal", &unsigned[2..]),
+            Some("0o") | Some("0O") => (8, "octal", &unsigned[2..]),
+            Some("0b") | Some("0B") => (2, "binary", &unsigned[2..]),
+            _ => (10, "decimal", unsigned),
+        };
+        let invalid = || if radix == 10 {
+            format!("{} must be an integer, but the string is not a number (decimal, or \
+                     prefixed with 0x, 0o or 0b)",
+                    self.desc)
+        } else {
+            format!("{} is not a valid {} integer", self.desc, name)
+        };
+        if digits.starts_with('+') || digits.starts_with('-') {
+            return Err(self.incorrect_value(invalid()));
+        }
+        i64::from_str_radix(&format!("{}{}", sign, digits), radix).map_err(|e| {
+            self.incorrect_value(match *e.kind() {
+                IntErrorKind::PosOverflow => {
+                    format!("{} is an integer too large, the maximum is {}",
+                            self.desc,
+                            i64::MAX)
+                }
+                IntErrorKind::NegOverflow => {
+                    format!("{} is an integer too small, the minimum is {}",
+                            self.desc,
+                            i64::MIN)
+                }
+                _ => invalid(),
+            })
+        })
+    }
+
+    /// Read a string that must be one of the `allowed` values, like `"fast"` or `"safe"`.
+    ///
+    /// Any other string produces `IncorrectValue` listing the allowed values.
+    pub fn one_of_str(&self, allowed: &[&str]) -> Result<&str> {
+        self.record_allowed(|| allowed.iter().map(|&s| Value::String(s.to_string())).collect());
+        let s = self.as_str()?;
+        if allowed.contains(&s) {
+            return Ok(s);
+        }
+        Err(Error::IncorrectValue {
+                explanation: Some(format!("unknown {}", self.desc)),
+                value: self.found_value(),
+                possible_list: allowed.iter().map(|s| Value::String(s.to_string())).collect(),
+            }
+            .at(self.to_string()))
+    }
+
+    /// Read a string naming one of the `variants`, ignoring ASCII case, and return the
+    /// matching value, like `Level::Info` for `"INFO"`.
+    ///
+    /// Any other string produces `IncorrectValue` listing the variant names.
+    pub fn as_enum<T: Clone>(&self, variants: &[(&str, T)]) -> Result<T> {
+        self.record_allowed(|| {
+            variants.iter().map(|&(name, _)| Value::String(name.to_string())).collect()
+        });
+        let s = self.as_str()?;
+        if let Some((_, value)) = variants.iter()
+            .find(|&&(name, _)| name.eq_ignore_ascii_case(s)) {
+            return Ok(value.clone());
+        }
+        Err(Error::IncorrectValue {
+                explanation: Some(format!("unknown {}", self.desc)),
+                value: self.found_value(),
+                possible_list: variants.iter()
+                    .map(|&(name, _)| Value::String(name.to_string()))
+                    .collect(),
+            }
+            .at(self.to_string()))
+    }
+
+    /// Read a string that must match `pattern`.
+    ///
+    /// The pattern is not anchored, so use `^` and `$` to match the whole string.
+    #[cfg(feature = "regex")]
+    pub fn as_str_matching(&self, pattern: &Regex) -> Result<&str> {
+        let s = self.as_str()?;
+        if !pattern.is_match(s) {
+            return Err(self.incorrect_value(format!("{} must match the pattern {}",
+                                                    self.desc,
+                                                    pattern.as_str())));
+        }
+        Ok(s)
+    }
+
+    /// Read an integer that can also be written as one of the labels, like `"warn"`.
+    ///
+    /// A label string is converted to its value. A raw integer is accepted only if it is
+    /// the value of one of the labels. Anything else produces `IncorrectValue` listing the
+    /// labels (or their values, for integers). Labels are searched one by one, so use
+    /// `as_labeled_integer_map` for more than a few dozen.
+    pub fn as_labeled_integer(&self, labels: &[(&str, i64)]) -> Result<i64> {
+        self.labeled_integer(|s| labels.iter().find(|&&(label, _)| label == s).map(|l| l.1),
+                             |i| labels.iter().any(|&(_, v)| v == i),
+                             labels.iter().cloned())
+    }
+
+    /// Same as `as_labeled_integer`, with the labels in a map for a faster lookup.
+    ///
+    /// Build the map once when there are many labels, like thousands of error codes. A
+    /// label string is found in logarithmic time, which is the usual case; a raw integer
+    /// is still checked against every value.
+    pub fn as_labeled_integer_map(&self, labels: &BTreeMap<String, i64>) -> Result<i64> {
+        self.labeled_integer(|s| labels.get(s).cloned(),
+                             |i| labels.values().any(|&v| v == i),
+                             labels.iter().map(|(label, &v)| (&label[..], v)))
+    }
+
+    fn labeled_integer<'l, F, C, I>(&self, find: F, contains: C, labels: I) -> Result<i64>
+        where F: FnOnce(&str) -> Option<i64>,
+              C: FnOnce(i64) -> bool,
+              I: Iterator<Item = (&'l str, i64)> + Clone
+    {
+        self.record_allowed(|| {
+            labels.clone().map(|(label, _)| Value::String(label.to_string())).collect()
+        });
+        if let Value::String(ref s) = *self.value {
+            if let Some(value) = find(s) {
+                return Ok(value);
+            }
+            if !self.lenient {
+                return Err(Error::IncorrectValue {
+                        explanation: Some(format!("unknown {}", self.desc)),
+                        value: self.found_value(),
+                        possible_list: labels.map(|(label, _)| Value::String(label.to_string()))
+                            .collect(),
+                    }
+                    .at(self.to_string()));
+            }
+        }
+        let value = self.as_integer()?;
+        if contains(value) {
+            return Ok(value);
+        }
+        Err(Error::IncorrectValue {
+                explanation: Some(format!("unknown {}", self.desc)),
+                value: self.found_value(),
+                possible_list: labels.map(|(_, v)| Value::Integer(v)).collect(),
+            }
+            .at(self.to_string()))
+    }
+
+    /// Read a float, also accepting integers and strings that contain a number.
+    pub fn as_float_lenient(&self) -> Result<f64> {
+        let s = match *self.value {
+            Value::Float(f) => return Ok(f),
+            Value::Integer(i) => return Ok(i as f64),
+            Value::String(ref s) => s.trim(),
+            _ => {
+                return Err(self.type_error(Error::ExpectedFloat { desc: self.desc.to_string() }))
+            }
+        };
+        s.parse::<f64>().map_err(|_| {
+            self.incorrect_value(format!("{} must be a number, but the string is not a number",
+                                         self.desc))
+        })
+    }
+
+    /// Read a boolean, also accepting common string and integer spellings.
+    ///
+    /// Strings `true`, `yes`, `on`, `1` and `false`, `no`, `off`, `0` are accepted,
+    /// ignoring ASCII case and surrounding whitespace. Integers `1` and `0` are accepted too.
+    pub fn as_bool_lenient(&self) -> Result<bool> {
+        let s = match *self.value {
+            Value::Boolean(b) => return Ok(b),
+            Value::Integer(1) => return Ok(true),
+            Value::Integer(0) => return Ok(false),
+            Value::String(ref s) => s.trim().to_ascii_lowercase(),
+            _ => {
+                return Err(self.type_error(Error::ExpectedBool { desc: self.desc.to_string() }))
+            }
+        };
+        match &s[..] {
+            "true" | "yes" | "on" | "1" => Ok(true),
+            "false" | "no" | "off" | "0" => Ok(false),
+            _ => {
+                Err(Error::IncorrectValue {
+                        explanation: Some(format!("{} must be a boolean", self.desc)),
+                        value: self.found_value(),
+                        possible_list: ["true", "false", "yes", "no", "on", "off", "1", "0"]
+                            .iter()
+                            .map(|s| Value::String(s.to_string()))
+                            .collect(),
+                    }
+                    .at(self.to_string()))
+            }
+        }
+    }
+
+    /// Read the string with `$VAR` and `${VAR}` references expanded from the process
+    /// environment.
+    ///
+    /// Write `$$` for a literal `$`. A `$` not followed by a variable name is kept as-is.
+    /// References to undefined variables are handled as specified by `undefined`.
+    pub fn as_str_env_expanded(&self, undefined: UndefinedVariables) -> Result<String> {
+        let s = self.as_str()?;
+        expand::expand(s,
+                       |name| env::var(name).ok(),
+                       undefined == UndefinedVariables::KeepLiteral)
+            .map_err(|e| {
+                self.incorrect_value(match e {
+                    ExpandError::Undefined(name) => {
+                        format!("{} references undefined environment variable {:?}",
+                                self.desc,
+                                name)
+                    }
+                    ExpandError::Unterminated => {
+                        format!("{} has a \"${{\" without the closing \"}}\"", self.desc)
+                    }
+                })
+            })
+    }
+
+    /// Read the string, checking that every character satisfies `allowed`.
+    ///
+    /// The error points at the first offending character, counting positions in
+    /// characters from zero.
+    pub fn as_str_charset<F>(&self, allowed: F) -> Result<&str>
+        where F: Fn(char) -> bool
+    {
+        let s = self.as_str()?;
+        match s.chars().enumerate().find(|&(_, c)| !allowed(c)) {
+            Some((position, c)) => {
+                Err(self.incorrect_value(format!("{} contains invalid character {:?} at \
+                                                  position {}",
+                                                 self.desc,
+                                                 c,
+                                                 position)))
+            }
+            None => Ok(s),
+        }
+    }
+
+    /// Read the string, checking that it only contains ASCII letters, digits and `_`.
+    pub fn as_identifier(&self) -> Result<&str> {
+        self.as_str_charset(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    /// Read the string, checking that it only contains ASCII characters.
+    ///
+    /// Only non-ASCII characters are rejected; ASCII control characters, like tab, are
+    /// accepted. The error points at the first non-ASCII character, counting positions in
+    /// characters from zero.
+    pub fn as_ascii_str(&self) -> Result<&str> {
+        let s = self.as_str()?;
+        match s.chars().enumerate().find(|&(_, c)| !c.is_ascii()) {
+            Some((position, c)) => {
+                Err(self.incorrect_value(format!("{} must be ASCII, but contains {:?} at \
+                                                  position {}",
+                                                 self.desc,
+                                                 c,
+                                                 position)))
+            }
+            None => Ok(s),
+        }
+    }
+
+    /// Read the string with leading and trailing whitespace removed.
+    ///
+    /// Returns a `Cow` like the other normalizing getters, so they can be swapped for
+    /// each other. Trimming never allocates, the result is always borrowed.
+    pub fn as_str_trimmed(&self) -> Result<Cow<'_, str>> {
+        Ok(Cow::Borrowed(self.as_str()?.trim()))
+    }
+
+    /// Read the string with surrounding whitespace removed, which must not be empty.
+    ///
+    /// The trimmed string is returned owned, since that is what is usually stored.
+    pub fn as_str_trimmed_nonempty(&self) -> Result<String> {
+        let s = self.as_str()?.trim();
+        if s.is_empty() {
+            return Err(self.incorrect_value(format!("{} must not be blank", self.desc)));
+        }
+        Ok(s.to_string())
+    }
+
+    /// Read the string converted to lowercase.
+    ///
+    /// Allocates only if lowercasing actually changes the string.
+    pub fn as_str_lowercased(&self) -> Result<Cow<'_, str>> {
+        let s = self.as_str()?;
+        let lowercase = if s.is_ascii() {
+            !s.bytes().any(|b| b.is_ascii_uppercase())
+        } else {
+            s.chars().flat_map(char::to_lowercase).eq(s.chars())
+        };
+        if lowercase {
+            Ok(Cow::Borrowed(s))
+        } else {
+            Ok(Cow::Owned(s.to_lowercase()))
+        }
+    }
+
+    /// Read the string in Unicode Normalization Form C, so visually identical strings
+    /// written with composed or decomposed accents compare equal.
+    ///
+    /// Allocates only if the string is not already in NFC. Only this value is normalized;
+    /// table keys are used as written.
+    #[cfg(feature = "unicode-normalization")]
+    pub fn as_str_nfc(&self) -> Result<Cow<'_, str>> {
+        let s = self.as_str()?;
+        if is_nfc(s) {
+            Ok(Cow::Borrowed(s))
+        } else {
+            Ok(Cow::Owned(s.nfc().collect()))
+        }
+    }
+
+    /// Read a ratio written as a string of the form `a/b`, for example `"16/9"`.
+    ///
+    /// Both parts must be integers and the denominator must not be zero.
+    pub fn as_ratio(&self) -> Result<f64> {
+        let s = self.as_str()?;
+        let parts = s.split_once('/').and_then(|(n, d)| {
+            match (n.trim().parse::<i64>(), d.trim().parse::<i64>()) {
+                (Ok(n), Ok(d)) => Some((n, d)),
+                _ => None,
+            }
+        });
+        let (numerator, denominator) = match parts {
+            Some(parts) => parts,
+            None => {
+                return Err(self.incorrect_value(format!("{} must be a ratio of two integers, \
+                                                         like \"16/9\"",
+                                                        self.desc)))
+            }
+        };
+        if denominator == 0 {
+            return Err(self.incorrect_value(format!("{} must not have a zero denominator",
+                                                    self.desc)));
+        }
+        Ok(numerator as f64 / denominator as f64)
+    }
+
+    /// Read a float and check it with `check`, which returns an error message on failure.
+    ///
+    /// The message is reported as `IncorrectValue` at this path, prefixed with the
+    /// description, for example "ratio is invalid: must be below 1".
+    pub fn as_float_validated<F>(&self, check: F) -> Result<f64>
+        where F: Fn(f64) -> result::Result<(), String>
+    {
+        let value = self.as_float()?;
+        self.validated(check(value))?;
+        Ok(value)
+    }
+
+    /// Read an integer that must be in the range from `min` to `max`, inclusive.
+    ///
+    /// Same as `as_integer_in(min..=max)`.
+    pub fn integer_in_range(&self, min: i64, max: i64) -> Result<i64> {
+        self.as_integer_in(min..=max)
+    }
+
+    /// Read an integer that must be in `range`, like `1..=65535` or `0..`.
+    pub fn as_integer_in<R: RangeBounds<i64>>(&self, range: R) -> Result<i64> {
+        let value = self.as_integer()?;
+        if range.contains(&value) {
+            return Ok(value);
+        }
+        if let (Bound::Included(min), Bound::Included(max)) = (range.start_bound(),
+                                                                range.end_bound()) {
+            return Err(self.incorrect_value(format!("{} must be between {} and {}",
+                                                    self.desc,
+                                                    min,
+                                                    max)));
+        }
+        let bound = |bound: Bound<&i64>, inclusive: &str, exclusive: &str| {
+            match bound {
+                Bound::Included(b) => Some(format!("{} {}", inclusive, b)),
+                Bound::Excluded(b) => Some(format!("{} {}", exclusive, b)),
+                Bound::Unbounded => None,
+            }
+        };
+        let limits: Vec<_> = bound(range.start_bound(), "at least", "greater than")
+            .into_iter()
+            .chain(bound(range.end_bound(), "at most", "less than"))
+            .collect();
+        Err(self.incorrect_value(format!("{} must be {}", self.desc, limits.join(" and "))))
+    }
+
+    /// Read an integer that fits `u8`.
+    pub fn as_u8(&self) -> Result<u8> {
+        self.integer_in_range(0, u8::MAX as i64).map(|v| v as u8)
+    }
+
+    /// Read an integer that fits `u16`, like a port number.
+    pub fn as_u16(&self) -> Result<u16> {
+        self.integer_in_range(0, u16::MAX as i64).map(|v| v as u16)
+    }
+
+    /// Read an integer that fits `u32`.
+    pub fn as_u32(&self) -> Result<u32> {
+        self.integer_in_range(0, u32::MAX as i64).map(|v| v as u32)
+    }
+
+    /// Read an integer that fits `usize`.
+    ///
+    /// On 64-bit targets the maximum is `i64::MAX`, the largest integer a value can hold.
+    pub fn as_usize(&self) -> Result<usize> {
+        let max = cmp::min(usize::MAX as u64, i64::MAX as u64) as i64;
+        self.integer_in_range(0, max).map(|v| v as usize)
+    }
+
+    /// Read a non-negative integer as `u64`.
+    pub fn as_u64(&self) -> Result<u64> {
+        self.integer_in_range(0, i64::MAX).map(|v| v as u64)
+    }
+
+    /// Read an integer that fits `i8`.
+    pub fn as_i8(&self) -> Result<i8> {
+        self.integer_in_range(i8::MIN as i64, i8::MAX as i64).map(|v| v as i8)
+    }
+
+    /// Read an integer that fits `i16`.
+    pub fn as_i16(&self) -> Result<i16> {
+        self.integer_in_range(i16::MIN as i64, i16::MAX as i64).map(|v| v as i16)
+    }
+
+    /// Read an integer that fits `i32`.
+    pub fn as_i32(&self) -> Result<i32> {
+        self.integer_in_range(i32::MIN as i64, i32::MAX as i64).map(|v| v as i32)
+    }
+
+    /// Read an integer and clamp it into the range from `min` to `max`, inclusive.
+    ///
+    /// A clamped value is recorded as `Warning::Clamped`, see `with_warnings`. Only
+    /// non-integers are rejected, so prefer `integer_in_range` where out of range input
+    /// must not be accepted.
+    ///
+    /// Panics if `min` is greater than `max`, like `Ord::clamp`.
+    pub fn as_integer_clamped(&self, min: i64, max: i64) -> Result<i64> {
+        assert!(min <= max, "as_integer_clamped: min {} is greater than max {}", min, max);
+        let value = self.as_integer()?;
+        let clamped = value.clamp(min, max);
+        if clamped != value {
+            self.warn(Warning::Clamped { value, clamped });
+        }
+        Ok(clamped)
+    }
+
+    /// Read an integer and check it with `check`, see `as_float_validated`.
+    pub fn as_integer_validated<F>(&self, check: F) -> Result<i64>
+        where F: Fn(i64) -> result::Result<(), String>
+    {
+        let value = self.as_integer()?;
+        self.validated(check(value))?;
+        Ok(value)
+    }
+
+    /// Read a string and check it with `check`, see `as_float_validated`.
+    pub fn as_str_validated<F>(&self, check: F) -> Result<&str>
+        where F: Fn(&str) -> result::Result<(), String>
+    {
+        let value = self.as_str()?;
+        self.validated(check(value))?;
+        Ok(value)
+    }
+
+    /// Read a byte size, like `"256MiB"` or `"1.5GB"`, as a number of bytes.
+    ///
+    /// Suffixes are matched ignoring ASCII case, and both `KB` and `KiB` forms are
+    /// multiples of 1024. A plain integer, or a string without a suffix, is a number of
+    /// bytes.
+    pub fn as_byte_size(&self) -> Result<u64> {
+        self.as_byte_size_measured().map(|m| m.value)
+    }
+
+    /// Read a byte size together with the text it was written as.
+    pub fn as_byte_size_measured(&self) -> Result<Measured<u64>> {
+        self.as_measured(units::parse_byte_size, units::BYTE_SIZE_SUFFIXES)
+    }
+
+    /// Read a duration, like `"30s"`, `"500ms"` or `"1h30m"`.
+    ///
+    /// Accepted suffixes are `ns`, `us`, `ms`, `s`, `m`, `h` and `d`. A plain integer, or
+    /// a string without a suffix, is a number of seconds.
+    pub fn as_duration(&self) -> Result<Duration> {
+        self.as_duration_measured().map(|m| m.value)
+    }
+
+    /// Read a duration together with the text it was written as.
+    pub fn as_duration_measured(&self) -> Result<Measured<Duration>> {
+        self.as_measured(units::parse_duration, units::DURATION_SUFFIXES)
+    }
+
+    fn as_measured<T, F>(&self, parse: F, suffixes: &[(&str, u64)]) -> Result<Measured<T>>
+        where F: Fn(&str) -> result::Result<T, String>
+    {
+        let original = match *self.value {
+            Value::Integer(i) if i < 0 => {
+                return Err(self.incorrect_value(format!("{} must not be negative", self.desc)))
+            }
+            Value::Integer(i) => i.to_string(),
+            Value::String(ref s) => s.clone(),
+            ref other => {
+                return Err(Error::ExpectedOneOfTypes {
+                        found_type: other.type_str().to_string(),
+                        possible_list: vec!["integer".to_string(), "string".to_string()],
+                    }
+                    .at(self.to_string()))
+            }
+        };
+        match parse(&original) {
+            Ok(value) => Ok(Measured { value, original }),
+            Err(reason) => {
+                Err(self.incorrect_value(format!("{} is invalid: {}; accepted suffixes are {}",
+                                                 self.desc,
+                                                 reason,
+                                                 units::suffix_list(suffixes))))
+            }
+        }
+    }
+
+    fn validated(&self, check: result::Result<(), String>) -> Result<()> {
+        check.map_err(|message| {
+            self.incorrect_value(format!("{} is invalid: {}", self.desc, message))
+        })
+    }
+
+    fn record(&self, kind: &'static str) {
+        if let Some(schema) = self.schema {
+            schema.record(&self.path.to_vec(), &self.desc, Some(kind), Some(self.value));
+        }
+    }
+
+    fn record_allowed<F: FnOnce() -> Vec<Value>>(&self, allowed: F) {
+        if let Some(schema) = self.schema {
+            schema.record_allowed(&self.path.to_vec(), &self.desc, allowed());
+        }
+    }
+
+    fn record_child(&self, component: &str, desc: &str) {
+        if let Some(schema) = self.schema {
+            let mut path = self.path.to_vec();
+            path.push(component);
+            schema.record(&path, desc, None, None);
+        }
+    }
+
+    fn warn(&self, warning: Warning) {
+        if let Some(warnings) = self.warnings {
+            warnings.push(At {
+                error: warning,
+                path: self.to_string(),
+                location: None,
+            });
+        }
+    }
+
+    /// Check the value of this path against the limits set with `with_limits`.
+    fn check_limits(&self) -> Result<()> {
+        match self.limits {
+            Some(limits) => limits.check_value(self.value, &self.path.to_path_components()),
+            None => Ok(()),
+        }
+    }
+
+    /// Return `error`, or `UnexpectedNull` with the type `error` expects if the value is
+    /// null.
+    fn type_error(&self, error: Error) -> At<Error> {
+        if !self.is_null() {
+            return error.at(self.to_string());
+        }
+        let expected = match error {
+            Error::ExpectedTable { .. } => "a table",
+            Error::ExpectedString { .. } => "a string",
+            Error::ExpectedInteger { .. } => "an integer",
+            Error::ExpectedFloat { .. } => "a float",
+            Error::ExpectedBool { .. } => "a boolean",
+            Error::ExpectedDatetime { .. } => "a datetime",
+            Error::ExpectedSlice { .. } => "an array",
+            _ => "a value",
+        };
+        Error::UnexpectedNull {
+                desc: self.desc.to_string(),
+                expected,
+            }
+            .at(self.to_string())
+    }
+
+    /// The value to put into errors, `REDACTED` for secret paths.
+    fn found_value(&self) -> Value {
+        if self.secret {
+            Value::String(REDACTED.to_string())
+        } else {
+            self.value.clone()
+        }
+    }
+
+    fn incorrect_value(&self, explanation: String) -> At<Error> {
+        Error::IncorrectValue {
+                explanation: Some(explanation),
+                value: self.found_value(),
+                possible_list: vec![],
+            }
+            .at(self.to_string())
+    }
+
+    pub(crate) fn child_path_string(&self, property_name: &str) -> String {
+        self.path.child(Cow::Borrowed(property_name)).to_string()
+    }
+
+    /// Join path components with `.`, like `servers[2].host`.
+    ///
+    /// Array index components are kept as they are, and keys containing `.`, `[` or `]`
+    /// are quoted in brackets, like `hosts["db.example.com"].port`. A plain string can not
+    /// tell a key reading `[2]` from an index, so walkers that know which is which build
+    /// their paths from `PathComponent`s and `components_to_string` instead.
+    pub(crate) fn path_as_string<S: AsRef<str>>(path: &[S]) -> String {
+        let mut result = String::new();
+        for v in path {
+            let v = v.as_ref();
+            if is_index_component(v) {
+                result.push_str(v);
+            } else {
+                push_key(&mut result, v);
+            }
+        }
+        result
+    }
+
+    /// Like `path_as_string` with `key` appended, which is always written as a key.
+    pub(crate) fn key_path_string<S: AsRef<str>>(path: &[S], key: &str) -> String {
+        let mut result = Self::path_as_string(path);
+        push_key(&mut result, key);
+        result
+    }
+}
+
+impl Value {
+    /// Replace `$VAR` and `${VAR}` references in every string of this tree using `lookup`.
+    ///
+    /// Write `$$` for a literal `$`. Table keys and datetimes are left alone. Stops at the
+    /// first reference `lookup` has no value for, with `UndefinedVariable` error at the
+    /// path of the string; strings visited before it are already expanded.
+    pub fn expand_env<F>(&mut self, lookup: F) -> Result<()>
+        where F: Fn(&str) -> Option<String>
+    {
+        self.expand_env_at(&mut vec![], &lookup)
+    }
+
+    fn expand_env_at<F>(&mut self, path: &mut Vec<PathComponent>, lookup: &F) -> Result<()>
+        where F: Fn(&str) -> Option<String>
+    {
+        match *self {
+            Value::String(ref mut s) => {
+                let error = match expand::expand(s, lookup, false) {
+                    Ok(expanded) => {
+                        *s = expanded;
+                        return Ok(());
+                    }
+                    Err(ExpandError::Undefined(name)) => Error::UndefinedVariable { name },
+                    Err(ExpandError::Unterminated) => {
+                        Error::IncorrectValue {
+                            explanation: Some("\"${\" without the closing \"}\"".to_string()),
+                            value: Value::String(s.clone()),
+                            possible_list: vec![],
+                        }
+                    }
+                };
+                return Err(error.at(components_to_string(path)));
+            }
+            Value::Array(ref mut a) => {
+                for (index, value) in a.iter_mut().enumerate() {
+                    path.push(PathComponent::Index(index));
+                    value.expand_env_at(path, lookup)?;
+                    path.pop();
+                }
+            }
+            Value::Table(ref mut t) => {
+                for (key, value) in t.iter_mut() {
+                    path.push(PathComponent::Key(key.clone()));
+                    value.expand_env_at(path, lookup)?;
+                    path.pop();
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for Path<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.path.fmt(f)
+    }
+}
+
+/// Owned counterpart of `Path`, for decoders kept in structs or sent to other threads.
+///
+/// The value is shared with `Arc`, so children created with `table_property` and the
+/// other drill-down methods are cheap and point into the same tree. Getters are used
+/// through the borrowed view returned by `as_path`:
+///
+/// ```
+/// # use config_model::Value;
+/// # use config_model::decode::OwnedPath;
+/// let value = Value::Table(vec![("port".to_string(), Value::Integer(80))].into_iter().collect());
+/// let port = OwnedPath::new(value, "config").table_property("port", "port to listen on").unwrap();
+/// let port = ::std::thread::spawn(move || port.as_path().as_integer()).join().unwrap();
+/// assert_eq!(port.unwrap(), 80);
+/// ```
+#[derive(Debug, Clone)]
+pub struct OwnedPath {
+    root: Arc<Value>,
+    path: Vec<Component<'static>>,
+    /// Number of leading path components above the root value.
+    depth: usize,
+    desc: String,
+    lenient: bool,
+    secret: bool,
+}
+
+impl OwnedPath {
+    /// Construct root value with specified description.
+    pub fn new<D: Into<String>>(value: Value, desc: D) -> OwnedPath {
+        OwnedPath::from_arc(Arc::new(value), desc)
+    }
+
+    /// Construct root value shared with other owners.
+    pub fn from_arc<D: Into<String>>(value: Arc<Value>, desc: D) -> OwnedPath {
+        OwnedPath {
+            root: value,
+            path: vec![],
+            depth: 0,
+            desc: desc.into(),
+            lenient: false,
+            secret: false,
+        }
+    }
+
+    /// Switch this path and all paths derived from it to lenient mode, see
+    /// `Path::lenient`.
+    pub fn lenient(self) -> OwnedPath {
+        OwnedPath { lenient: true, ..self }
+    }
+
+    /// Mark this path and all paths derived from it as holding a secret, see
+    /// `Path::secret`.
+    pub fn secret(self) -> OwnedPath {
+        OwnedPath { secret: true, ..self }
+    }
+
+    /// Return true if this path is in lenient mode.
+    pub fn is_lenient(&self) -> bool {
+        self.lenient
+    }
+
+    /// Return path to this configuration, see `Path::components`.
+    pub fn components(&self) -> Vec<&str> {
+        self.iter_components().collect()
+    }
+
+    /// Iterate over the path to this configuration, from the root, see
+    /// `Path::iter_components`.
+    pub fn iter_components(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator {
+        self.path.iter().map(|c| &c.name[..])
+    }
+
+    /// Return raw value of this configuration, `None` if the path does not lead to a
+    /// value of the shared tree.
+    pub fn value(&self) -> Option<&Value> {
+        let mut value = &*self.root;
+        for component in &self.path[self.depth..] {
+            value = match component.index {
+                Some(index) => value.as_slice().and_then(|a| a.get(index))?,
+                None => value.as_table().and_then(|t| t.get(&component.name[..]))?,
+            };
+        }
+        Some(value)
+    }
+
+    /// Return description of the configuration at this path.
+    pub fn description(&self) -> &str {
+        &self.desc
+    }
+
+    /// Borrow as a `Path`, to use its getters.
+    ///
+    /// Warnings and schema can be attached to the returned path as usual, but they are
+    /// not kept by children created through `OwnedPath`. If `value` finds no value, the
+    /// path has a null value, so getters report it like a missing one.
+    pub fn as_path(&self) -> Path<'_> {
+        Path {
+            path: self.path.iter().fold(Components::default(), |path, c| {
+                path.push(Component {
+                    name: Cow::Borrowed(&c.name[..]),
+                    index: c.index,
+                })
+            }),
+            value: self.value().unwrap_or(&NULL),
+            desc: Cow::Borrowed(&self.desc),
+            lenient: self.lenient,
+            secret: self.secret,
+            warnings: None,
+            schema: None,
+            limits: None,
+        }
+    }
+
+    /// Join decode path component that must be a property of this table value, see
+    /// `Path::table_property`.
+    pub fn table_property<D: Into<String>>(&self,
+                                           property_name: &str,
+                                           property_desc: D)
+                                           -> Result<OwnedPath> {
+        let property_desc = property_desc.into();
+        let path = self.as_path();
+        let child = path.table_property(property_name, &property_desc[..])?;
+        Ok(self.child(&child))
+    }
+
+    /// Join decode path component that may be a property of this table value, see
+    /// `Path::optional_table_property`.
+    pub fn optional_table_property<D: Into<String>>(&self,
+                                                    property_name: &str,
+                                                    property_desc: D)
+                                                    -> Result<Option<OwnedPath>> {
+        let property_desc = property_desc.into();
+        let path = self.as_path();
+        let child = path.optional_table_property(property_name, &property_desc[..])?;
+        Ok(child.map(|child| self.child(&child)))
+    }
+
+    /// Return child paths of the elements of this array value, see
+    /// `Path::array_elements`.
+    pub fn array_elements<D: Into<String>>(&self, element_desc: D) -> Result<Vec<OwnedPath>> {
+        let element_desc = element_desc.into();
+        let path = self.as_path();
+        let elements = path.array_elements(&element_desc[..])?;
+        Ok(elements.iter().map(|child| self.child(child)).collect())
+    }
+
+    fn child(&self, child: &Path) -> OwnedPath {
+        OwnedPath {
+            root: self.root.clone(),
+            path: child.path.nodes().into_iter().map(|c| c.clone().into_owned()).collect(),
+            depth: self.depth,
+            desc: child.desc.to_string(),
+            lenient: self.lenient,
+            secret: self.secret,
+        }
+    }
+}
+
+impl<'a> Path<'a> {
+    /// Convert to an `OwnedPath` with the same components and description.
+    ///
+    /// The value at this path is cloned. Warnings and schema are not kept.
+    pub fn to_owned_path(&self) -> OwnedPath {
+        let path: Vec<Component<'static>> =
+            self.path.nodes().into_iter().map(|c| c.clone().into_owned()).collect();
+        OwnedPath {
+            root: Arc::new(self.value.clone()),
+            depth: path.len(),
+            path,
+            desc: self.desc.to_string(),
+            lenient: self.lenient,
+            secret: self.secret,
+        }
+    }
+}
+
+impl fmt::Display for OwnedPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        components_as_string(&self.path).fmt(f)
+    }
+}
+
+pub type Result<T> = result::Result<T, At<Error>>;
+
+/// Value of owned paths that lead nowhere.
+static NULL: Value = Value::Null;
+
+/// Return true if the path component is an array index, like `[2]`.
+fn is_index_component(component: &str) -> bool {
+    component.len() > 2 && component.starts_with('[') && component.ends_with(']') &&
+    component[1..component.len() - 1].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Return the index of an array index component, like `2` for `[2]`.
+fn index_component(component: &str) -> Option<usize> {
+    if is_index_component(component) {
+        component[1..component.len() - 1].parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Reference to a decoding function, used where decoders are selected at runtime.
+pub type Decoder<'f, T> = &'f dyn Fn(&Path) -> Result<T>;
+
+/// Collect table entries in the order they appear in the source, rejecting duplicate keys.
+///
+/// Loaders that see the raw key sequence should use this instead of collecting into a
+/// `Table` directly, which silently keeps the last value. The `path` is the location of
+/// the table being loaded; the error is reported at the duplicated key under it.
+pub fn table_from_entries_strict<I>(entries: I, path: &[&str]) -> Result<Table>
+    where I: IntoIterator<Item = (String, Value)>
+{
+    let mut table = Table::new();
+    for (name, value) in entries {
+        match table.entry(name) {
+            Entry::Vacant(e) => {
+                e.insert(value);
+            }
+            Entry::Occupied(e) => {
+                return Err(Error::DuplicateKey { name: e.key().clone() }
+                    .at(Path::key_path_string(path, e.key())));
+            }
+        }
+    }
+    Ok(table)
+}
+
+/// A type that can be decoded from the value at a `Path`.
+///
+/// Implemented for the scalar types read by the `Path` getters, for `Vec` and string-keyed
+/// `BTreeMap` of decodable values, and for `Option`, which is always `Some` for a value
+/// that is present. With the `derive` feature, `#[derive(DecodeConfig)]` implements it for
+/// structs with named fields. Decode a value with `Path::decode`.
+pub trait DecodeConfig: Sized {
+    fn decode(path: &Path) -> Result<Self>;
+}
+
+macro_rules! impl_decode_config {
+    ($($t:ty => $getter:ident),*) => {
+        $(
+            impl DecodeConfig for $t {
+                fn decode(path: &Path) -> Result<$t> {
+                    path.$getter().map(Into::into)
+                }
+            }
+        )*
+    }
+}
+
+impl_decode_config! {
+    String => as_str, bool => as_bool, i64 => as_integer, f64 => as_float, u8 => as_u8,
+    u16 => as_u16, u32 => as_u32, u64 => as_u64, usize => as_usize, i8 => as_i8,
+    i16 => as_i16, i32 => as_i32
+}
+
+impl<T: DecodeConfig> DecodeConfig for Vec<T> {
+    fn decode(path: &Path) -> Result<Vec<T>> {
+        path.as_slice_of(T::decode)
+    }
+}
+
+impl<T: DecodeConfig> DecodeConfig for BTreeMap<String, T> {
+    fn decode(path: &Path) -> Result<BTreeMap<String, T>> {
+        path.as_table_of(T::decode)
+    }
+}
+
+impl<T: DecodeConfig> DecodeConfig for Option<T> {
+    fn decode(path: &Path) -> Result<Option<T>> {
+        if path.is_null() {
+            return Ok(None);
+        }
+        T::decode(path).map(Some)
+    }
+}
+
+/// Assertions for testing decoders built on `Path`.
+///
+/// Errors are matched by path and by `Error::code`, so tests do not depend on the wording
+/// of descriptions. The `value!` macro and `TableBuilder` are re-exported here to build
+/// the values under test:
+///
+/// ```
+/// use config_model::decode::testing::{assert_decode_err, assert_decode_ok, value,
+///                                     TableBuilder};
+///
+/// let port = |path: &config_model::decode::Path| path.table_property("port", "port")?.as_u16();
+/// assert_eq!(assert_decode_ok(&value!{ port: 80 }, port), 80);
+/// assert_decode_err(&value!{ port: "80" }, port, "port", "E_EXPECTED_INTEGER");
+/// let value = TableBuilder::new().property("port", &70000).build();
+/// assert_decode_err(&value, port, "port", "E_INCORRECT_VALUE");
+/// ```
+pub mod testing {
+    use std::fmt;
+    use Value;
+    use super::{Path, Result};
+
+    pub use encode::TableBuilder;
+    pub use value;
+
+    /// Run `decoder` on `value` at the root, and return the decoded value.
+    ///
+    /// Panics with the error if decoding fails.
+    #[track_caller]
+    pub fn assert_decode_ok<T, F>(value: &Value, decoder: F) -> T
+        where F: Fn(&Path) -> Result<T>
+    {
+        match decoder(&Path::new(value, "value")) {
+            Ok(decoded) => decoded,
+            Err(e) => panic!("expected decoding to succeed, but got error {}", e),
+        }
+    }
+
+    /// Run `decoder` on `value` at the root, and check that it fails with an error of
+    /// code `expected_code` at `expected_path`.
+    ///
+    /// Panics if decoding succeeds, or fails with a different error.
+    #[track_caller]
+    pub fn assert_decode_err<T, F>(value: &Value,
+                                   decoder: F,
+                                   expected_path: &str,
+                                   expected_code: &str)
+        where T: fmt::Debug,
+              F: Fn(&Path) -> Result<T>
+    {
+        match decoder(&Path::new(value, "value")) {
+            Ok(decoded) => {
+                panic!("expected {} at '{}', but decoding succeeded with {:?}",
+                       expected_code,
+                       expected_path,
+                       decoded)
+            }
+            Err(e) => {
+                if e.path != expected_path || e.error.code() != expected_code {
+                    panic!("expected {} at '{}', but got {} at '{}': {}",
+                           expected_code,
+                           expected_path,
+                           e.error.code(),
+                           e.path,
+                           e.error)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+    use Value;
+    use super::{At, Component, Error, Errors, Limit, Limits, Location, OwnedPath, Path, Result,
+                Schema, SourceMap, Span, Warnings};
+
+    #[test]
+    fn require_not_both_allows_none_or_one() {
+        for value in &[value!{}, value!{ a: 1 }, value!{ b: 2 }] {
+            assert!(Path::new(value, "value").require_not_both("a", "b").is_ok());
+        }
+    }
+
+    #[test]
+    fn require_not_both_rejects_both() {
+        let value = value!{ server: { a: 1, b: 2 } };
+        let root = Path::new(&value, "value");
+        let server = root.table_property("server", "server").unwrap();
+        let e = server.require_not_both("a", "b").unwrap_err();
+        assert_eq!(e.path, "server");
+        match e.error {
+            Error::ConflictingProperties { ref paths } => {
+                assert_eq!(paths, &["server.a", "server.b"])
+            }
+            ref e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn empty_table_is_a_table_without_entries() {
+        let value = value!{};
+        let path = Path::new(&value, "value");
+        assert!(path.as_table().unwrap().is_empty());
+        assert!(path.as_table_entries("entry").unwrap().is_empty());
+    }
+
+    #[test]
+    fn require_all_properties_on_empty_table_lists_all() {
+        let value = value!{};
+        let e = Path::new(&value, "value")
+            .require_all_properties(&[("host", "server host"), ("port", "server port")])
+            .unwrap_err();
+        assert_eq!(e.path, "");
+        match e.error {
+            Error::ExpectedProperties(ref missing) => {
+                let names: Vec<&str> = missing.iter().map(|p| &p.name[..]).collect();
+                assert_eq!(names, ["host", "port"]);
+                assert_eq!(missing[1].desc, "server port");
+            }
+            ref e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn as_str_trimmed_slices_the_value() {
+        let value = Value::String(" db \n".to_string());
+        match Path::new(&value, "value").as_str_trimmed().unwrap() {
+            Cow::Borrowed(s) => assert_eq!(s, "db"),
+            Cow::Owned(s) => panic!("trimmed string {:?} was copied", s),
+        }
+    }
+
+    #[test]
+    fn as_str_lowercased_borrows_lowercase_input() {
+        for &(input, expected, borrowed) in &[("prod", "prod", true),
+                                             ("Prod", "prod", false),
+                                             ("pärk", "pärk", true),
+                                             ("PÄRK", "pärk", false)] {
+            let value = Value::String(input.to_string());
+            let path = Path::new(&value, "value");
+            let lowercased = path.as_str_lowercased().unwrap();
+            assert_eq!(lowercased, expected);
+            assert_eq!(matches!(lowercased, Cow::Borrowed(_)), borrowed, "{}", input);
+        }
+    }
+
+    #[test]
+    fn as_integer_lenient_tells_overflow_from_malformed() {
+        assert_eq!(lenient_integer("9223372036854775807").unwrap(), i64::MAX);
+        assert!(explanation(&lenient_integer("9223372036854775808").unwrap_err())
+            .contains("too large"));
+        assert!(explanation(&lenient_integer("-9223372036854775809").unwrap_err())
+            .contains("too small"));
+        assert!(explanation(&lenient_integer("12a").unwrap_err()).contains("not a number"));
+    }
+
+    fn lenient_integer(s: &str) -> Result<i64> {
+        Path::new(&Value::String(s.to_string()), "port").as_integer_lenient()
+    }
+
+    fn explanation(e: &At<Error>) -> &str {
+        match e.error {
+            Error::IncorrectValue { explanation: Some(ref explanation), .. } => explanation,
+            ref e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn schema_records_allowed_values() {
+        let value = value!{ level: "debug", mode: "fast", workers: [{ port: 80 }] };
+        let schema = Schema::new();
+        let root = Path::new(&value, "config").with_schema(&schema);
+        let level = root.table_property("level", "log level").unwrap();
+        level.as_enum(&[("info", 0), ("debug", 1)]).unwrap();
+        let workers = root.table_property("workers", "workers").unwrap();
+        for worker in workers.array_elements("worker").unwrap() {
+            worker.table_property("port", "worker port").unwrap().as_integer().unwrap();
+        }
+        let entries = schema.entries();
+        assert_eq!(entries[0].allowed,
+                   vec![Value::String("info".to_string()), Value::String("debug".to_string())]);
+        assert_eq!(schema.sample_value(),
+                   value!{ level: "debug", workers: [{ port: 80 }] });
+    }
+
+    #[test]
+    fn as_labeled_integer_map_reads_labels_and_values() {
+        let labels: BTreeMap<String, i64> =
+            (0..5000).map(|i| (format!("code_{}", i), i * 2)).collect();
+        let decode = |value: Value| Path::new(&value, "code").as_labeled_integer_map(&labels);
+        assert_eq!(decode(Value::from("code_4999")).unwrap(), 9998);
+        assert_eq!(decode(Value::Integer(9998)).unwrap(), 9998);
+        match decode(Value::Integer(9999)).unwrap_err().error {
+            Error::IncorrectValue { ref possible_list, .. } => {
+                assert_eq!(possible_list.len(), 5000)
+            }
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        match decode(Value::from("code_5000")).unwrap_err().error {
+            Error::IncorrectValue { ref possible_list, .. } => {
+                assert_eq!(possible_list[0], Value::from("code_0"))
+            }
+            ref e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn as_integer_lenient_reads_radix_prefixes() {
+        assert_eq!(lenient_integer("0xFF").unwrap(), 255);
+        assert_eq!(lenient_integer("0o755").unwrap(), 493);
+        assert_eq!(lenient_integer("0b1010").unwrap(), 10);
+        assert_eq!(lenient_integer("-0x10").unwrap(), -16);
+        assert_eq!(lenient_integer("0X7fffffffffffffff").unwrap(), i64::MAX);
+    }
+
+    #[test]
+    fn as_integer_lenient_rejects_invalid_digits() {
+        for &(s, radix) in &[("0xFG", "hexadecimal"),
+                             ("0o8", "octal"),
+                             ("0b102", "binary"),
+                             ("0x", "hexadecimal"),
+                             ("0x-1", "hexadecimal")] {
+            let e = lenient_integer(s).unwrap_err();
+            assert_eq!(explanation(&e), format!("port is not a valid {} integer", radix));
+        }
+        assert!(explanation(&lenient_integer("0x10000000000000000").unwrap_err())
+            .contains("too large"));
+    }
+
+    #[test]
+    fn as_integer_clamped_clamps_and_warns() {
+        let value = Value::Integer(64);
+        let warnings = Warnings::new();
+        let path = Path::new(&value, "threads").with_warnings(&warnings);
+        assert_eq!(path.as_integer_clamped(1, 16).unwrap(), 16);
+        assert_eq!(path.as_integer_clamped(1, 100).unwrap(), 64);
+        assert_eq!(warnings.into_vec().len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "min 16 is greater than max 1")]
+    fn as_integer_clamped_panics_on_inverted_range() {
+        let _ = Path::new(&Value::Integer(4), "threads").as_integer_clamped(16, 1);
+    }
+
+    #[test]
+    fn as_str_rejects_wrong_type_and_null() {
+        let value = value!{ name: 1, empty: null };
+        let root = Path::new(&value, "config");
+        let e = root.table_property("name", "name").unwrap().as_str().unwrap_err();
+        assert!(matches!(e.error, Error::ExpectedString { ref desc } if desc == "name"));
+        assert_eq!(e.path, "name");
+        assert_null(root.table_property("empty", "empty").unwrap().as_str(), "a string");
+        assert_missing(root.table_property("missing", "missing"));
+    }
+
+    #[test]
+    fn as_integer_rejects_wrong_type_and_null() {
+        let value = value!{ port: "80", empty: null };
+        let root = Path::new(&value, "config");
+        let e = root.table_property("port", "port").unwrap().as_integer().unwrap_err();
+        assert!(matches!(e.error, Error::ExpectedInteger { ref desc } if desc == "port"));
+        assert_null(root.table_property("empty", "empty").unwrap().as_integer(), "an integer");
+    }
+
+    #[test]
+    fn as_float_rejects_wrong_type_and_null() {
+        let value = value!{ ratio: "0.5", empty: null };
+        let root = Path::new(&value, "config");
+        let e = root.table_property("ratio", "ratio").unwrap().as_float().unwrap_err();
+        assert!(matches!(e.error, Error::ExpectedFloat { ref desc } if desc == "ratio"));
+        assert_null(root.table_property("empty", "empty").unwrap().as_float(), "a float");
+    }
+
+    #[test]
+    fn as_bool_rejects_wrong_type_and_null() {
+        let value = value!{ tls: "yes", empty: null };
+        let root = Path::new(&value, "config");
+        let e = root.table_property("tls", "tls").unwrap().as_bool().unwrap_err();
+        assert!(matches!(e.error, Error::ExpectedBool { ref desc } if desc == "tls"));
+        assert_null(root.table_property("empty", "empty").unwrap().as_bool(), "a boolean");
+    }
+
+    #[test]
+    fn as_datetime_rejects_wrong_type_and_null() {
+        let value = value!{ since: 1979, empty: null };
+        let root = Path::new(&value, "config");
+        let e = root.table_property("since", "since").unwrap().as_datetime().unwrap_err();
+        assert!(matches!(e.error, Error::ExpectedDatetime { ref desc } if desc == "since"));
+        assert_null(root.table_property("empty", "empty").unwrap().as_datetime(), "a datetime");
+    }
+
+    #[test]
+    fn as_slice_rejects_wrong_type_and_null() {
+        let value = value!{ hosts: "a,b", empty: null };
+        let root = Path::new(&value, "config");
+        let e = root.table_property("hosts", "hosts").unwrap().as_slice().unwrap_err();
+        assert!(matches!(e.error, Error::ExpectedSlice { ref desc } if desc == "hosts"));
+        assert_null(root.table_property("empty", "empty").unwrap().as_slice(), "an array");
+    }
+
+    #[test]
+    fn as_table_rejects_wrong_type_and_null() {
+        let value = value!{ server: [1], empty: null };
+        let root = Path::new(&value, "config");
+        let e = root.table_property("server", "server").unwrap().as_table().unwrap_err();
+        assert!(matches!(e.error, Error::ExpectedTable { ref desc } if desc == "server"));
+        assert_null(root.table_property("empty", "empty").unwrap().as_table(), "a table");
+        let e = Path::new(&Value::Integer(1), "config").table_property("a", "a").unwrap_err();
+        assert!(matches!(e.error, Error::ExpectedTable { ref desc } if desc == "config"));
+    }
+
+    fn assert_null<T: ::std::fmt::Debug>(result: Result<T>, expected_type: &str) {
+        match result.unwrap_err() {
+            At { error: Error::UnexpectedNull { ref desc, expected }, ref path, .. } => {
+                assert_eq!((&desc[..], expected, &path[..]), ("empty", expected_type, "empty"))
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    fn assert_missing<T: ::std::fmt::Debug>(result: Result<T>) {
+        match result.unwrap_err() {
+            At { error: Error::ExpectedProperty(ref property), ref path, .. } => {
+                assert_eq!((&property.name[..], &path[..]), ("missing", "missing"))
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn hint_suggests_similar_property() {
+        let error = |suggestion: Option<&str>| {
+            Error::UnexpectedProperty {
+                name: "prot".to_string(),
+                suggestion: suggestion.map(String::from),
+            }
+        };
+        assert_eq!(error(Some("port")).hint().unwrap(), "did you mean `port`?");
+        assert_eq!(error(None).hint().unwrap(), "remove `prot` or check it for typos");
+    }
+
+    #[test]
+    fn as_number_accepts_integers_and_floats() {
+        assert_eq!(Path::new(&Value::Integer(5), "timeout").as_number().unwrap(), 5.0);
+        assert_eq!(Path::new(&Value::Float(5.0), "timeout").as_number().unwrap(), 5.0);
+        let e = Path::new(&Value::from("5"), "timeout").as_number().unwrap_err();
+        assert!(matches!(e.error, Error::ExpectedFloat { ref desc } if desc == "timeout"));
+        let e = Path::new(&Value::Integer(5), "timeout").as_float().unwrap_err();
+        assert!(matches!(e.error, Error::ExpectedFloat { .. }));
+    }
+
+    #[test]
+    fn collect_reports_every_missing_property() {
+        let value = value!{ server: { port: 80 } };
+        let root = Path::new(&value, "config");
+        let errors = root.collect(|c| {
+                c.nested("server", "server", |server| {
+                    server.table_property("host", "host", |p| p.as_str().map(String::from));
+                    server.table_property("port", "port", |p| p.as_integer());
+                });
+                c.table_property("name", "name", |p| p.as_str().map(String::from));
+            })
+            .unwrap_err();
+        let found: Vec<(&str, &str)> =
+            errors.0.iter().map(|e| (&e.path[..], e.error.code())).collect();
+        assert_eq!(found,
+                   [("server.host", "E_EXPECTED_PROPERTY"), ("name", "E_EXPECTED_PROPERTY")]);
+    }
+
+    #[test]
+    fn table_property_or_defaults_missing_property() {
+        let value = value!{ server: { host: "::" } };
+        let root = Path::new(&value, "config");
+        let server = root.table_property("server", "server").unwrap();
+        assert_eq!(server.table_property_or("port", "port", 80, |p| p.as_integer()).unwrap(), 80);
+    }
+
+    #[test]
+    fn table_property_or_rejects_wrong_type() {
+        let value = value!{ server: { port: "80" } };
+        let root = Path::new(&value, "config");
+        let server = root.table_property("server", "server").unwrap();
+        let e = server.table_property_or("port", "port", 80, |p| p.as_integer()).unwrap_err();
+        assert_eq!(e.path, "server.port");
+        assert!(matches!(e.error, Error::ExpectedInteger { ref desc } if desc == "port"));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn as_chrono_datetime_reads_offset_datetimes() {
+        let value = Value::Datetime("1979-05-27T07:32:00-07:00".to_string());
+        let datetime = Path::new(&value, "released").as_chrono_datetime().unwrap();
+        assert_eq!(datetime.to_rfc3339(), "1979-05-27T07:32:00-07:00");
+        assert_eq!(datetime.timestamp(), 296663520);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn as_chrono_datetime_rejects_malformed_datetimes() {
+        for s in &["1979-05-27T25:32:00Z", "1979-05-27", "yesterday"] {
+            let value = Value::Datetime(s.to_string());
+            let e = Path::new(&value, "released").as_chrono_datetime().unwrap_err();
+            assert!(matches!(e.error, Error::ExpectedDatetime { ref desc } if desc == "released"),
+                    "{}",
+                    s);
+        }
+    }
+
+    #[test]
+    fn table_property_ci_matches_ignoring_case() {
+        let value = value!{ Server: { PORT: 80 } };
+        let root = Path::new(&value, "config");
+        let server = root.table_property_ci("server", "server").unwrap();
+        let port = server.table_property_ci("port", "port").unwrap();
+        assert_eq!(port.to_string(), "Server.PORT");
+        assert_eq!(port.as_integer().unwrap(), 80);
+    }
+
+    #[test]
+    fn table_property_ci_rejects_colliding_keys() {
+        let value = value!{ Port: 80, port: 443 };
+        let e = Path::new(&value, "config").table_property_ci("PORT", "port").unwrap_err();
+        assert_eq!(e.path, "PORT");
+        match e.error {
+            Error::AmbiguousProperty { ref name, ref matches } => {
+                assert_eq!(name, "PORT");
+                assert_eq!(matches, &["Port", "port"]);
+            }
+            ref e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    fn decode_item(item: &Path) -> Result<(String, i64)> {
+        Ok((item.table_property("name", "name")?.as_str()?.to_string(),
+            item.table_property("count", "count")?.as_integer()?))
+    }
+
+    #[test]
+    fn decode_array_decodes_tables() {
+        let value = value!{ items: [{ name: "a", count: 1 }, { name: "b", count: 2 }] };
+        let root = Path::new(&value, "config");
+        let items = root.table_property("items", "items").unwrap();
+        assert_eq!(items.decode_array("item", decode_item).unwrap(),
+                   [("a".to_string(), 1), ("b".to_string(), 2)]);
+    }
+
+    #[test]
+    fn decode_array_reports_element_index() {
+        let value = value!{ items: [{ name: "a", count: 1 }, { name: "b", count: 2 },
+                                    { name: "c", count: 3 }, "d"] };
+        let root = Path::new(&value, "config");
+        let items = root.table_property("items", "items").unwrap();
+        let e = items.decode_array("item", decode_item).unwrap_err();
+        assert_eq!(e.path, "items[3]");
+        assert!(matches!(e.error, Error::ExpectedTable { ref desc } if desc == "item"));
+    }
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => Some("db.internal".to_string()),
+            "PORT" => Some("5432".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expand_env_replaces_references() {
+        let mut value = value!{ url: "postgres://$HOST:${PORT}/app", hosts: ["$HOST"], n: 1 };
+        value.expand_env(lookup).unwrap();
+        assert_eq!(value,
+                   value!{ url: "postgres://db.internal:5432/app", hosts: ["db.internal"], n: 1 });
+    }
+
+    #[test]
+    fn expand_env_reports_undefined_variable() {
+        let mut value = value!{ db: { hosts: ["$HOST", "${MISSING}"] } };
+        let e = value.expand_env(lookup).unwrap_err();
+        assert_eq!(e.path, "db.hosts[1]");
+        assert!(matches!(e.error, Error::UndefinedVariable { ref name } if name == "MISSING"));
+    }
+
+    #[test]
+    fn expand_env_keeps_escaped_dollar() {
+        let mut value = value!{ price: "$$5 at $$HOST", alone: "$ 1" };
+        value.expand_env(lookup).unwrap();
+        assert_eq!(value, value!{ price: "$5 at $HOST", alone: "$ 1" });
+    }
+
+    #[test]
+    fn table_keys_that_look_like_indices_are_quoted() {
+        let value = value!{ a: { "[0]": { port: "x" }, "db.example.com": { port: "y" } } };
+        let root = Path::new(&value, "config");
+        let a = root.table_property("a", "a").unwrap();
+        let key = a.table_property("[0]", "key").unwrap();
+        let e = key.table_property("port", "port").unwrap().as_integer().unwrap_err();
+        assert_eq!(e.path, "a[\"[0]\"].port");
+        let host = a.table_property("db.example.com", "host").unwrap();
+        assert_eq!(host.table_property("port", "port").unwrap().to_string(),
+                   "a[\"db.example.com\"].port");
+        assert_eq!(key.to_owned_path().to_string(), "a[\"[0]\"]");
+        assert_eq!(value.get_path("a[\"[0]\"].port"), Some(&Value::from("x")));
+    }
+
+    #[test]
+    fn schema_renders_nested_markdown() {
+        let value = value!{ server: { host: "localhost", port: 80 }, workers: [{ name: "a" }] };
+        let schema = Schema::new();
+        let root = Path::new(&value, "config").with_schema(&schema);
+        let server = root.table_property("server", "HTTP server").unwrap();
+        server.table_property("host", "host to bind").unwrap().as_str().unwrap();
+        server.table_property("port", "port to listen on").unwrap().as_integer().unwrap();
+        let workers = root.table_property("workers", "worker pool").unwrap();
+        for worker in workers.array_elements("worker").unwrap() {
+            worker.table_property("name", "worker name").unwrap().as_str().unwrap();
+        }
+        assert_eq!(schema.to_markdown(),
+                   "- `server` (table): HTTP server\n  \
+                    - `host` (string): host to bind\n  \
+                    - `port` (integer): port to listen on\n\
+                    - `workers` (array): worker pool\n  \
+                    - `[]` (table): worker\n    \
+                    - `name` (string): worker name\n");
+    }
+
+    #[test]
+    fn owned_path_value_walks_tables_and_arrays() {
+        let root = OwnedPath::new(value!{ servers: [{ port: 80 }] }, "config");
+        let servers = root.table_property("servers", "servers").unwrap();
+        let port = servers.array_elements("server").unwrap()[0]
+            .table_property("port", "port")
+            .unwrap();
+        assert_eq!(port.value(), Some(&Value::Integer(80)));
+        assert_eq!(port.to_string(), "servers[0].port");
+        let nowhere = OwnedPath {
+            path: vec![Component {
+                           name: Cow::Borrowed("servers"),
+                           index: None,
+                       },
+                       Component {
+                           name: Cow::Borrowed("[1]"),
+                           index: Some(1),
+                       }],
+            ..root
+        };
+        assert_eq!(nowhere.value(), None);
+        assert!(nowhere.as_path().is_null());
+    }
+
+    #[test]
+    fn limits_count_nodes() {
+        let value = value!{ a: [1, 2], b: { c: "x" } };
+        assert!(Limits::new().max_nodes(6).check(&value).is_ok());
+        let e = Limits::new().max_nodes(5).check(&value).unwrap_err();
+        assert!(matches!(e.error, Error::LimitExceeded { limit: Limit::Nodes, max: 5, found: 6 }));
+        assert_eq!(e.path, "b.c");
+        let limits = Limits::new().max_nodes(5);
+        let path = Path::new(&value, "config").with_limits(&limits);
+        assert!(format!("{:?}", path).contains("limits: Some(Limits {"));
+    }
+
+    #[test]
+    fn components_list_keys_and_indices_from_the_root() {
+        let value = value!{ servers: [{ port: 80 }] };
+        let root = Path::new(&value, "config");
+        let servers = root.table_property("servers", "servers").unwrap();
+        let elements = servers.array_elements("server").unwrap();
+        let port = elements[0].table_property("port", "port").unwrap();
+        assert_eq!(port.components(), ["servers", "[0]", "port"]);
+        assert_eq!(port.iter_components().rev().collect::<Vec<_>>(), ["port", "[0]", "servers"]);
+        assert_eq!(port.iter_components().len(), 3);
+        assert_eq!(root.iter_components().next(), None);
+        let owned = OwnedPath::new(value.clone(), "config")
+            .table_property("servers", "servers")
+            .unwrap()
+            .array_elements("server")
+            .unwrap()
+            .remove(0);
+        assert_eq!(owned.components(), ["servers", "[0]"]);
+        assert_eq!(owned.iter_components().last(), Some("[0]"));
+    }
+
+    #[test]
+    fn integer_ranges_describe_their_bounds() {
+        let value = value!(70000);
+        let path = Path::new(&value, "port");
+        assert_eq!(path.as_integer_in(0..).unwrap(), 70000);
+        let message = |e: At<Error>| e.error.to_string();
+        assert_eq!(message(path.integer_in_range(1, 65535).unwrap_err()),
+                   "port must be between 1 and 65535 (found 70000)");
+        assert_eq!(message(path.as_integer_in(1..=65535).unwrap_err()),
+                   "port must be between 1 and 65535 (found 70000)");
+        assert_eq!(message(path.as_integer_in(1..65535).unwrap_err()),
+                   "port must be at least 1 and less than 65535 (found 70000)");
+        assert_eq!(message(path.as_integer_in(..=80).unwrap_err()),
+                   "port must be at most 80 (found 70000)");
+        assert_eq!(message(path.as_u16().unwrap_err()),
+                   "port must be between 0 and 65535 (found 70000)");
+    }
+
+    #[test]
+    fn insert_span_counts_columns_in_characters() {
+        let source = "a = 1\nžąs = [\"ė\", 2]\n";
+        let mut source_map = SourceMap::new("c.toml");
+        let two = source.find('2').unwrap();
+        source_map.insert_span("a".to_string(), source, Span { start: 4, end: 5 });
+        source_map.insert_span("žąs[1]".to_string(), source, Span { start: two, end: two + 1 });
+        source_map.insert_span("x".to_string(), source, Span { start: 7, end: 8 });
+        source_map.insert_span("y".to_string(), source, Span { start: 99, end: 99 });
+        let location = |path: &str| source_map.location(path).map(|l| (l.line, l.column));
+        assert_eq!(location("a"), Some((1, 5)));
+        assert_eq!(location("žąs[1]"), Some((2, 13)));
+        assert_eq!(location("x"), Some((2, 1)));
+        assert_eq!(location("y"), Some((3, 1)));
+    }
+
+    #[test]
+    fn locate_uses_the_closest_parent() {
+        let mut source_map = SourceMap::new("c.toml");
+        source_map.insert("servers".to_string(), 3, 1);
+        source_map.insert("servers[0]".to_string(), 4, 3);
+        source_map.insert("hosts[\"a.b\"]".to_string(), 9, 1);
+        let at = |path: &str| {
+            Error::ExpectedTable { desc: "server".to_string() }.at(path.to_string())
+        };
+        assert_eq!(at("servers[0].tls.key").locate(&source_map).to_string(),
+                   "c.toml:4:3: at 'servers[0].tls.key': expected a table for server");
+        assert_eq!(at("servers[1]").locate(&source_map).location,
+                   Some(Box::new(Location {
+                       file: "c.toml".to_string(),
+                       line: 3,
+                       column: 1,
+                   })));
+        assert_eq!(at("hosts[\"a.b\"].port").locate(&source_map).location.unwrap().line, 9);
+        let unknown = at("other").locate(&source_map);
+        assert_eq!(unknown.location, None);
+        let located = at("servers").locate(&source_map);
+        assert_eq!(located.clone().locate(&SourceMap::new("d.toml")).location,
+                   located.location);
+        let errors = Errors(vec![at("servers[0]"), at("other")]).locate(&source_map);
+        assert_eq!(errors.0[0].location.as_ref().unwrap().line, 4);
+        assert_eq!(errors.0[1].location, None);
+    }
+}
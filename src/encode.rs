@@ -0,0 +1,217 @@
+//! Writing typed configuration back into `Value`, the reverse of `decode`.
+//!
+//! Types implement `ToValue`, and structs build their table with `TableBuilder`, using
+//! the same property names their decoder passes to `Path::table_property`. The result
+//! can be written out with `Value::to_toml_string`, for example to generate a default
+//! config file.
+
+use std::collections::BTreeMap;
+use {Value, Table};
+
+/// A type that can be converted into a config value.
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Boolean(*self)
+    }
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self)
+    }
+}
+
+macro_rules! impl_to_value_integer {
+    ($($t:ty),*) => {
+        $(
+            impl ToValue for $t {
+                fn to_value(&self) -> Value {
+                    Value::Integer(*self as i64)
+                }
+            }
+        )*
+    }
+}
+
+impl_to_value_integer!(i8, i16, i32, i64, u8, u16, u32);
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+impl<T: ToValue> ToValue for [T] {
+    fn to_value(&self) -> Value {
+        Value::Array(self.iter().map(ToValue::to_value).collect())
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(&self) -> Value {
+        self[..].to_value()
+    }
+}
+
+impl<T: ToValue> ToValue for BTreeMap<String, T> {
+    fn to_value(&self) -> Value {
+        Value::Table(self.iter().map(|(k, v)| (k.clone(), v.to_value())).collect())
+    }
+}
+
+/// Builder of a table value, mirroring the property accessors of `decode::Path`.
+///
+/// ```
+/// # use config_model::encode::TableBuilder;
+/// let value = TableBuilder::new()
+///     .property("host", "localhost")
+///     .optional_property("timeout", None::<i64>)
+///     .table("tls", |t| t.property("enabled", &true))
+///     .build();
+/// assert_eq!(value.get_path("tls.enabled").and_then(|v| v.as_bool()), Some(true));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TableBuilder {
+    table: Table,
+}
+
+impl TableBuilder {
+    pub fn new() -> TableBuilder {
+        TableBuilder::default()
+    }
+
+    /// Set the property `name`, replacing any previous value.
+    pub fn property<T: ToValue + ?Sized>(mut self, name: &str, value: &T) -> TableBuilder {
+        self.table.insert(name.to_string(), value.to_value());
+        self
+    }
+
+    /// Set the property `name` if `value` is not `None`, the counterpart of
+    /// `Path::optional_table_property`.
+    pub fn optional_property<T: ToValue>(self, name: &str, value: Option<T>) -> TableBuilder {
+        match value {
+            Some(value) => self.property(name, &value),
+            None => self,
+        }
+    }
+
+    /// Set the property `name` to a nested table built by `f`.
+    pub fn table<F>(mut self, name: &str, f: F) -> TableBuilder
+        where F: FnOnce(TableBuilder) -> TableBuilder
+    {
+        self.table.insert(name.to_string(), f(TableBuilder::new()).build());
+        self
+    }
+
+    /// Finish building and return the table value.
+    pub fn build(self) -> Value {
+        Value::Table(self.table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use decode::{self, Path};
+    use Value;
+    use super::{TableBuilder, ToValue};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Tls {
+        enabled: bool,
+        ratio: f64,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Server {
+        host: String,
+        port: u16,
+        tags: Vec<String>,
+        timeout: Option<u32>,
+        limits: BTreeMap<String, i64>,
+        tls: Tls,
+    }
+
+    impl ToValue for Server {
+        fn to_value(&self) -> Value {
+            TableBuilder::new()
+                .property("host", &self.host)
+                .property("port", &self.port)
+                .property("tags", &self.tags)
+                .optional_property("timeout", self.timeout)
+                .property("limits", &self.limits)
+                .table("tls", |t| {
+                    t.property("enabled", &self.tls.enabled).property("ratio", &self.tls.ratio)
+                })
+                .build()
+        }
+    }
+
+    fn decode_server(path: &Path) -> decode::Result<Server> {
+        let tls = path.table_property("tls", "tls")?;
+        Ok(Server {
+            host: path.table_property("host", "host")?.as_str()?.to_string(),
+            port: path.table_property("port", "port")?.as_u16()?,
+            tags: path.table_property("tags", "tags")?.decode()?,
+            timeout: path.optional_validated("timeout", "timeout", |p| p.as_u32())?,
+            limits: path.table_property("limits", "limits")?.decode()?,
+            tls: Tls {
+                enabled: tls.table_property("enabled", "tls enabled")?.as_bool()?,
+                ratio: tls.table_property("ratio", "tls ratio")?.as_float()?,
+            },
+        })
+    }
+
+    #[test]
+    fn encoded_models_decode_back() {
+        let mut server = Server {
+            host: "localhost".to_string(),
+            port: 8080,
+            tags: vec!["a".to_string(), "b".to_string()],
+            timeout: Some(30),
+            limits: vec![("conns".to_string(), 100)].into_iter().collect(),
+            tls: Tls {
+                enabled: true,
+                ratio: 0.5,
+            },
+        };
+        let value = server.to_value();
+        assert_eq!(value,
+                   value!{
+                       host: "localhost",
+                       port: 8080,
+                       tags: ["a", "b"],
+                       timeout: 30,
+                       limits: { conns: 100 },
+                       tls: { enabled: true, ratio: 0.5 },
+                   });
+        assert_eq!(decode_server(&Path::new(&value, "server")).unwrap(), server);
+        server.timeout = None;
+        server.tags = vec![];
+        let value = server.to_value();
+        assert_eq!(value.get_path("timeout"), None);
+        assert_eq!(decode_server(&Path::new(&value, "server")).unwrap(), server);
+    }
+}
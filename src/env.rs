@@ -0,0 +1,174 @@
+//! Overriding configuration values from environment variables.
+//!
+//! A variable like `APP_SERVER__PORT=8080` sets `server.port` to `8080` when the prefix
+//! is `APP_` and the separator is `__`. This is meant to be applied to the loaded
+//! config before decoding, so containers can change settings without editing files.
+
+use std::env;
+use {Value, Table};
+
+/// Mapping of environment variables onto the config tree.
+///
+/// After the prefix is removed, the variable name is split on the separator, and each
+/// part is converted to lowercase to get the path of the value. Variables with an empty
+/// part, like `APP_SERVER____PORT`, are ignored.
+///
+/// Variable values that parse as a boolean (`true` or `false`), an integer or a float
+/// become `Value::Boolean`, `Value::Integer` or `Value::Float`, anything else stays a
+/// string. Leading zeros are allowed, so `08` is the integer 8, and `1e5` is a float,
+/// but `inf` and `nan` stay strings. A value already in the tree is replaced, and tables
+/// on the way are created, replacing a non-table value if necessary.
+#[derive(Debug, Clone)]
+pub struct EnvOverlay {
+    prefix: String,
+    separator: String,
+}
+
+impl EnvOverlay {
+    /// Map variables starting with `prefix` (like `APP_`), using the `__` separator.
+    pub fn new(prefix: &str) -> EnvOverlay {
+        EnvOverlay {
+            prefix: prefix.to_string(),
+            separator: "__".to_string(),
+        }
+    }
+
+    /// Use `separator` between nested keys instead of `__`.
+    pub fn separator(self, separator: &str) -> EnvOverlay {
+        EnvOverlay { separator: separator.to_string(), ..self }
+    }
+
+    /// Apply variables of the process environment to `value`.
+    ///
+    /// Variables with names or values that are not valid unicode are skipped.
+    pub fn apply(&self, value: &mut Value) {
+        self.apply_vars(value, env::vars_os().filter_map(|(k, v)| {
+            Some((k.into_string().ok()?, v.into_string().ok()?))
+        }))
+    }
+
+    /// Apply the given `(name, value)` variables to `value`, in order.
+    pub fn apply_vars<I>(&self, value: &mut Value, vars: I)
+        where I: IntoIterator<Item = (String, String)>
+    {
+        for (name, var) in vars {
+            let path = match name.strip_prefix(&self.prefix[..]) {
+                Some(rest) if !rest.is_empty() => {
+                    rest.split(&self.separator[..])
+                        .map(|part| part.to_lowercase())
+                        .collect::<Vec<_>>()
+                }
+                _ => continue,
+            };
+            if path.iter().any(|part| part.is_empty()) {
+                continue;
+            }
+            set(value, &path, parse_scalar(&var));
+        }
+    }
+}
+
+fn set(value: &mut Value, path: &[String], new: Value) {
+    if path.is_empty() {
+        *value = new;
+        return;
+    }
+    if value.as_table().is_none() {
+        *value = Value::Table(Table::new());
+    }
+    if let Value::Table(ref mut table) = *value {
+        let child = table.entry(path[0].clone()).or_insert_with(|| Value::Table(Table::new()));
+        set(child, &path[1..], new);
+    }
+}
+
+pub(crate) fn parse_scalar(s: &str) -> Value {
+    match s {
+        "true" => return Value::Boolean(true),
+        "false" => return Value::Boolean(false),
+        _ => (),
+    }
+    if let Ok(i) = s.parse::<i64>() {
+        return Value::Integer(i);
+    }
+    let is_number = s.bytes().any(|b| b.is_ascii_digit()) &&
+                    s.bytes().all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b));
+    match s.parse::<f64>() {
+        Ok(f) if is_number => Value::Float(f),
+        _ => Value::String(s.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Value;
+    use super::{parse_scalar, EnvOverlay};
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn apply_vars_sets_nested_paths() {
+        let mut value = value!{ server: { port: 80, host: "a" }, debug: true };
+        EnvOverlay::new("APP_").apply_vars(&mut value,
+                                           vars(&[("APP_SERVER__PORT", "8080"),
+                                                  ("APP_DEBUG", "false"),
+                                                  ("OTHER_NAME", "x"),
+                                                  ("APP_", "x"),
+                                                  ("APP_LOG__LEVEL", "info")]));
+        assert_eq!(value,
+                   value!{
+                       server: { port: 8080, host: "a" },
+                       debug: false,
+                       log: { level: "info" },
+                   });
+    }
+
+    #[test]
+    fn apply_vars_uses_a_custom_separator() {
+        let mut value = value!{};
+        EnvOverlay::new("APP_")
+            .separator("_")
+            .apply_vars(&mut value, vars(&[("APP_DB_POOL_SIZE", "4"), ("APP_DB__X", "1")]));
+        assert_eq!(value, value!{ db: { pool: { size: 4 } } });
+    }
+
+    #[test]
+    fn apply_vars_ignores_empty_parts() {
+        let mut value = value!{ a: 1 };
+        EnvOverlay::new("APP_").apply_vars(&mut value,
+                                           vars(&[("APP_A____B", "2"),
+                                                  ("APP___A", "3"),
+                                                  ("APP_A__", "4")]));
+        assert_eq!(value, value!{ a: 1 });
+    }
+
+    #[test]
+    fn apply_vars_replaces_values_that_are_not_tables() {
+        let mut value = value!{ server: "localhost", ports: [1, 2] };
+        EnvOverlay::new("APP_").apply_vars(&mut value,
+                                           vars(&[("APP_SERVER__PORT", "80"),
+                                                  ("APP_PORTS__0", "3")]));
+        assert_eq!(value, value!{ server: { port: 80 }, ports: { "0": 3 } });
+        let mut value = Value::Integer(1);
+        EnvOverlay::new("APP_").apply_vars(&mut value, vars(&[("APP_A", "x")]));
+        assert_eq!(value, value!{ a: "x" });
+    }
+
+    #[test]
+    fn parse_scalar_infers_types() {
+        assert_eq!(parse_scalar("true"), Value::Boolean(true));
+        assert_eq!(parse_scalar("True"), Value::from("True"));
+        assert_eq!(parse_scalar("-12"), Value::Integer(-12));
+        assert_eq!(parse_scalar("08"), Value::Integer(8));
+        assert_eq!(parse_scalar("1e5"), Value::Float(100000.0));
+        assert_eq!(parse_scalar("-0.5"), Value::Float(-0.5));
+        assert_eq!(parse_scalar("99999999999999999999"), Value::Float(1e20));
+        assert_eq!(parse_scalar("inf"), Value::from("inf"));
+        assert_eq!(parse_scalar("NaN"), Value::from("NaN"));
+        assert_eq!(parse_scalar("-"), Value::from("-"));
+        assert_eq!(parse_scalar("1.2.3"), Value::from("1.2.3"));
+        assert_eq!(parse_scalar(""), Value::from(""));
+    }
+}
@@ -0,0 +1,62 @@
+//! Expansion of `$VAR` and `${VAR}` references inside strings.
+//!
+//! A variable name after a bare `$` consists of ASCII letters, digits and `_`, and
+//! can not start with a digit. The braced form allows any name not containing `}`.
+//! `$$` stands for a literal `$`, and a `$` not followed by a name is kept as-is.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpandError {
+    /// The lookup has no value for the variable.
+    Undefined(String),
+    /// A `${` without the closing `}`.
+    Unterminated,
+}
+
+/// Expand variable references in `s` using `lookup`.
+///
+/// If `keep_undefined` is set, references to undefined variables are left in the
+/// output exactly as written, otherwise they produce `ExpandError::Undefined`.
+pub fn expand<F>(s: &str, lookup: F, keep_undefined: bool) -> Result<String, ExpandError>
+    where F: Fn(&str) -> Option<String>
+{
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(position) = rest.find('$') {
+        result.push_str(&rest[..position]);
+        let after = &rest[position + 1..];
+        if after.starts_with('$') {
+            result.push('$');
+            rest = &rest[position + 2..];
+            continue;
+        }
+        let (name, reference_len) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 3),
+                None => return Err(ExpandError::Unterminated),
+            }
+        } else {
+            let len = after.char_indices()
+                .find(|&(i, c)| !is_name_char(i, c))
+                .map_or(after.len(), |(i, _)| i);
+            (&after[..len], len + 1)
+        };
+        if name.is_empty() && reference_len == 1 {
+            result.push('$');
+        } else {
+            match lookup(name) {
+                Some(value) => result.push_str(&value),
+                None if keep_undefined => {
+                    result.push_str(&rest[position..position + reference_len])
+                }
+                None => return Err(ExpandError::Undefined(name.to_string())),
+            }
+        }
+        rest = &rest[position + reference_len..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn is_name_char(index: usize, c: char) -> bool {
+    c == '_' || c.is_ascii_alphabetic() || (index > 0 && c.is_ascii_digit())
+}
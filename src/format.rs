@@ -0,0 +1,1021 @@
+//! Loading configuration text of various formats into `Value`.
+//!
+//! Each format is behind a cargo feature: `toml`, and `yaml` for the `yaml-rust2` parser.
+//! Large TOML and JSON (`json` feature) documents can also be read section by section
+//! with `LazyToml` and `LazyJson`.
+
+#[cfg(feature = "json")]
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path;
+use {Value, ProfileError};
+use decode::{At, Error, Limits, Span};
+#[cfg(feature = "toml")]
+use decode::SourceMap;
+#[cfg(any(feature = "toml", feature = "yaml"))]
+use PathComponent;
+#[cfg(any(feature = "toml", feature = "yaml"))]
+use value::components_to_string;
+#[cfg(feature = "yaml")]
+use Table;
+#[cfg(feature = "yaml")]
+use yaml_rust2::Yaml;
+#[cfg(feature = "yaml")]
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser, Tag};
+#[cfg(feature = "yaml")]
+use yaml_rust2::scanner::{Marker, TScalarStyle};
+#[cfg(any(feature = "json", feature = "yaml"))]
+use std::collections::BTreeMap;
+#[cfg(feature = "json")]
+use std::collections::BTreeSet;
+#[cfg(feature = "json")]
+use std::marker::PhantomData;
+#[cfg(feature = "json")]
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+#[cfg(feature = "json")]
+use serde_json::value::RawValue;
+
+/// A configuration text format.
+pub trait Format {
+    /// Parse the whole configuration text into a value.
+    fn parse(&self, input: &str) -> Result<Value, ParseError>;
+
+    /// Parse like `parse`, failing with `LoadError::LimitExceeded` if the value exceeds
+    /// `limits`.
+    ///
+    /// The default checks the parsed value. Formats that can build a tree much larger
+    /// than their input, like YAML with aliases, check while parsing instead.
+    fn parse_limited(&self, input: &str, limits: &Limits) -> Result<Value, LoadError> {
+        let value = self.parse(input).map_err(LoadError::Parse)?;
+        limits.check(&value).map_err(|e| LoadError::LimitExceeded(Box::new(e)))?;
+        Ok(value)
+    }
+}
+
+/// Error reported by the parser of a format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// Location of the problem in the input, if the parser reports it.
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.message.fmt(f)
+    }
+}
+
+impl error::Error for ParseError {}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(ParseError),
+    /// The profile selected with `ConfigBuilder::profile` could not be applied.
+    Profile(ProfileError),
+    /// A value exceeds the limits set with `ConfigBuilder::limits`.
+    LimitExceeded(Box<At<Error>>),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io(ref e) => e.fmt(f),
+            LoadError::Parse(ref e) => e.fmt(f),
+            LoadError::Profile(ref e) => e.fmt(f),
+            LoadError::LimitExceeded(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            LoadError::Io(ref e) => Some(e),
+            LoadError::Parse(ref e) => Some(e),
+            LoadError::Profile(ref e) => Some(e),
+            LoadError::LimitExceeded(ref e) => Some(e),
+        }
+    }
+}
+
+/// Read the file at `path` and parse it using `format`.
+pub fn load<P: AsRef<path::Path>>(path: P, format: &dyn Format) -> Result<Value, LoadError> {
+    let input = fs::read_to_string(path).map_err(LoadError::Io)?;
+    format.parse(&input).map_err(LoadError::Parse)
+}
+
+/// Read the file at `path` and parse it using `format`, within `limits`.
+pub fn load_limited<P: AsRef<path::Path>>(path: P,
+                                          format: &dyn Format,
+                                          limits: &Limits)
+                                          -> Result<Value, LoadError> {
+    let input = fs::read_to_string(path).map_err(LoadError::Io)?;
+    format.parse_limited(&input, limits)
+}
+
+/// YAML format.
+///
+/// Scalars get their type from the YAML core schema: `42` is an integer and `"42"` or
+/// `!!str 42` a string. Only the first document of the input is parsed, and empty input
+/// is an empty table. Nulls become `Value::Null`. Aliases are replaced with a copy of
+/// their anchored node, so untrusted input should be parsed with `parse_limited` and
+/// `Limits::max_nodes`. Mapping keys that are not strings and duplicate keys are errors,
+/// reported with their path.
+#[cfg(feature = "yaml")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct YamlFormat;
+
+#[cfg(feature = "yaml")]
+impl Format for YamlFormat {
+    fn parse(&self, input: &str) -> Result<Value, ParseError> {
+        parse_yaml(input, &Limits::new()).map_err(|e| {
+            match e {
+                LoadError::Parse(e) => e,
+                other => {
+                    ParseError {
+                        message: other.to_string(),
+                        span: None,
+                    }
+                }
+            }
+        })
+    }
+
+    /// Values are counted while the document is built, so an alias that would expand
+    /// past `Limits::max_nodes` fails before it is copied.
+    fn parse_limited(&self, input: &str, limits: &Limits) -> Result<Value, LoadError> {
+        let value = parse_yaml(input, limits)?;
+        limits.check(&value).map_err(|e| LoadError::LimitExceeded(Box::new(e)))?;
+        Ok(value)
+    }
+}
+
+#[cfg(feature = "yaml")]
+fn parse_yaml(input: &str, limits: &Limits) -> Result<Value, LoadError> {
+    let mut builder = YamlBuilder {
+        limits,
+        stack: vec![],
+        anchors: BTreeMap::new(),
+        nodes: 0,
+        document: None,
+        done: false,
+        error: None,
+    };
+    Parser::new_from_str(input).load(&mut builder, true).map_err(|e| {
+        LoadError::Parse(ParseError {
+            message: e.info().to_string(),
+            span: Some(Span {
+                start: e.marker().index(),
+                end: e.marker().index(),
+            }),
+        })
+    })?;
+    match builder.error {
+        Some(error) => Err(error),
+        None => Ok(builder.document.unwrap_or_else(|| Value::Table(Table::new()))),
+    }
+}
+
+/// Builds the first document of a YAML stream from parser events, expanding aliases.
+///
+/// Every value counts against `Limits::max_nodes` when it is added, and an alias counts
+/// all the values of its anchored node, so the limit is reached before a large expansion
+/// is allocated. Events after the first error or the first document are ignored.
+#[cfg(feature = "yaml")]
+struct YamlBuilder<'l> {
+    limits: &'l Limits,
+    /// Open arrays and tables, with their anchor and the value count before them.
+    stack: Vec<(YamlNode, usize, usize)>,
+    /// Anchored values, with the number of values in each.
+    anchors: BTreeMap<usize, (Value, usize)>,
+    nodes: usize,
+    document: Option<Value>,
+    done: bool,
+    error: Option<LoadError>,
+}
+
+#[cfg(feature = "yaml")]
+enum YamlNode {
+    Array(Vec<Value>),
+    /// A table, with the key of the value being read once the key was read.
+    Table(Table, Option<String>),
+}
+
+#[cfg(feature = "yaml")]
+impl<'l> MarkedEventReceiver for YamlBuilder<'l> {
+    fn on_event(&mut self, event: Event, _mark: Marker) {
+        if self.done || self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.handle(event) {
+            self.error = Some(e);
+        }
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl<'l> YamlBuilder<'l> {
+    fn handle(&mut self, event: Event) -> Result<(), LoadError> {
+        match event {
+            Event::DocumentEnd => self.done = true,
+            Event::SequenceStart(anchor, _) => self.open(YamlNode::Array(vec![]), anchor)?,
+            Event::MappingStart(anchor, _) => {
+                self.open(YamlNode::Table(Table::new(), None), anchor)?
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                let (node, anchor, before) = self.stack.pop().expect("an open array or table");
+                let value = match node {
+                    YamlNode::Array(array) => Value::Array(array),
+                    YamlNode::Table(table, _) => Value::Table(table),
+                };
+                let nodes = self.nodes - before;
+                self.insert(value, anchor, nodes)?;
+            }
+            Event::Scalar(text, style, anchor, tag) => {
+                let value = yaml_scalar(text, style, tag).map_err(|m| self.error(m))?;
+                let nodes = self.count(1)?;
+                self.insert(value, anchor, nodes)?;
+            }
+            Event::Alias(id) => {
+                let nodes = match self.anchors.get(&id) {
+                    Some(&(_, nodes)) => nodes,
+                    None => return Err(self.error("unknown anchor".to_string())),
+                };
+                self.count(nodes)?;
+                let value = self.anchors[&id].0.clone();
+                self.insert(value, 0, nodes)?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, node: YamlNode, anchor: usize) -> Result<(), LoadError> {
+        let before = self.nodes;
+        self.count(1)?;
+        self.stack.push((node, anchor, before));
+        Ok(())
+    }
+
+    /// Count `nodes` new values, unless they are a table key, returning the count added.
+    fn count(&mut self, nodes: usize) -> Result<usize, LoadError> {
+        if self.is_key() {
+            return Ok(0);
+        }
+        self.nodes += nodes;
+        self.limits
+            .check_nodes(self.nodes, &self.path())
+            .map_err(|e| LoadError::LimitExceeded(Box::new(e)))?;
+        Ok(nodes)
+    }
+
+    /// Add a finished value to the open array or table, as a key if a table expects one.
+    fn insert(&mut self, value: Value, anchor: usize, nodes: usize) -> Result<(), LoadError> {
+        // Valid anchors start from 1.
+        if anchor > 0 {
+            self.anchors.insert(anchor, (value.clone(), nodes));
+        }
+        if self.is_key() {
+            let key = match value {
+                Value::String(key) => key,
+                other => {
+                    return Err(self.error(format!("keys must be strings, found {}",
+                                                  other.type_str())))
+                }
+            };
+            if let Some(&mut (YamlNode::Table(ref table, ref mut pending), ..)) =
+                self.stack.last_mut() {
+                if !table.contains_key(&key) {
+                    *pending = Some(key);
+                    return Ok(());
+                }
+            }
+            let mut path = self.path();
+            path.push(PathComponent::Key(key.clone()));
+            let error = Error::DuplicateKey { name: key }.at(components_to_string(&path));
+            return Err(LoadError::Parse(ParseError {
+                message: error.to_string(),
+                span: None,
+            }));
+        }
+        match self.stack.last_mut() {
+            None => self.document = Some(value),
+            Some(&mut (YamlNode::Array(ref mut array), ..)) => array.push(value),
+            Some(&mut (YamlNode::Table(ref mut table, ref mut key), ..)) => {
+                table.insert(key.take().expect("a table key"), value);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_key(&self) -> bool {
+        matches!(self.stack.last(), Some(&(YamlNode::Table(_, None), ..)))
+    }
+
+    /// Path of the value being read.
+    fn path(&self) -> Vec<PathComponent> {
+        self.stack
+            .iter()
+            .filter_map(|entry| match entry.0 {
+                YamlNode::Array(ref array) => Some(PathComponent::Index(array.len())),
+                YamlNode::Table(_, ref key) => key.clone().map(PathComponent::Key),
+            })
+            .collect()
+    }
+
+    fn error(&self, message: String) -> LoadError {
+        let path = self.path();
+        let message = if path.is_empty() {
+            message
+        } else {
+            format!("at '{}': {}", components_to_string(&path), message)
+        };
+        LoadError::Parse(ParseError { message, span: None })
+    }
+}
+
+/// Resolve a scalar with the YAML core schema, the same way `YamlLoader` does.
+#[cfg(feature = "yaml")]
+fn yaml_scalar(text: String, style: TScalarStyle, tag: Option<Tag>) -> Result<Value, String> {
+    let yaml = match tag {
+        _ if style != TScalarStyle::Plain => Yaml::String(text),
+        Some(ref tag) if tag.handle == "tag:yaml.org,2002:" => {
+            match &tag.suffix[..] {
+                "bool" => {
+                    match &text[..] {
+                        "true" | "True" | "TRUE" => Yaml::Boolean(true),
+                        "false" | "False" | "FALSE" => Yaml::Boolean(false),
+                        _ => Yaml::BadValue,
+                    }
+                }
+                "int" => text.parse().map(Yaml::Integer).unwrap_or(Yaml::BadValue),
+                "float" => Yaml::Real(text),
+                "null" => {
+                    match &text[..] {
+                        "~" | "null" => Yaml::Null,
+                        _ => Yaml::BadValue,
+                    }
+                }
+                _ => Yaml::String(text),
+            }
+        }
+        Some(_) => Yaml::String(text),
+        None => Yaml::from_str(&text),
+    };
+    Ok(match yaml {
+        Yaml::String(s) => Value::String(s),
+        Yaml::Integer(i) => Value::Integer(i),
+        Yaml::Real(ref s) => {
+            match yaml.as_f64() {
+                Some(f) => Value::Float(f),
+                None => return Err(format!("`{}` is not a valid float", s)),
+            }
+        }
+        Yaml::Boolean(b) => Value::Boolean(b),
+        Yaml::Null => Value::Null,
+        _ => return Err("value does not match its tag".to_string()),
+    })
+}
+
+/// Parse the TOML document `input`, see `TomlFormat`.
+#[cfg(feature = "toml")]
+pub fn from_toml_str(input: &str) -> Result<Value, LoadError> {
+    TomlFormat.parse(input).map_err(LoadError::Parse)
+}
+
+/// TOML format, the root of a document is always a table.
+#[cfg(feature = "toml")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TomlFormat;
+
+#[cfg(feature = "toml")]
+impl Format for TomlFormat {
+    fn parse(&self, input: &str) -> Result<Value, ParseError> {
+        ::toml::from_str::<::toml::Table>(input)
+            .map(|t| Value::from(::toml::Value::Table(t)))
+            .map_err(|e| {
+                ParseError {
+                    message: e.message().to_string(),
+                    span: e.span().map(|s| {
+                        Span {
+                            start: s.start,
+                            end: s.end,
+                        }
+                    }),
+                }
+            })
+    }
+}
+
+#[cfg(feature = "toml")]
+impl TomlFormat {
+    /// Parse the document, also returning the location of every value in it.
+    ///
+    /// `file` is the name used in locations, like `config.toml`. Values are located at
+    /// their first character, tables defined by a header at the header.
+    pub fn parse_with_source_map(&self,
+                                 file: &str,
+                                 input: &str)
+                                 -> Result<(Value, SourceMap), ParseError> {
+        let value = self.parse(input)?;
+        let mut source_map = SourceMap::new(file);
+        if let Ok(table) = ::toml::de::DeTable::parse(input) {
+            record_toml_table(&mut source_map, input, &mut vec![], table.get_ref());
+        }
+        Ok((value, source_map))
+    }
+}
+
+#[cfg(feature = "toml")]
+fn record_toml_table(source_map: &mut SourceMap,
+                     input: &str,
+                     path: &mut Vec<PathComponent>,
+                     table: &::toml::de::DeTable) {
+    for (key, value) in table {
+        path.push(PathComponent::Key(key.get_ref().to_string()));
+        record_toml_value(source_map, input, path, value);
+        path.pop();
+    }
+}
+
+#[cfg(feature = "toml")]
+fn record_toml_value(source_map: &mut SourceMap,
+                     input: &str,
+                     path: &mut Vec<PathComponent>,
+                     value: &::toml::Spanned<::toml::de::DeValue>) {
+    let span = value.span();
+    source_map.insert_span(components_to_string(path),
+                           input,
+                           Span {
+                               start: span.start,
+                               end: span.end,
+                           });
+    match *value.get_ref() {
+        ::toml::de::DeValue::Table(ref table) => record_toml_table(source_map, input, path, table),
+        ::toml::de::DeValue::Array(ref array) => {
+            for (index, element) in array.iter().enumerate() {
+                path.push(PathComponent::Index(index));
+                record_toml_value(source_map, input, path, element);
+                path.pop();
+            }
+        }
+        _ => (),
+    }
+}
+
+/// A TOML document that parses top-level sections only when they are requested.
+///
+/// Creating a `LazyToml` scans the text once to find the `[section]` and `[[section]]`
+/// headers, and parses only the top-level keys written before the first header. Every
+/// call to `section` then parses the parts of the text belonging to one top-level key.
+///
+/// The whole text stays in memory, but no `Value` tree is kept: the text is usually
+/// several times smaller than the tree built from it. Parsed sections are not cached,
+/// so every `section` call parses again. This pays off when a consumer reads a few
+/// sections of a large document, for example one service's settings out of a generated
+/// machine config; when every section is read, `TomlFormat` is faster. Only the parsed
+/// parts of the document are validated, so an error in a section that is never
+/// requested goes unnoticed.
+#[cfg(feature = "toml")]
+#[derive(Debug, Clone)]
+pub struct LazyToml {
+    source: String,
+    preamble: ::toml::Table,
+    sections: Vec<(String, usize, usize)>,
+}
+
+#[cfg(feature = "toml")]
+impl LazyToml {
+    /// Index the sections of `source` and parse the keys before the first header.
+    pub fn new(source: String) -> Result<LazyToml, ParseError> {
+        let headers = toml_headers(&source)?;
+        let preamble_end = headers.first().map_or(source.len(), |&(_, start)| start);
+        let preamble = parse_toml(&[(&source[..preamble_end], 0)])?;
+        let sections = headers.iter()
+            .enumerate()
+            .map(|(i, &(ref name, start))| {
+                (name.clone(), start, headers.get(i + 1).map_or(source.len(), |h| h.1))
+            })
+            .collect();
+        Ok(LazyToml {
+            source,
+            preamble,
+            sections,
+        })
+    }
+
+    /// Return the names of the top-level keys, in key order.
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.preamble
+            .keys()
+            .map(|k| &k[..])
+            .chain(self.sections.iter().map(|s| &s.0[..]))
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    /// Parse the value of the top-level key `name`, or return `None` if there is none.
+    ///
+    /// Error spans are relative to the whole document.
+    pub fn section(&self, name: &str) -> Result<Option<Value>, ParseError> {
+        let chunks: Vec<(&str, usize)> = self.sections
+            .iter()
+            .filter(|s| s.0 == name)
+            .map(|&(_, start, end)| (&self.source[start..end], start))
+            .collect();
+        let mut table = parse_toml(&chunks)?;
+        let mut value = table.remove(name).map(Value::from);
+        if let Some(preamble) = self.preamble.get(name) {
+            let preamble = Value::from(preamble.clone());
+            value = Some(match value {
+                Some(mut value) => {
+                    value.merge(preamble);
+                    value
+                }
+                None => preamble,
+            });
+        }
+        Ok(value)
+    }
+}
+
+/// Parse the concatenation of chunks, given with their offsets in the whole document.
+#[cfg(feature = "toml")]
+fn parse_toml(chunks: &[(&str, usize)]) -> Result<::toml::Table, ParseError> {
+    let text: String = chunks.iter().map(|c| c.0).collect();
+    ::toml::from_str::<::toml::Table>(&text).map_err(|e| {
+        let to_source = |offset: usize| {
+            let mut chunk_start = 0;
+            for &(chunk, source_start) in chunks {
+                if offset < chunk_start + chunk.len() {
+                    return source_start + offset - chunk_start;
+                }
+                chunk_start += chunk.len();
+            }
+            chunks.last().map_or(offset, |&(chunk, start)| start + chunk.len())
+        };
+        ParseError {
+            message: e.message().to_string(),
+            span: e.span().map(|s| {
+                Span {
+                    start: to_source(s.start),
+                    end: to_source(s.end),
+                }
+            }),
+        }
+    })
+}
+
+/// Find the table headers of a TOML document, as the top-level key and the offset of
+/// the header line.
+///
+/// Lines inside multi-line strings and multi-line arrays are skipped.
+#[cfg(feature = "toml")]
+fn toml_headers(source: &str) -> Result<Vec<(String, usize)>, ParseError> {
+    let mut headers = vec![];
+    let mut multiline: Option<&str> = None;
+    let mut depth = 0usize;
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        if multiline.is_none() && depth == 0 && line.trim_start().starts_with('[') {
+            let table = parse_toml(&[(line, start)])?;
+            if let Some(name) = table.keys().next() {
+                headers.push((name.clone(), start));
+            }
+            continue;
+        }
+        let mut rest = line;
+        while !rest.is_empty() {
+            if let Some(delimiter) = multiline {
+                match find_closing(rest, delimiter) {
+                    Some(end) => {
+                        multiline = None;
+                        rest = &rest[end..];
+                    }
+                    None => break,
+                }
+                continue;
+            }
+            let c = rest.chars().next().unwrap();
+            rest = &rest[c.len_utf8()..];
+            match c {
+                '#' => break,
+                '"' | '\'' => {
+                    let triple = if c == '"' { "\"\"" } else { "''" };
+                    if let Some(after) = rest.strip_prefix(triple) {
+                        multiline = Some(if c == '"' { "\"\"\"" } else { "'''" });
+                        rest = after;
+                    } else {
+                        let delimiter = if c == '"' { "\"" } else { "'" };
+                        rest = find_closing(rest, delimiter).map_or("", |end| &rest[end..]);
+                    }
+                }
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    Ok(headers)
+}
+
+/// Return the offset just after the closing `delimiter` of a string, skipping escapes in
+/// basic strings.
+#[cfg(feature = "toml")]
+fn find_closing(s: &str, delimiter: &str) -> Option<usize> {
+    let escapes = delimiter.starts_with('"');
+    let mut i = 0;
+    while i < s.len() {
+        if escapes && s.as_bytes()[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if s.as_bytes()[i..].starts_with(delimiter.as_bytes()) {
+            return Some(i + delimiter.len());
+        }
+        i += 1;
+    }
+    None
+}
+
+/// A JSON object whose top-level values are parsed only when they are requested.
+///
+/// Creating a `LazyJson` checks the syntax of the whole text and records where the value
+/// of every top-level key starts and ends, without building any of them. Every call to
+/// `section` then parses the text of one value.
+///
+/// Like `LazyToml`, only the text and this index stay in memory, and parsed sections are
+/// not cached. It pays off when a few keys of a large object are read; when every key is
+/// read, parsing the whole document at once is faster. Unlike `LazyToml`, syntax errors
+/// anywhere in the document are reported by `new`. A duplicate top-level key is an error
+/// of `new`, and a duplicate key inside a section an error of `section`.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone)]
+pub struct LazyJson {
+    source: String,
+    /// Top-level keys with the byte range of their value, in key order.
+    sections: Vec<(String, usize, usize)>,
+}
+
+#[cfg(feature = "json")]
+impl LazyJson {
+    /// Check the syntax of `source`, which must be a JSON object, and index its keys.
+    pub fn new(source: String) -> Result<LazyJson, ParseError> {
+        let sections = {
+            let raw: JsonSections = ::serde_json::from_str(&source)
+                .map_err(|e| json_error(&source, 0, &e))?;
+            raw.0
+                .into_iter()
+                .map(|(key, value)| {
+                    let start = value.get().as_ptr() as usize - source.as_ptr() as usize;
+                    (key, start, start + value.get().len())
+                })
+                .collect()
+        };
+        Ok(LazyJson { source, sections })
+    }
+
+    /// Return the names of the top-level keys, in key order.
+    pub fn keys(&self) -> Vec<&str> {
+        self.sections.iter().map(|s| &s.0[..]).collect()
+    }
+
+    /// Parse the value of the top-level key `name`, or return `None` if there is none.
+    ///
+    /// Error spans are relative to the whole document.
+    pub fn section(&self, name: &str) -> Result<Option<Value>, ParseError> {
+        let (start, end) = match self.sections.binary_search_by(|s| s.0[..].cmp(name)) {
+            Ok(index) => (self.sections[index].1, self.sections[index].2),
+            Err(..) => return Ok(None),
+        };
+        let text = &self.source[start..end];
+        ::serde_json::from_str::<UniqueKeys>(text)
+            .and_then(|_| ::serde_json::from_str::<::serde_json::Value>(text))
+            .map(|value| Some(Value::from(value)))
+            .map_err(|e| json_error(text, start, &e))
+    }
+}
+
+#[cfg(feature = "json")]
+fn duplicate_key<E: de::Error>(name: String) -> E {
+    E::custom(Error::DuplicateKey { name })
+}
+
+/// Top-level entries of a JSON object, in key order, that fail on a duplicate key.
+#[cfg(feature = "json")]
+struct JsonSections<'a>(BTreeMap<String, &'a RawValue>);
+
+#[cfg(feature = "json")]
+impl<'de: 'a, 'a> Deserialize<'de> for JsonSections<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<JsonSections<'a>, D::Error> {
+        struct SectionsVisitor<'a>(PhantomData<&'a ()>);
+
+        impl<'de: 'a, 'a> Visitor<'de> for SectionsVisitor<'a> {
+            type Value = JsonSections<'a>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut sections = BTreeMap::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    if sections.contains_key(&key) {
+                        return Err(duplicate_key(key));
+                    }
+                    let value = map.next_value()?;
+                    sections.insert(key, value);
+                }
+                Ok(JsonSections(sections))
+            }
+        }
+
+        deserializer.deserialize_map(SectionsVisitor(PhantomData))
+    }
+}
+
+/// A JSON value checked for duplicate keys in all its objects, and otherwise ignored.
+#[cfg(feature = "json")]
+struct UniqueKeys;
+
+#[cfg(feature = "json")]
+impl<'de> Deserialize<'de> for UniqueKeys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<UniqueKeys, D::Error> {
+        deserializer.deserialize_any(UniqueKeysVisitor)
+    }
+}
+
+#[cfg(feature = "json")]
+struct UniqueKeysVisitor;
+
+#[cfg(feature = "json")]
+impl<'de> Visitor<'de> for UniqueKeysVisitor {
+    type Value = UniqueKeys;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<UniqueKeys, A::Error> {
+        while seq.next_element::<UniqueKeys>()?.is_some() {}
+        Ok(UniqueKeys)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<UniqueKeys, A::Error> {
+        let mut keys = BTreeSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if keys.contains(&key) {
+                return Err(duplicate_key(key));
+            }
+            map.next_value::<UniqueKeys>()?;
+            keys.insert(key);
+        }
+        Ok(UniqueKeys)
+    }
+}
+
+/// Convert the error of parsing `text`, which is at `offset` in the whole document.
+#[cfg(feature = "json")]
+fn json_error(text: &str, offset: usize, e: &::serde_json::Error) -> ParseError {
+    let span = if e.line() == 0 {
+        None
+    } else {
+        let line_start: usize = text.split_inclusive('\n').take(e.line() - 1).map(str::len).sum();
+        let position = cmp::min(offset + line_start + e.column().saturating_sub(1),
+                                offset + text.len());
+        Some(Span {
+            start: position,
+            end: position,
+        })
+    };
+    ParseError {
+        message: e.to_string(),
+        span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "json")]
+    use decode::Span;
+    #[cfg(feature = "json")]
+    use Value;
+    #[cfg(feature = "json")]
+    use super::LazyJson;
+    #[cfg(feature = "yaml")]
+    use decode::{Error, Limit, Limits};
+    #[cfg(feature = "yaml")]
+    use source::{ConfigBuilder, StringSource};
+    #[cfg(feature = "yaml")]
+    use super::{Format, LoadError, YamlFormat};
+    #[cfg(feature = "toml")]
+    use super::TomlFormat;
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn lazy_json_parses_requested_sections() {
+        let source = r#"{ "b": [1, 2.5, null], "a": { "port": 80 } }"#;
+        let json = LazyJson::new(source.to_string()).unwrap();
+        assert_eq!(json.keys(), ["a", "b"]);
+        assert_eq!(json.section("a").unwrap(), Some(value!{ port: 80 }));
+        assert_eq!(json.section("b").unwrap(), Some(value!([1, 2.5, null])));
+        assert_eq!(json.section("c").unwrap(), None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn lazy_json_reports_syntax_errors_in_any_section() {
+        let source = "{\n  \"a\": 1,\n  \"b\": [1,, 2]\n}";
+        let e = LazyJson::new(source.to_string()).unwrap_err();
+        assert_eq!(e.span, Some(Span { start: 22, end: 22 }));
+        assert_eq!(&source[21..23], ",,");
+        assert!(LazyJson::new("[1, 2]".to_string()).is_err());
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_expands_aliases() {
+        let value = YamlFormat.parse("base: &base { port: 80 }\nserver: *base\n").unwrap();
+        assert_eq!(value, value!{ base: { port: 80 }, server: { port: 80 } });
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_rejects_invalid_floats() {
+        assert_eq!(YamlFormat.parse("a: [1.5, !!float x]").unwrap_err().message,
+                   "at 'a[1]': `x` is not a valid float");
+        assert_eq!(YamlFormat.parse("a: !!int x").unwrap_err().message,
+                   "at 'a': value does not match its tag");
+        assert_eq!(YamlFormat.parse("? [1]\n: a").unwrap_err().message,
+                   "keys must be strings, found array");
+    }
+
+    #[cfg(feature = "yaml")]
+    const LAUGHS: &str = "a: &a [lol, lol, lol, lol, lol, lol, lol, lol, lol, lol]
+b: &b [*a, *a, *a, *a, *a, *a, *a, *a, *a, *a]
+c: &c [*b, *b, *b, *b, *b, *b, *b, *b, *b, *b]
+d: &d [*c, *c, *c, *c, *c, *c, *c, *c, *c, *c]
+e: &e [*d, *d, *d, *d, *d, *d, *d, *d, *d, *d]
+f: &f [*e, *e, *e, *e, *e, *e, *e, *e, *e, *e]
+g: &g [*f, *f, *f, *f, *f, *f, *f, *f, *f, *f]
+h: &h [*g, *g, *g, *g, *g, *g, *g, *g, *g, *g]
+i: [*h, *h, *h, *h, *h, *h, *h, *h, *h, *h]
+";
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_counts_alias_expansions_against_max_nodes() {
+        let limits = Limits::new().max_nodes(10_000);
+        match YamlFormat.parse_limited(LAUGHS, &limits) {
+            Err(LoadError::LimitExceeded(e)) => {
+                match e.error {
+                    Error::LimitExceeded { limit: Limit::Nodes, max: 10_000, found } => {
+                        assert!(found > 10_000)
+                    }
+                    ref other => panic!("unexpected error {:?}", other),
+                }
+                assert_eq!(e.path, "d[7]");
+            }
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+        let value = YamlFormat.parse("a: &a { k: [1, 2] }\nb: *a\n").unwrap();
+        assert_eq!(value, value!{ a: { k: [1, 2] }, b: { k: [1, 2] } });
+        assert!(Limits::new().max_nodes(9).check(&value).is_ok());
+        assert!(YamlFormat.parse_limited("a: &a { k: [1, 2] }\nb: *a\n",
+                                         &Limits::new().max_nodes(9))
+            .is_ok());
+        assert!(YamlFormat.parse_limited("a: &a { k: [1, 2] }\nb: *a\n",
+                                         &Limits::new().max_nodes(8))
+            .is_err());
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn config_builder_parses_yaml_within_limits() {
+        let builder = ConfigBuilder::new()
+            .add_source(StringSource::new(LAUGHS, &YamlFormat))
+            .limits(Limits::new().max_nodes(1000));
+        match builder.build() {
+            Err(LoadError::LimitExceeded(e)) => assert_eq!(e.path, "c[7]"),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn lazy_json_rejects_duplicate_keys() {
+        let source = r#"{ "a": 1, "b": 2, "a": 3 }"#;
+        let e = LazyJson::new(source.to_string()).unwrap_err();
+        assert!(e.message.starts_with("duplicate key 'a'"), "{}", e.message);
+        assert_eq!(e.span, Some(Span { start: 20, end: 20 }));
+        let source = r#"{ "a": [{ "x": 1, "x": 2 }], "b": 2 }"#;
+        let json = LazyJson::new(source.to_string()).unwrap();
+        assert_eq!(json.section("b").unwrap(), Some(Value::Integer(2)));
+        let e = json.section("a").unwrap_err();
+        assert!(e.message.starts_with("duplicate key 'x'"), "{}", e.message);
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_rejects_duplicate_keys() {
+        assert_eq!(YamlFormat.parse("a: 1\na: 2\n").unwrap_err().message,
+                   "at 'a': duplicate key 'a'");
+        assert_eq!(YamlFormat.parse("s:\n  - { x: 1, x: 2 }\n").unwrap_err().message,
+                   "at 's[0].x': duplicate key 'x'");
+        assert_eq!(YamlFormat.parse("a: { x: 1 }\nb: { x: 2 }\n").unwrap(),
+                   value!{ a: { x: 1 }, b: { x: 2 } });
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn source_map_lists_keys_in_source_order() {
+        let source = "zeta = 1\nalpha = 2\n[server]\nport = 80\nhost = \"a\"\n";
+        let (value, source_map) = TomlFormat.parse_with_source_map("c.toml", source).unwrap();
+        let mut root = value.as_table().unwrap().clone();
+        assert_eq!(source_map.keys_in_source_order("", &root), ["zeta", "alpha", "server"]);
+        let server = value.get_path("server").unwrap().as_table().unwrap();
+        assert_eq!(source_map.keys_in_source_order("server", server), ["port", "host"]);
+        root.insert("added".to_string(), value!(true));
+        root.insert("aaa".to_string(), value!(true));
+        assert_eq!(source_map.keys_in_source_order("", &root),
+                   ["zeta", "alpha", "server", "aaa", "added"]);
+    }
+
+    #[cfg(all(feature = "toml", feature = "indexmap"))]
+    #[test]
+    fn source_map_builds_ordered_tables() {
+        let source = "zeta = 1\nalpha = { b = 2, a = 1 }\n";
+        let (value, source_map) = TomlFormat.parse_with_source_map("c.toml", source).unwrap();
+        let ordered = source_map.ordered_table("", value.as_table().unwrap());
+        assert_eq!(ordered.keys().collect::<Vec<_>>(), ["zeta", "alpha"]);
+        assert_eq!(ordered["alpha"], value!{ a: 1, b: 2 });
+        let alpha = source_map.ordered_table("alpha", ordered["alpha"].as_table().unwrap());
+        assert_eq!(alpha.keys().collect::<Vec<_>>(), ["b", "a"]);
+    }
+
+    #[cfg(all(feature = "toml", feature = "indexmap"))]
+    #[test]
+    fn table_entries_follow_source_order() {
+        let source = "zeta = 1\n[server]\nport = 80\nhost = \"a\"\n";
+        let (value, source_map) = TomlFormat.parse_with_source_map("c.toml", source).unwrap();
+        let server = value.get_path("server").unwrap();
+        assert_eq!(server.table_entries_insertion_order("server", &source_map),
+                   Some(vec![("port", &value!(80)), ("host", &value!("a"))]));
+        assert_eq!(value.table_entries_insertion_order("", &source_map).unwrap()[0],
+                   ("zeta", &value!(1)));
+        assert_eq!(value!(1).table_entries_insertion_order("", &source_map), None);
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn source_map_locates_nested_values_and_array_elements() {
+        let source = "\"žalias\" = \"ółw\"\nports = [80,\n  443]\n\n[server.tls]\nkey = \"ą\" \
+                      # ė\ncert = 1\n";
+        let (_, source_map) = TomlFormat.parse_with_source_map("c.toml", source).unwrap();
+        let location = |path: &str| {
+            source_map.location(path).map(|l| (l.line, l.column))
+        };
+        assert_eq!(location("žalias"), Some((1, 12)));
+        assert_eq!(location("server.tls"), Some((5, 1)));
+        assert_eq!(location("ports"), Some((2, 9)));
+        assert_eq!(location("ports[0]"), Some((2, 10)));
+        assert_eq!(location("ports[1]"), Some((3, 3)));
+        assert_eq!(location("server.tls.key"), Some((6, 7)));
+        assert_eq!(location("server.tls.cert"), Some((7, 8)));
+        assert_eq!(location("server.tls.cert.missing"), Some((7, 8)));
+        assert_eq!(source_map.location("server.tls.cert").unwrap().to_string(), "c.toml:7:8");
+    }
+}
@@ -0,0 +1,400 @@
+//! Composing a configuration from several documents with `include` keys.
+//!
+//! A table may list other documents in its `include` key, as a string or an array of
+//! strings, like `include = ["db.toml", "secrets/*.toml"]`. The included documents
+//! are merged in order into an empty table, and the entries of the including table are
+//! merged over the result, so the including table can override what it includes.
+//! Included documents may include others, and the `include` key is removed everywhere.
+//!
+//! Documents are found and loaded by a `Loader`, so they can come from anywhere;
+//! `FileLoader` reads them from the file system.
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path;
+use value::components_to_string;
+use format::{self, Format, LoadError};
+use {Value, PathComponent, Table};
+
+/// Name of the table key listing the included documents.
+pub const INCLUDE_KEY: &str = "include";
+
+/// Source of documents for `load`.
+pub trait Loader {
+    /// Return the names of the documents `pattern` refers to, in the order to merge them.
+    ///
+    /// `from` is the name of the including document, so that patterns can be relative
+    /// to it. A pattern matching nothing may return an empty list.
+    fn find(&self, from: &str, pattern: &str) -> Result<Vec<String>, LoadError>;
+
+    /// Load the document `name`, as returned by `find`.
+    fn load(&self, name: &str) -> Result<Value, LoadError>;
+}
+
+/// Loader of files in the given format.
+///
+/// Patterns are file paths relative to the directory of the including file. The last
+/// component of a pattern may contain `*` (any characters) and `?` (one character)
+/// wildcards; matching files are included in name order.
+#[derive(Clone, Copy)]
+pub struct FileLoader<'f> {
+    format: &'f dyn Format,
+}
+
+impl<'f> FileLoader<'f> {
+    pub fn new(format: &'f dyn Format) -> FileLoader<'f> {
+        FileLoader { format }
+    }
+}
+
+impl<'f> Loader for FileLoader<'f> {
+    fn find(&self, from: &str, pattern: &str) -> Result<Vec<String>, LoadError> {
+        let pattern = normalize(&match path::Path::new(from).parent() {
+            Some(dir) => dir.join(pattern),
+            None => path::PathBuf::from(pattern),
+        });
+        let file_pattern = match pattern.file_name().and_then(|n| n.to_str()) {
+            Some(name) if name.contains(['*', '?']) => name.to_string(),
+            _ => return Ok(vec![pattern.to_string_lossy().into_owned()]),
+        };
+        let dir = match pattern.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => path::PathBuf::from("."),
+        };
+        let mut names = vec![];
+        for entry in fs::read_dir(&dir).map_err(LoadError::Io)? {
+            let entry = entry.map_err(LoadError::Io)?;
+            let matches = entry.file_name()
+                .to_str()
+                .is_some_and(|n| wildcard_match(&file_pattern, n));
+            if matches && entry.file_type().map_err(LoadError::Io)?.is_file() {
+                names.push(dir.join(entry.file_name()).to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn load(&self, name: &str) -> Result<Value, LoadError> {
+        format::load(name, self.format)
+    }
+}
+
+/// Error returned by `load`.
+#[derive(Debug)]
+pub struct IncludeError {
+    /// Name of the document with the failed include; for a failed root document, the
+    /// name of that document.
+    pub document: String,
+    /// Path of the failed `include` entry in the document, empty for the root document.
+    pub path: String,
+    pub kind: IncludeErrorKind,
+}
+
+#[derive(Debug)]
+pub enum IncludeErrorKind {
+    /// A document could not be found or loaded.
+    Load(LoadError),
+    /// Documents include each other; the first name is also the last one.
+    Cycle(Vec<String>),
+    /// The `include` value is not a string or an array of strings.
+    ExpectedPaths {
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for IncludeError {
+    /// Formats as `app.toml: at 'include[1]': ...`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.document)?;
+        if !self.path.is_empty() {
+            write!(f, "at '{}': ", self.path)?;
+        }
+        match self.kind {
+            IncludeErrorKind::Load(ref e) => write!(f, "{}", e),
+            IncludeErrorKind::Cycle(ref names) => {
+                write!(f, "include cycle '{}'", names.join("' -> '"))
+            }
+            IncludeErrorKind::ExpectedPaths { found } => {
+                write!(f, "expected a string or an array of strings, found {}", found)
+            }
+        }
+    }
+}
+
+impl error::Error for IncludeError {}
+
+/// Load the document `name` with `loader`, resolving includes.
+pub fn load(name: &str, loader: &dyn Loader) -> Result<Value, IncludeError> {
+    let error = |kind| {
+        IncludeError {
+            document: name.to_string(),
+            path: String::new(),
+            kind,
+        }
+    };
+    let mut value = loader.load(name).map_err(|e| error(IncludeErrorKind::Load(e)))?;
+    let mut stack = vec![name.to_string()];
+    resolve(&mut value, loader, &mut vec![], &mut stack)?;
+    Ok(value)
+}
+
+/// Resolve includes in `value`, at `path` of the last document in `stack`.
+fn resolve(value: &mut Value,
+           loader: &dyn Loader,
+           path: &mut Vec<PathComponent>,
+           stack: &mut Vec<String>)
+           -> Result<(), IncludeError> {
+    let include = match *value {
+        Value::Table(ref mut t) => {
+            let include = t.remove(INCLUDE_KEY);
+            for (key, value) in t.iter_mut() {
+                path.push(PathComponent::Key(key.clone()));
+                resolve(value, loader, path, stack)?;
+                path.pop();
+            }
+            include
+        }
+        Value::Array(ref mut a) => {
+            for (index, value) in a.iter_mut().enumerate() {
+                path.push(PathComponent::Index(index));
+                resolve(value, loader, path, stack)?;
+                path.pop();
+            }
+            None
+        }
+        _ => None,
+    };
+    if let Some(include) = include {
+        path.push(PathComponent::Key(INCLUDE_KEY.to_string()));
+        let mut merged = include_all(&include, loader, path, stack)?;
+        path.pop();
+        merged.merge(::std::mem::replace(value, Value::Table(Table::new())));
+        *value = merged;
+    }
+    Ok(())
+}
+
+/// Load and merge the documents listed in the `include` value at `path`.
+fn include_all(include: &Value,
+               loader: &dyn Loader,
+               path: &mut Vec<PathComponent>,
+               stack: &mut Vec<String>)
+               -> Result<Value, IncludeError> {
+    let patterns: Vec<(Option<usize>, &str)> = match *include {
+        Value::String(ref s) => vec![(None, s)],
+        Value::Array(ref a) => {
+            let mut patterns = vec![];
+            for (index, pattern) in a.iter().enumerate() {
+                match *pattern {
+                    Value::String(ref s) => patterns.push((Some(index), &s[..])),
+                    ref other => {
+                        path.push(PathComponent::Index(index));
+                        return Err(error_at(path,
+                                            stack,
+                                            IncludeErrorKind::ExpectedPaths {
+                                                found: other.type_str(),
+                                            }));
+                    }
+                }
+            }
+            patterns
+        }
+        ref other => {
+            return Err(error_at(path,
+                                stack,
+                                IncludeErrorKind::ExpectedPaths { found: other.type_str() }))
+        }
+    };
+    let mut merged = Value::Table(Table::new());
+    for (index, pattern) in patterns {
+        if let Some(index) = index {
+            path.push(PathComponent::Index(index));
+        }
+        let from = stack.last().expect("including document").clone();
+        let names = loader.find(&from, pattern)
+            .map_err(|e| error_at(path, stack, IncludeErrorKind::Load(e)))?;
+        for name in names {
+            if let Some(start) = stack.iter().position(|n| *n == name) {
+                let mut cycle = stack[start..].to_vec();
+                cycle.push(name);
+                return Err(error_at(path, stack, IncludeErrorKind::Cycle(cycle)));
+            }
+            let mut value = loader.load(&name)
+                .map_err(|e| error_at(path, stack, IncludeErrorKind::Load(e)))?;
+            stack.push(name);
+            resolve(&mut value, loader, &mut vec![], stack)?;
+            stack.pop();
+            merged.merge(value);
+        }
+        if index.is_some() {
+            path.pop();
+        }
+    }
+    Ok(merged)
+}
+
+fn error_at(path: &[PathComponent], stack: &[String], kind: IncludeErrorKind) -> IncludeError {
+    IncludeError {
+        document: stack.last().expect("including document").clone(),
+        path: components_to_string(path),
+        kind,
+    }
+}
+
+/// Remove `.` components and resolve `..` where possible, so that every way of writing
+/// a path relative to another file gives the same name, and include cycles are found.
+fn normalize(path: &path::Path) -> path::PathBuf {
+    let mut result = path::PathBuf::new();
+    for component in path.components() {
+        match component {
+            path::Component::CurDir => (),
+            path::Component::ParentDir => {
+                match result.components().next_back() {
+                    Some(path::Component::Normal(..)) => {
+                        result.pop();
+                    }
+                    Some(path::Component::RootDir) | Some(path::Component::Prefix(..)) => (),
+                    _ => result.push(".."),
+                }
+            }
+            other => result.push(other.as_os_str()),
+        }
+    }
+    result
+}
+
+/// Match `name` against `pattern` with `*` and `?` wildcards.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut star = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io;
+    use std::path;
+    use format::LoadError;
+    use Value;
+    use super::{load, normalize, wildcard_match, IncludeErrorKind, Loader};
+
+    /// Documents kept in memory; patterns are matched against every name.
+    struct Documents(BTreeMap<&'static str, Value>);
+
+    impl Loader for Documents {
+        fn find(&self, _from: &str, pattern: &str) -> Result<Vec<String>, LoadError> {
+            if !pattern.contains(['*', '?']) {
+                return Ok(vec![pattern.to_string()]);
+            }
+            Ok(self.0
+                .keys()
+                .filter(|n| wildcard_match(pattern, n))
+                .map(|n| n.to_string())
+                .collect())
+        }
+
+        fn load(&self, name: &str) -> Result<Value, LoadError> {
+            self.0
+                .get(name)
+                .cloned()
+                .ok_or_else(|| LoadError::Io(io::Error::new(io::ErrorKind::NotFound, "missing")))
+        }
+    }
+
+    fn documents(documents: Vec<(&'static str, Value)>) -> Documents {
+        Documents(documents.into_iter().collect())
+    }
+
+    #[test]
+    fn including_table_overrides_included_documents() {
+        let loader = documents(vec![
+            ("app", value!{ include: ["base", "env-*"], port: 80, db: { user: "app" } }),
+            ("base", value!{ port: 1, host: "a", db: { user: "base", pool: 2 } }),
+            ("env-a", value!{ host: "b", include: "nested" }),
+            ("env-b", value!{ host: "c" }),
+            ("nested", value!{ host: "nested", debug: true }),
+        ]);
+        assert_eq!(load("app", &loader).unwrap(),
+                   value!{ port: 80, host: "c", debug: true, db: { user: "app", pool: 2 } });
+    }
+
+    #[test]
+    fn resolves_includes_in_nested_tables_and_arrays() {
+        let loader = documents(vec![
+            ("app", value!{ servers: [{ include: "web", port: 81 }] }),
+            ("web", value!{ port: 80, name: "web" }),
+        ]);
+        assert_eq!(load("app", &loader).unwrap(),
+                   value!{ servers: [{ port: 81, name: "web" }] });
+    }
+
+    #[test]
+    fn reports_include_cycles() {
+        let loader = documents(vec![
+            ("a", value!{ include: "b" }),
+            ("b", value!{ x: { include: ["c", "a"] } }),
+            ("c", value!{}),
+        ]);
+        let e = load("a", &loader).unwrap_err();
+        assert_eq!(e.to_string(), "b: at 'x.include[1]': include cycle 'a' -> 'b' -> 'a'");
+        match e.kind {
+            IncludeErrorKind::Cycle(ref names) => assert_eq!(names, &["a", "b", "a"]),
+            ref other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_include_values_that_are_not_paths() {
+        let loader = documents(vec![("a", value!{ include: ["b", 1] }),
+                                    ("b", value!{ include: { x: 1 } }),
+                                    ("c", value!{ include: "missing" })]);
+        assert_eq!(load("a", &loader).unwrap_err().to_string(),
+                   "a: at 'include[1]': expected a string or an array of strings, found integer");
+        assert_eq!(load("b", &loader).unwrap_err().to_string(),
+                   "b: at 'include': expected a string or an array of strings, found table");
+        assert_eq!(load("c", &loader).unwrap_err().to_string(), "c: at 'include': missing");
+        assert_eq!(load("nope", &loader).unwrap_err().to_string(), "nope: missing");
+    }
+
+    #[test]
+    fn normalize_removes_dots() {
+        let normalized = |p: &str| normalize(path::Path::new(p)).to_string_lossy().into_owned();
+        assert_eq!(normalized("conf/./db/../app.toml"), "conf/app.toml");
+        assert_eq!(normalized("../a/../../b.toml"), "../../b.toml");
+        assert_eq!(normalized("/../etc/app.toml"), "/etc/app.toml");
+        assert_eq!(normalized("./app.toml"), "app.toml");
+    }
+
+    #[test]
+    fn wildcard_match_supports_star_and_question_mark() {
+        assert!(wildcard_match("*.toml", "db.toml"));
+        assert!(wildcard_match("*.toml", ".toml"));
+        assert!(wildcard_match("db-?.toml", "db-1.toml"));
+        assert!(wildcard_match("a*b*c", "axxbyybc"));
+        assert!(wildcard_match("**", ""));
+        assert!(wildcard_match("ž?.toml", "žą.toml"));
+        assert!(!wildcard_match("db-?.toml", "db-10.toml"));
+        assert!(!wildcard_match("*.toml", "db.yaml"));
+        assert!(!wildcard_match("a*b", "ab c"));
+    }
+}
@@ -0,0 +1,80 @@
+//! Conversions to and from the `config` crate's `Value`.
+//!
+//! The `config` crate has no datetime type, so datetimes become strings, and strings
+//! coming from it are always `Value::String`. The origin of converted values is not kept.
+
+use std::convert::TryFrom;
+use config::{Map, Value as ConfigValue, ValueKind};
+use {Value, Table};
+use super::{child_path, index_path, ConvertError};
+
+impl From<Value> for ConfigValue {
+    fn from(value: Value) -> ConfigValue {
+        ConfigValue::new(None,
+                         match value {
+                             Value::String(s) |
+                             Value::Datetime(s) => ValueKind::String(s),
+                             Value::Integer(i) => ValueKind::I64(i),
+                             Value::Float(f) => ValueKind::Float(f),
+                             Value::Boolean(b) => ValueKind::Boolean(b),
+                             Value::Null => ValueKind::Nil,
+                             Value::Array(a) => {
+                                 ValueKind::Array(a.into_iter().map(ConfigValue::from).collect())
+                             }
+                             Value::Table(t) => {
+                                 ValueKind::Table(t.into_iter()
+                                     .map(|(k, v)| (k, ConfigValue::from(v)))
+                                     .collect::<Map<_, _>>())
+                             }
+                         })
+    }
+}
+
+impl TryFrom<ConfigValue> for Value {
+    type Error = ConvertError;
+
+    /// `Nil` becomes `Value::Null`. Fails on integers that do not fit into `i64`.
+    fn try_from(value: ConfigValue) -> Result<Value, ConvertError> {
+        convert(value, "")
+    }
+}
+
+fn convert(value: ConfigValue, path: &str) -> Result<Value, ConvertError> {
+    let out_of_range = |value: String| {
+        ConvertError::IntegerOutOfRange {
+            path: path.to_string(),
+            value,
+        }
+    };
+    Ok(match value.kind {
+        ValueKind::Nil => Value::Null,
+        ValueKind::Boolean(b) => Value::Boolean(b),
+        ValueKind::I64(i) => Value::Integer(i),
+        ValueKind::I128(i) => {
+            Value::Integer(i64::try_from(i).map_err(|_| out_of_range(i.to_string()))?)
+        }
+        ValueKind::U64(i) => {
+            Value::Integer(i64::try_from(i).map_err(|_| out_of_range(i.to_string()))?)
+        }
+        ValueKind::U128(i) => {
+            Value::Integer(i64::try_from(i).map_err(|_| out_of_range(i.to_string()))?)
+        }
+        ValueKind::Float(f) => Value::Float(f),
+        ValueKind::String(s) => Value::String(s),
+        ValueKind::Array(a) => {
+            let mut array = Vec::with_capacity(a.len());
+            for (index, v) in a.into_iter().enumerate() {
+                array.push(convert(v, &index_path(path, index))?);
+            }
+            Value::Array(array)
+        }
+        ValueKind::Table(t) => {
+            let mut table = Table::new();
+            for (k, v) in t {
+                let v = convert(v, &child_path(path, &k))?;
+                table.insert(k, v);
+            }
+            Value::Table(table)
+        }
+    })
+}
@@ -0,0 +1,120 @@
+//! Conversions to and from the `serde_json` crate's `Value`, and serialization to JSON text.
+//!
+//! JSON numbers become `Value::Integer` if they are integral and fit into `i64`, and
+//! `Value::Float` otherwise, so integers beyond the `i64` range lose precision. JSON has
+//! no datetime type, so datetimes stay strings, and `Value::Datetime` is written as a
+//! string.
+
+use std::convert::TryFrom;
+use serde_json::{Map, Number};
+use serde_json::Value as JsonValue;
+use Value;
+use super::{child_path, index_path, SerializeError};
+
+impl From<JsonValue> for Value {
+    fn from(value: JsonValue) -> Value {
+        match value {
+            JsonValue::Null => Value::Null,
+            JsonValue::Bool(b) => Value::Boolean(b),
+            JsonValue::Number(n) => {
+                match n.as_i64() {
+                    Some(i) => Value::Integer(i),
+                    None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+                }
+            }
+            JsonValue::String(s) => Value::String(s),
+            JsonValue::Array(a) => Value::Array(a.into_iter().map(Value::from).collect()),
+            JsonValue::Object(o) => {
+                Value::Table(o.into_iter().map(|(k, v)| (k, Value::from(v))).collect())
+            }
+        }
+    }
+}
+
+impl TryFrom<Value> for JsonValue {
+    type Error = SerializeError;
+
+    /// Fails on NaN and infinite floats.
+    fn try_from(value: Value) -> Result<JsonValue, SerializeError> {
+        to_json(&value, "")
+    }
+}
+
+impl Value {
+    /// Serialize this value as pretty-printed JSON text.
+    ///
+    /// Will return error if the value contains a NaN or infinite float, which JSON can
+    /// not represent.
+    pub fn to_json_string(&self) -> Result<String, SerializeError> {
+        ::serde_json::to_string_pretty(&to_json(self, "")?).map_err(|e| {
+            SerializeError {
+                message: e.to_string(),
+                path: String::new(),
+            }
+        })
+    }
+}
+
+fn to_json(value: &Value, path: &str) -> Result<JsonValue, SerializeError> {
+    Ok(match *value {
+        Value::String(ref s) |
+        Value::Datetime(ref s) => JsonValue::String(s.clone()),
+        Value::Integer(i) => JsonValue::Number(i.into()),
+        Value::Float(f) => {
+            JsonValue::Number(Number::from_f64(f).ok_or_else(|| {
+                SerializeError {
+                    message: format!("{} can not be represented in JSON", f),
+                    path: path.to_string(),
+                }
+            })?)
+        }
+        Value::Boolean(b) => JsonValue::Bool(b),
+        Value::Null => JsonValue::Null,
+        Value::Array(ref a) => {
+            let mut array = Vec::with_capacity(a.len());
+            for (index, v) in a.iter().enumerate() {
+                array.push(to_json(v, &index_path(path, index))?);
+            }
+            JsonValue::Array(array)
+        }
+        Value::Table(ref t) => {
+            let mut object = Map::new();
+            for (k, v) in t {
+                object.insert(k.clone(), to_json(v, &child_path(path, k))?);
+            }
+            JsonValue::Object(object)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value as JsonValue;
+    use Value;
+
+    fn convert(json: &str) -> Value {
+        Value::from(::serde_json::from_str::<JsonValue>(json).unwrap())
+    }
+
+    #[test]
+    fn integers_beyond_i64_become_floats() {
+        assert_eq!(convert("9223372036854775807"), Value::Integer(i64::MAX));
+        assert_eq!(convert("-9223372036854775808"), Value::Integer(i64::MIN));
+        assert_eq!(convert("9223372036854775808"), Value::Float(9223372036854775808.0));
+        assert_eq!(convert("18446744073709551616"), Value::Float(18446744073709551616.0));
+        assert_eq!(convert("5.0"), Value::Float(5.0));
+    }
+
+    #[test]
+    fn null_becomes_value_null() {
+        assert_eq!(convert("null"), Value::Null);
+        assert_eq!(convert(r#"{ "a": null }"#), value!{ a: null });
+    }
+
+    #[test]
+    fn nested_objects_become_tables() {
+        let json = r#"{ "server": { "hosts": [{ "name": "a", "tls": true }], "port": 80 } }"#;
+        assert_eq!(convert(json),
+                   value!{ server: { hosts: [{ name: "a", tls: true }], port: 80 } });
+    }
+}
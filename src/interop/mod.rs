@@ -0,0 +1,62 @@
+//! Conversions between `Value` and value types of other crates.
+//!
+//! Each conversion is behind a cargo feature named after the other crate, except for
+//! `serde_json`, which is behind the `json` feature and enables `serde` too. The `serde`
+//! feature implements `Serialize` and `Deserialize` for `Value`. The `toml` and `json`
+//! features also add `Value::to_toml_string` and `Value::to_json_string`.
+
+#[cfg(feature = "config")]
+mod config;
+#[cfg(feature = "toml")]
+mod toml;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "serde")]
+mod serde;
+
+use std::error;
+use std::fmt;
+#[cfg(any(feature = "config", feature = "json", feature = "toml"))]
+use decode::push_key;
+
+/// Error converting a foreign value that has no `Value` counterpart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConvertError {
+    /// An integer does not fit into `i64`.
+    IntegerOutOfRange {
+        path: String,
+        value: String,
+    },
+}
+
+/// Error serializing a `Value` that the target format can not represent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerializeError {
+    pub message: String,
+    /// Location of the value that could not be serialized, empty for the root.
+    pub path: String,
+}
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            self.message.fmt(f)
+        } else {
+            write!(f, "at '{}': {}", self.path, self.message)
+        }
+    }
+}
+
+impl error::Error for SerializeError {}
+
+#[cfg(any(feature = "config", feature = "json", feature = "toml"))]
+fn child_path(path: &str, key: &str) -> String {
+    let mut path = path.to_string();
+    push_key(&mut path, key);
+    path
+}
+
+#[cfg(any(feature = "config", feature = "json", feature = "toml"))]
+fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
@@ -0,0 +1,165 @@
+//! Conversions to and from the `toml` crate's `Value`, and serialization to TOML text.
+//!
+//! TOML datetimes are stored as `Value::Datetime`, rendered in the TOML (ISO 8601)
+//! form, like `1979-05-27T07:32:00Z`.
+
+use std::convert::TryFrom;
+use toml::Value as TomlValue;
+use toml::value::Datetime;
+use Value;
+use super::{child_path, index_path, SerializeError};
+
+impl From<TomlValue> for Value {
+    fn from(value: TomlValue) -> Value {
+        match value {
+            TomlValue::String(s) => Value::String(s),
+            TomlValue::Integer(i) => Value::Integer(i),
+            TomlValue::Float(f) => Value::Float(f),
+            TomlValue::Boolean(b) => Value::Boolean(b),
+            TomlValue::Datetime(d) => Value::Datetime(d.to_string()),
+            TomlValue::Array(a) => Value::Array(a.into_iter().map(Value::from).collect()),
+            TomlValue::Table(t) => {
+                Value::Table(t.into_iter().map(|(k, v)| (k, Value::from(v))).collect())
+            }
+        }
+    }
+}
+
+impl TryFrom<Value> for TomlValue {
+    type Error = SerializeError;
+
+    /// Fails if a datetime string is not a valid TOML datetime.
+    fn try_from(value: Value) -> Result<TomlValue, SerializeError> {
+        to_toml(&value, "")
+    }
+}
+
+impl Value {
+    /// Serialize this value as a TOML document.
+    ///
+    /// Datetimes are written as bare TOML datetimes. Will return error if this value is
+    /// not a table, since a TOML document always is, or if a datetime string is not a
+    /// valid TOML datetime.
+    pub fn to_toml_string(&self) -> Result<String, SerializeError> {
+        match to_toml(self, "")? {
+            TomlValue::Table(t) => {
+                ::toml::to_string(&t).map_err(|e| {
+                    SerializeError {
+                        message: e.to_string(),
+                        path: String::new(),
+                    }
+                })
+            }
+            other => {
+                Err(SerializeError {
+                    message: format!("expected a table at the root, found {}", other.type_str()),
+                    path: String::new(),
+                })
+            }
+        }
+    }
+}
+
+fn to_toml(value: &Value, path: &str) -> Result<TomlValue, SerializeError> {
+    Ok(match *value {
+        Value::String(ref s) => TomlValue::String(s.clone()),
+        Value::Integer(i) => TomlValue::Integer(i),
+        Value::Float(f) => TomlValue::Float(f),
+        Value::Boolean(b) => TomlValue::Boolean(b),
+        Value::Null => {
+            return Err(SerializeError {
+                message: "null can not be represented in TOML".to_string(),
+                path: path.to_string(),
+            })
+        }
+        Value::Datetime(ref s) => {
+            TomlValue::Datetime(s.parse::<Datetime>().map_err(|e| {
+                SerializeError {
+                    message: format!("invalid datetime {:?}: {}", s, e),
+                    path: path.to_string(),
+                }
+            })?)
+        }
+        Value::Array(ref a) => {
+            let mut array = Vec::with_capacity(a.len());
+            for (index, v) in a.iter().enumerate() {
+                array.push(to_toml(v, &index_path(path, index))?);
+            }
+            TomlValue::Array(array)
+        }
+        Value::Table(ref t) => {
+            let mut table = ::toml::Table::new();
+            for (k, v) in t {
+                table.insert(k.clone(), to_toml(v, &child_path(path, k))?);
+            }
+            TomlValue::Table(table)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Value as TomlValue;
+    use decode::Path;
+    use Value;
+
+    const DOCUMENT: &str = r#"
+title = "example"
+released = 1979-05-27T07:32:00Z
+
+[server]
+port = 8080
+ratio = 0.5
+
+[[server.hosts]]
+name = "a"
+tls = true
+
+[[server.hosts]]
+name = "b"
+tls = false
+"#;
+
+    fn document() -> Value {
+        Value::from(TomlValue::Table(::toml::from_str(DOCUMENT).unwrap()))
+    }
+
+    #[test]
+    fn converted_toml_decodes_with_path() {
+        let value = document();
+        let root = Path::new(&value, "config");
+        assert_eq!(root.table_property("title", "title").unwrap().as_str().unwrap(), "example");
+        let released = root.table_property("released", "release date").unwrap();
+        assert_eq!(released.as_datetime().unwrap(), "1979-05-27T07:32:00Z");
+        let server = root.table_property("server", "server").unwrap();
+        assert_eq!(server.table_property("port", "port").unwrap().as_integer().unwrap(), 8080);
+        assert_eq!(server.table_property("ratio", "ratio").unwrap().as_float().unwrap(), 0.5);
+        let hosts = server.table_property("hosts", "hosts").unwrap();
+        let hosts = hosts.array_elements("host").unwrap();
+        assert_eq!(hosts[1].to_string(), "server.hosts[1]");
+        let tls = hosts[1].table_property("tls", "tls").unwrap();
+        assert!(!tls.as_bool().unwrap());
+        let e = tls.as_str().unwrap_err();
+        assert_eq!(e.path, "server.hosts[1].tls");
+    }
+
+    #[test]
+    fn to_toml_string_round_trips() {
+        let value = document();
+        let text = value.to_toml_string().unwrap();
+        assert_eq!(Value::from(TomlValue::Table(::toml::from_str(&text).unwrap())), value);
+    }
+
+    #[test]
+    fn to_toml_string_rejects_null() {
+        let e = value!{ server: { port: null } }.to_toml_string().unwrap_err();
+        assert_eq!(e.path, "server.port");
+        assert!(Value::Integer(1).to_toml_string().is_err());
+    }
+
+    #[test]
+    fn errors_quote_dotted_keys() {
+        let e = value!{ hosts: { "db.example.com": { port: null } } }.to_toml_string().unwrap_err();
+        assert_eq!(e.path, "hosts[\"db.example.com\"].port");
+    }
+}
@@ -0,0 +1,317 @@
+//! Resolution of `${source:name}` placeholders inside string values.
+//!
+//! Three sources are known:
+//!
+//! - `${env:HOME}` is the environment variable `HOME`;
+//! - `${file:secret.txt}` is the contents of the file, without the trailing newline;
+//! - `${ref:server.host}` is the value at another path of the same tree, itself
+//!   resolved first.
+//!
+//! A placeholder without a source, like `${HOME}` or `$HOME`, is an environment
+//! variable, and `$$` stands for a literal `$`, the same as in `Value::expand_env`.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use decode::{At, Error, Result};
+use expand::{self, ExpandError};
+use value::components_to_string;
+use {Value, PathComponent};
+
+type EnvLookup<'f> = Box<dyn Fn(&str) -> Option<String> + 'f>;
+type FileRead<'f> = Box<dyn Fn(&str) -> io::Result<String> + 'f>;
+
+/// Resolver of placeholders, with replaceable environment and file access.
+pub struct Interpolator<'f> {
+    env: EnvLookup<'f>,
+    file: FileRead<'f>,
+}
+
+impl<'f> Default for Interpolator<'f> {
+    fn default() -> Interpolator<'f> {
+        Interpolator {
+            env: Box::new(|name| env::var(name).ok()),
+            file: Box::new(|name| fs::read_to_string(name)),
+        }
+    }
+}
+
+impl<'f> Interpolator<'f> {
+    /// Resolve placeholders with the process environment and the file system, relative
+    /// file names starting in the current directory.
+    pub fn new() -> Interpolator<'f> {
+        Interpolator::default()
+    }
+
+    /// Look up environment variables with `lookup` instead.
+    pub fn env<F>(self, lookup: F) -> Interpolator<'f>
+        where F: Fn(&str) -> Option<String> + 'f
+    {
+        Interpolator { env: Box::new(lookup), ..self }
+    }
+
+    /// Read files with `read` instead.
+    pub fn file<F>(self, read: F) -> Interpolator<'f>
+        where F: Fn(&str) -> io::Result<String> + 'f
+    {
+        Interpolator { file: Box::new(read), ..self }
+    }
+
+    /// Resolve the placeholders in every string of `value`.
+    ///
+    /// A string that is a single `${ref:...}` placeholder takes the referenced value
+    /// with its type, so `port = "${ref:defaults.port}"` becomes an integer. Elsewhere,
+    /// referenced values are written as text. Referencing a table or an array is an
+    /// error. Errors are reported at the path of the string containing the
+    /// placeholder, and references that lead back to themselves are `ReferenceCycle`
+    /// error. Table keys and datetimes are left alone. Strings visited before an error
+    /// are already resolved.
+    pub fn apply(&self, value: &mut Value) -> Result<()> {
+        let resolution = Resolution {
+            interpolator: self,
+            source: value.clone(),
+            resolved: RefCell::new(BTreeMap::new()),
+            stack: RefCell::new(vec![]),
+        };
+        resolution.apply(value, &mut vec![])
+    }
+}
+
+impl Value {
+    /// Resolve `${env:...}`, `${file:...}` and `${ref:...}` placeholders in every string
+    /// of this tree, see `Interpolator::apply`.
+    pub fn interpolate(&mut self) -> Result<()> {
+        Interpolator::new().apply(self)
+    }
+}
+
+struct Resolution<'i, 'f: 'i> {
+    interpolator: &'i Interpolator<'f>,
+    /// The tree before resolution, to look up references in.
+    source: Value,
+    /// Resolved strings, by path.
+    resolved: RefCell<BTreeMap<String, Value>>,
+    /// Paths of the strings being resolved, to detect cycles.
+    stack: RefCell<Vec<String>>,
+}
+
+impl<'i, 'f> Resolution<'i, 'f> {
+    fn apply(&self, value: &mut Value, path: &mut Vec<PathComponent>) -> Result<()> {
+        match *value {
+            Value::String(ref s) => {
+                let resolved = self.resolve_string(&components_to_string(path), s)?;
+                *value = resolved;
+            }
+            Value::Array(ref mut a) => {
+                for (index, value) in a.iter_mut().enumerate() {
+                    path.push(PathComponent::Index(index));
+                    self.apply(value, path)?;
+                    path.pop();
+                }
+            }
+            Value::Table(ref mut t) => {
+                for (key, value) in t.iter_mut() {
+                    path.push(PathComponent::Key(key.clone()));
+                    self.apply(value, path)?;
+                    path.pop();
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn resolve_string(&self, path: &str, text: &str) -> Result<Value> {
+        if let Some(value) = self.resolved.borrow().get(path) {
+            return Ok(value.clone());
+        }
+        if let Some(start) = self.stack.borrow().iter().position(|p| p == path) {
+            let mut paths = self.stack.borrow()[start..].to_vec();
+            paths.push(path.to_string());
+            return Err(Error::ReferenceCycle { paths }.at(path.to_string()));
+        }
+        self.stack.borrow_mut().push(path.to_string());
+        let result = self.expand(path, text);
+        self.stack.borrow_mut().pop();
+        let value = result?;
+        self.resolved.borrow_mut().insert(path.to_string(), value.clone());
+        Ok(value)
+    }
+
+    fn expand(&self, path: &str, text: &str) -> Result<Value> {
+        let whole_reference = text.strip_prefix("${ref:")
+            .and_then(|rest| rest.strip_suffix('}'))
+            .filter(|target| !target.contains('}'));
+        if let Some(target) = whole_reference {
+            return self.reference(path, target);
+        }
+        let error = RefCell::new(None);
+        let lookup = |name: &str| match self.lookup(path, name) {
+            Ok(value) => value,
+            Err(e) => {
+                *error.borrow_mut() = Some(e);
+                None
+            }
+        };
+        match expand::expand(text, lookup, false) {
+            Ok(expanded) => Ok(Value::String(expanded)),
+            Err(_) if error.borrow().is_some() => Err(error.into_inner().unwrap()),
+            Err(ExpandError::Undefined(name)) => {
+                Err(Error::UndefinedVariable { name }.at(path.to_string()))
+            }
+            Err(ExpandError::Unterminated) => {
+                Err(Error::IncorrectValue {
+                        explanation: Some("\"${\" without the closing \"}\"".to_string()),
+                        value: Value::String(text.to_string()),
+                        possible_list: vec![],
+                    }
+                    .at(path.to_string()))
+            }
+        }
+    }
+
+    /// Return the text of the placeholder `name`, `None` if it is not defined.
+    fn lookup(&self, path: &str, name: &str) -> Result<Option<String>> {
+        let (source, name) = match name.split_once(':') {
+            Some(split) => split,
+            None => ("env", name),
+        };
+        match source {
+            "env" => Ok((self.interpolator.env)(name)),
+            "file" => {
+                match (self.interpolator.file)(name) {
+                    Ok(mut contents) => {
+                        if contents.ends_with('\n') {
+                            contents.pop();
+                            if contents.ends_with('\r') {
+                                contents.pop();
+                            }
+                        }
+                        Ok(Some(contents))
+                    }
+                    Err(e) => {
+                        Err(self.incorrect(path, format!("can not read '{}': {}", name, e)))
+                    }
+                }
+            }
+            "ref" => {
+                Ok(Some(match self.reference(path, name)? {
+                    Value::String(s) | Value::Datetime(s) => s,
+                    other => other.to_pretty_string(),
+                }))
+            }
+            _ => Err(self.incorrect(path, format!("unknown placeholder source '{}'", source))),
+        }
+    }
+
+    /// Return the resolved scalar at `target`, referenced from the string at `path`.
+    fn reference(&self, path: &str, target: &str) -> Result<Value> {
+        match self.source.get_path(target) {
+            Some(Value::String(s)) => self.resolve_string(target, s),
+            Some(&Value::Table(..)) => {
+                Err(self.incorrect(path, format!("'{}' is a table, not a scalar", target)))
+            }
+            Some(&Value::Array(..)) => {
+                Err(self.incorrect(path, format!("'{}' is an array, not a scalar", target)))
+            }
+            Some(other) => Ok(other.clone()),
+            None => {
+                Err(Error::UndefinedVariable { name: format!("ref:{}", target) }
+                    .at(path.to_string()))
+            }
+        }
+    }
+
+    fn incorrect(&self, path: &str, explanation: String) -> At<Error> {
+        let value = self.source.get_path(path).cloned().unwrap_or(Value::String(String::new()));
+        Error::IncorrectValue {
+                explanation: Some(explanation),
+                value,
+                possible_list: vec![],
+            }
+            .at(path.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use Value;
+    use decode::Error;
+    use super::Interpolator;
+
+    fn interpolator<'f>() -> Interpolator<'f> {
+        Interpolator::new()
+            .env(|name| if name == "HOME" { Some("/home/me".to_string()) } else { None })
+            .file(|name| match name {
+                "key.pem" => Ok("secret\r\n".to_string()),
+                "two.txt" => Ok("one\n\n".to_string()),
+                _ => Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
+            })
+    }
+
+    #[test]
+    fn resolves_env_and_file_placeholders() {
+        let mut value = value!{
+            home: "${env:HOME}/x",
+            plain: "$HOME and ${HOME} cost $$5",
+            key: "${file:key.pem}",
+            two: "[${file:two.txt}]",
+            list: ["${HOME}", 1],
+        };
+        interpolator().apply(&mut value).unwrap();
+        assert_eq!(value,
+                   value!{
+                       home: "/home/me/x",
+                       plain: "/home/me and /home/me cost $5",
+                       key: "secret",
+                       two: "[one\n]",
+                       list: ["/home/me", 1],
+                   });
+    }
+
+    #[test]
+    fn whole_string_references_keep_their_type() {
+        let mut value = value!{
+            defaults: { port: 80, host: "${ref:defaults.name}.local", name: "db" },
+            port: "${ref:defaults.port}",
+            url: "${ref:host}:${ref:defaults.port}",
+            host: "${ref:defaults.host}",
+        };
+        interpolator().apply(&mut value).unwrap();
+        assert_eq!(value["port"], Value::Integer(80));
+        assert_eq!(value["host"], Value::from("db.local"));
+        assert_eq!(value["url"], Value::from("db.local:80"));
+    }
+
+    #[test]
+    fn reports_reference_cycles() {
+        let mut value = value!{ a: "${ref:b}", b: "x${ref:c}", c: "${ref:a}" };
+        let e = interpolator().apply(&mut value).unwrap_err();
+        assert_eq!(e.path, "a");
+        match e.error {
+            Error::ReferenceCycle { ref paths } => assert_eq!(paths, &["a", "b", "c", "a"]),
+            ref other => panic!("unexpected error {:?}", other),
+        }
+        let mut value = value!{ a: "${ref:a}" };
+        assert!(interpolator().apply(&mut value).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_sources_and_missing_values() {
+        let error = |value: Value| {
+            let mut value = value;
+            interpolator().apply(&mut value).unwrap_err().to_string()
+        };
+        assert_eq!(error(value!{ a: "${vault:x}" }),
+                   "at 'a': unknown placeholder source 'vault' (found \"${vault:x}\")");
+        assert_eq!(error(value!{ a: "${file:nope}" }),
+                   "at 'a': can not read 'nope': not found (found \"${file:nope}\")");
+        assert_eq!(error(value!{ a: "${ref:t}", t: {} }),
+                   "at 'a': 't' is a table, not a scalar (found \"${ref:t}\")");
+        assert_eq!(error(value!{ a: "${MISSING}" }), "at 'a': undefined variable 'MISSING'");
+        assert_eq!(error(value!{ a: "${ref:nope}" }), "at 'a': undefined variable 'ref:nope'");
+    }
+}
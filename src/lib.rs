@@ -1,4 +1,54 @@
+#[cfg(feature = "config")]
+extern crate config;
+#[cfg(feature = "toml")]
+extern crate toml;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(feature = "unicode-normalization")]
+extern crate unicode_normalization;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "yaml")]
+extern crate yaml_rust2;
+#[cfg(feature = "derive")]
+extern crate config_model_derive;
+#[cfg(feature = "indexmap")]
+extern crate indexmap;
+
+#[macro_use]
+mod macros;
+
 pub mod decode;
+pub mod schema;
+pub mod rules;
+pub mod migrate;
+pub mod encode;
+pub mod env;
+pub mod args;
+pub mod include;
+pub mod source;
+pub mod watch;
+pub mod interpolate;
+pub mod report;
+pub mod interop;
+pub mod format;
+#[cfg(feature = "chrono")]
+pub mod datetime;
+#[cfg(feature = "serde")]
+pub mod serde_de;
 mod value;
+mod shared;
+mod expand;
+mod units;
 
-pub use value::{Value,Array,Table};
+pub use value::{Value,Array,Table,TypeMismatch,MergeStrategy,PathComponent,SortError,SetPathError,
+                Change,PrettyConfig,ProfileError,Walk,Visitor,VisitorMut};
+#[cfg(feature = "indexmap")]
+pub use value::OrderedTable;
+pub use shared::{SharedValue,SharedArray,SharedTable};
+#[cfg(feature = "derive")]
+pub use config_model_derive::DecodeConfig;
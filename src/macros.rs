@@ -0,0 +1,149 @@
+/// Asserts that two `Value`s are equal, printing the changes from `Value::diff` on failure.
+///
+/// An optional `epsilon = ...` argument allows floats to differ by at most that much,
+/// like `Value::approx_eq`.
+///
+/// ```
+/// # #[macro_use] extern crate config_model;
+/// # use config_model::Value;
+/// # fn main() {
+/// assert_value_eq!(Value::Float(0.1 + 0.2), Value::Float(0.3), epsilon = 1e-9);
+/// # }
+/// ```
+///
+/// It can also be called by path, without importing it:
+///
+/// ```
+/// use config_model::Value;
+/// config_model::assert_value_eq!(Value::from(1), Value::Integer(1));
+/// ```
+#[macro_export]
+macro_rules! assert_value_eq {
+    ($left:expr, $right:expr) => {
+        $crate::assert_value_eq!(@check $left, $right, None)
+    };
+    ($left:expr, $right:expr, epsilon = $epsilon:expr) => {
+        $crate::assert_value_eq!(@check $left, $right, Some($epsilon))
+    };
+    (@check $left:expr, $right:expr, $epsilon:expr) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                let epsilon: Option<f64> = $epsilon;
+                let changes: Vec<String> = $crate::Value::diff(left, right)
+                    .into_iter()
+                    .filter(|change| match (epsilon, change) {
+                        (Some(epsilon), &$crate::Change::Changed { ref old, ref new, .. }) => {
+                            !old.approx_eq(new, epsilon)
+                        }
+                        _ => true,
+                    })
+                    .map(|change| change.to_string())
+                    .collect();
+                if !changes.is_empty() {
+                    panic!("assertion failed: values differ at {} path(s):\n  {}",
+                           changes.len(),
+                           changes.join("\n  "));
+                }
+            }
+        }
+    };
+}
+
+/// Builds a `Value` from a JSON-like literal, for fixtures and defaults.
+///
+/// Tables are written in braces with `key: value` entries, keys being identifiers or
+/// string literals, and arrays in brackets. `null` is `Value::Null`, and any other
+/// expression is converted with `Value::from`. The outer braces of a table may be left
+/// out.
+///
+/// ```
+/// # #[macro_use] extern crate config_model;
+/// # use config_model::Value;
+/// # fn main() {
+/// let port = 8080;
+/// let value = value!{
+///     server: { port: port, hosts: ["a", "b"] },
+///     "log-level": "info",
+///     proxy: null,
+/// };
+/// assert_eq!(value["server"]["port"], Value::Integer(8080));
+/// assert_eq!(value["server"]["hosts"][1], Value::from("b"));
+/// assert!(value["proxy"].is_null());
+/// # }
+/// ```
+///
+/// The macro can also be called by path, without importing it:
+///
+/// ```
+/// let value = config_model::value!{ a: { b: [1, null] } };
+/// assert_eq!(value["a"]["b"][0], config_model::Value::Integer(1));
+/// ```
+#[macro_export]
+macro_rules! value {
+    () => {
+        $crate::Value::new_table()
+    };
+    (null) => {
+        $crate::Value::Null
+    };
+    ([ $($elements:tt)* ]) => {
+        $crate::Value::Array($crate::value!(@array [] $($elements)*))
+    };
+    ({}) => {
+        $crate::Value::new_table()
+    };
+    ({ $($entries:tt)* }) => {
+        {
+            let mut table = $crate::Table::new();
+            $crate::value!(@table table $($entries)*);
+            $crate::Value::Table(table)
+        }
+    };
+    ($key:tt : $($rest:tt)*) => {
+        $crate::value!({ $key : $($rest)* })
+    };
+    ($other:expr) => {
+        $crate::Value::from($other)
+    };
+
+    (@array [$($done:expr,)*]) => {
+        vec![$($done,)*]
+    };
+    (@array [$($done:expr,)*] null $(, $($rest:tt)*)?) => {
+        $crate::value!(@array [$($done,)* $crate::value!(null),] $($($rest)*)?)
+    };
+    (@array [$($done:expr,)*] [ $($array:tt)* ] $(, $($rest:tt)*)?) => {
+        $crate::value!(@array [$($done,)* $crate::value!([ $($array)* ]),] $($($rest)*)?)
+    };
+    (@array [$($done:expr,)*] { $($table:tt)* } $(, $($rest:tt)*)?) => {
+        $crate::value!(@array [$($done,)* $crate::value!({ $($table)* }),] $($($rest)*)?)
+    };
+    (@array [$($done:expr,)*] $element:expr $(, $($rest:tt)*)?) => {
+        $crate::value!(@array [$($done,)* $crate::value!($element),] $($($rest)*)?)
+    };
+
+    (@table $table:ident) => {};
+    (@table $table:ident $key:tt : null $(, $($rest:tt)*)?) => {
+        $table.insert($crate::value!(@key $key), $crate::value!(null));
+        $crate::value!(@table $table $($($rest)*)?);
+    };
+    (@table $table:ident $key:tt : [ $($array:tt)* ] $(, $($rest:tt)*)?) => {
+        $table.insert($crate::value!(@key $key), $crate::value!([ $($array)* ]));
+        $crate::value!(@table $table $($($rest)*)?);
+    };
+    (@table $table:ident $key:tt : { $($inner:tt)* } $(, $($rest:tt)*)?) => {
+        $table.insert($crate::value!(@key $key), $crate::value!({ $($inner)* }));
+        $crate::value!(@table $table $($($rest)*)?);
+    };
+    (@table $table:ident $key:tt : $value:expr $(, $($rest:tt)*)?) => {
+        $table.insert($crate::value!(@key $key), $crate::value!($value));
+        $crate::value!(@table $table $($($rest)*)?);
+    };
+
+    (@key $key:ident) => {
+        stringify!($key).to_string()
+    };
+    (@key $key:expr) => {
+        ::std::string::String::from($key)
+    };
+}
@@ -0,0 +1,262 @@
+//! Upgrading configuration values written for older versions of the config format.
+//!
+//! The version of a config is kept as an integer in the `config_version` field of the
+//! root table. Versions are sequential, and each `Migration` upgrades the value by
+//! exactly one version: `migrations[n]` takes the value from version `n` to `n + 1`.
+//!
+//! A migration is any `Fn(&mut Value)`. The common steps are built by `rename_key`,
+//! `move_to_table` and `map_value`, which take dotted paths like `server.port`:
+//!
+//! ```
+//! # use config_model::encode::TableBuilder;
+//! # use config_model::migrate::{self, Migration};
+//! # use config_model::Value;
+//! let mut value = TableBuilder::new().property("max_conns", &10).build();
+//! let to_v1 = migrate::rename_key("max_conns", "max_connections");
+//! let to_v2 = migrate::move_to_table("max_connections", "db");
+//! let migrations: [&dyn Migration; 2] = [&to_v1, &to_v2];
+//! assert_eq!(migrate::migrate_to_latest(&mut value, &migrations), Ok(2));
+//! assert_eq!(value.get_path("db.max_connections"), Some(&Value::Integer(10)));
+//! ```
+
+use std::{cmp, error, fmt};
+use Value;
+use value::{components_to_string, parse_dotted_path};
+
+/// Name of the root table field holding the config version.
+pub const VERSION_FIELD: &str = "config_version";
+
+/// A single upgrade step from one config version to the next.
+pub trait Migration {
+    /// Modify the value in place so it matches the next version.
+    fn apply(&self, value: &mut Value);
+}
+
+impl<F> Migration for F
+    where F: Fn(&mut Value)
+{
+    fn apply(&self, value: &mut Value) {
+        self(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Migrations only go forward.
+    Downgrade {
+        from: u32,
+        to: u32,
+    },
+    /// There is no migration for upgrading from this version.
+    MissingMigration {
+        version: u32,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Downgrade { from, to } => {
+                write!(f, "cannot downgrade config from version {} to {}", from, to)
+            }
+            Error::MissingMigration { version } => {
+                write!(f, "no migration from config version {}", version)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// Read the config version from the `config_version` field of the root table.
+///
+/// Returns `None` if the field is missing or is not a non-negative integer.
+pub fn version(value: &Value) -> Option<u32> {
+    value.as_table()
+        .and_then(|t| t.get(VERSION_FIELD))
+        .and_then(|v| v.as_integer())
+        .and_then(|v| if v >= 0 && v <= u32::MAX as i64 {
+            Some(v as u32)
+        } else {
+            None
+        })
+}
+
+/// Upgrade the value from its `config_version` to the latest version, `migrations.len()`.
+///
+/// A value without the version field is taken to be at version 0. Returns the version of
+/// the upgraded value.
+pub fn migrate_to_latest(value: &mut Value, migrations: &[&dyn Migration]) -> Result<u32, Error> {
+    let latest = migrations.len() as u32;
+    migrate(value, version(value).unwrap_or(0), latest, migrations)?;
+    Ok(latest)
+}
+
+/// Apply migrations in sequence to upgrade the value from version `from` to `to`.
+///
+/// On success, the `config_version` field of the root table (if the value is a table)
+/// is set to `to`. Nothing is modified if a required migration is missing.
+pub fn migrate(value: &mut Value,
+               from: u32,
+               to: u32,
+               migrations: &[&dyn Migration])
+               -> Result<(), Error> {
+    if from > to {
+        return Err(Error::Downgrade { from, to });
+    }
+    if to as usize > migrations.len() {
+        return Err(Error::MissingMigration { version: cmp::max(from, migrations.len() as u32) });
+    }
+    for migration in &migrations[from as usize..to as usize] {
+        migration.apply(value);
+    }
+    if let Value::Table(ref mut t) = *value {
+        t.insert(VERSION_FIELD.to_string(), Value::Integer(to as i64));
+    }
+    Ok(())
+}
+
+/// Migration moving the value at the dotted path `from` to `to`, creating missing tables.
+///
+/// `to` may be inside `from`, like `db` to `db.inner`, which nests the value one level
+/// deeper. Nothing is changed if `from` is missing, or if `to` is already taken or can
+/// not be created.
+pub fn rename_key(from: &str, to: &str) -> impl Fn(&mut Value) {
+    let (from, to) = (from.to_string(), to.to_string());
+    let nested = nested_path(&from, &to);
+    move |value: &mut Value| {
+        if value.get_path(&to).is_some() {
+            return;
+        }
+        if let Some(ref rest) = nested {
+            if let Some(target) = value.get_path_mut(&from) {
+                let mut table = Value::new_table();
+                if table.set_path(rest, target.clone()).is_ok() {
+                    *target = table;
+                }
+            }
+        } else if let Some(moved) = value.get_path(&from).cloned() {
+            if value.set_path(&to, moved).is_ok() {
+                value.remove_path(&from);
+            }
+        }
+    }
+}
+
+/// If the dotted path `to` is inside `from`, return the part of `to` after `from`.
+fn nested_path(from: &str, to: &str) -> Option<String> {
+    let (from, to) = (parse_dotted_path(from)?, parse_dotted_path(to)?);
+    if to.len() > from.len() && to.starts_with(&from) {
+        Some(components_to_string(&to[from.len()..]))
+    } else {
+        None
+    }
+}
+
+/// Migration moving the value at the dotted path `key` into the table at `table`, under
+/// the last component of `key`, like `port` into `server.port`.
+pub fn move_to_table(key: &str, table: &str) -> impl Fn(&mut Value) {
+    let name = match key.rfind('.') {
+        Some(dot) => &key[dot + 1..],
+        None => key,
+    };
+    rename_key(key, &format!("{}.{}", table, name))
+}
+
+/// Migration replacing the value at the dotted path `path` with `f` of it, if present.
+pub fn map_value<F>(path: &str, f: F) -> impl Fn(&mut Value)
+    where F: Fn(&Value) -> Value
+{
+    let path = path.to_string();
+    move |value: &mut Value| {
+        if let Some(target) = value.get_path_mut(&path) {
+            *target = f(target);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Value;
+    use super::{map_value, migrate, migrate_to_latest, move_to_table, rename_key, version,
+                Error, Migration, VERSION_FIELD};
+
+    #[test]
+    fn migrate_to_latest_applies_missing_steps() {
+        let add_a = |value: &mut Value| {
+            value.set_path("a", Value::Integer(1)).unwrap();
+        };
+        let add_b = |value: &mut Value| {
+            value.set_path("b", Value::Integer(2)).unwrap();
+        };
+        let migrations: [&dyn Migration; 2] = [&add_a, &add_b];
+        let mut value = value!{};
+        assert_eq!(migrate_to_latest(&mut value, &migrations), Ok(2));
+        assert_eq!(value, value!{ a: 1, b: 2, config_version: 2 });
+        let mut value = value!{ config_version: 1 };
+        assert_eq!(migrate_to_latest(&mut value, &migrations), Ok(2));
+        assert_eq!(value, value!{ b: 2, config_version: 2 });
+        assert_eq!(version(&value), Some(2));
+        assert_eq!(version(&value!{ config_version: -1 }), None);
+    }
+
+    #[test]
+    fn migrate_refuses_downgrades_and_missing_migrations() {
+        let noop = |_: &mut Value| {};
+        let migrations: [&dyn Migration; 1] = [&noop];
+        let mut value = value!{ config_version: 1 };
+        assert_eq!(migrate(&mut value, 1, 0, &migrations),
+                   Err(Error::Downgrade { from: 1, to: 0 }));
+        assert_eq!(migrate(&mut value, 0, 3, &migrations),
+                   Err(Error::MissingMigration { version: 1 }));
+        assert_eq!(migrate_to_latest(&mut value!{ config_version: 2 }, &migrations),
+                   Err(Error::Downgrade { from: 2, to: 1 }));
+        assert_eq!(value, value!{ config_version: 1 });
+        assert_eq!(Error::MissingMigration { version: 1 }.to_string(),
+                   "no migration from config version 1");
+        assert_eq!(migrate(&mut value, 1, 1, &migrations), Ok(()));
+        assert_eq!(value[VERSION_FIELD], Value::Integer(1));
+    }
+
+    #[test]
+    fn rename_key_moves_values() {
+        let mut value = value!{ a: { b: 1 }, c: 2 };
+        rename_key("a.b", "x.y")(&mut value);
+        assert_eq!(value, value!{ a: {}, x: { y: 1 }, c: 2 });
+        rename_key("missing", "d")(&mut value);
+        rename_key("c", "x")(&mut value);
+        rename_key("x", "c.d")(&mut value);
+        assert_eq!(value, value!{ a: {}, x: { y: 1 }, c: 2 });
+    }
+
+    #[test]
+    fn rename_key_nests_values_under_their_own_key() {
+        let mut value = value!{ db: { host: "a" }, list: [1, 2] };
+        rename_key("db", "db.inner")(&mut value);
+        assert_eq!(value["db"], value!{ inner: { host: "a" } });
+        rename_key("list[0]", "list[0].x")(&mut value);
+        assert_eq!(value["list"], value!([{ x: 1 }, 2]));
+        rename_key("list", "list[0]")(&mut value);
+        assert_eq!(value["list"], value!([{ x: 1 }, 2]));
+    }
+
+    #[test]
+    fn move_to_table_keeps_the_key_name() {
+        let mut value = value!{ server: { port: 80 }, port: 81 };
+        move_to_table("server.port", "http")(&mut value);
+        assert_eq!(value, value!{ server: {}, http: { port: 80 }, port: 81 });
+        move_to_table("port", "http")(&mut value);
+        assert_eq!(value["port"], Value::Integer(81));
+        move_to_table("server", "server")(&mut value);
+        assert_eq!(value["server"], value!{ server: {} });
+    }
+
+    #[test]
+    fn map_value_replaces_present_values() {
+        let mut value = value!{ timeout: 5 };
+        let to_ms = map_value("timeout", |v| Value::Integer(v.as_integer().unwrap() * 1000));
+        to_ms(&mut value);
+        map_value("missing", |_| Value::Integer(0))(&mut value);
+        assert_eq!(value, value!{ timeout: 5000 });
+    }
+}
@@ -0,0 +1,261 @@
+//! Rendering decode errors as multi-line diagnostics for the config author.
+//!
+//! `Display` of `At<Error>` gives a single line, good for logs. Command line tools
+//! usually want more: what was expected, what was found, and how to fix it.
+//! `Report` renders such a block for every error:
+//!
+//! ```text
+//! error[E_EXPECTED_INTEGER]: expected an integer for port
+//!   at: server.port (config.toml:2:8)
+//!   expected: integer
+//!   found: "8080"
+//!   hint: write the number without quotes, or enable lenient parsing
+//! ```
+
+use decode::{At, Error};
+use Value;
+
+/// Maximum number of characters of the found value shown in a report.
+const SNIPPET_LEN: usize = 60;
+
+const RED: &str = "\x1b[1;31m";
+const BOLD: &str = "\x1b[1m";
+const CYAN: &str = "\x1b[36m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Renderer of decode errors, configured with the builder methods.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Report<'v> {
+    value: Option<&'v Value>,
+    profile: Option<&'v str>,
+    colors: bool,
+}
+
+impl<'v> Report<'v> {
+    /// Plain text renderer, without the found value of errors that do not carry it.
+    pub fn new() -> Report<'v> {
+        Report::default()
+    }
+
+    /// Look up the found value of every error in `value`, the decoded config.
+    ///
+    /// Errors like `ExpectedInteger` do not carry the value, so without this the
+    /// `found` line is only shown for `IncorrectValue` and `ExpectedOneOfTypes`.
+    pub fn value(self, value: &'v Value) -> Report<'v> {
+        Report { value: Some(value), ..self }
+    }
+
+    /// Mention the active `profile` in every error, see `Value::apply_profile`.
+    pub fn profile(self, profile: &'v str) -> Report<'v> {
+        Report { profile: Some(profile), ..self }
+    }
+
+    /// Highlight the output with ANSI escape codes, for terminals.
+    pub fn colors(self, colors: bool) -> Report<'v> {
+        Report { colors, ..self }
+    }
+
+    /// Render a single error, ending with a newline.
+    pub fn render(&self, error: &At<Error>) -> String {
+        let mut out = format!("{}: {}\n",
+                              self.paint(RED, &format!("error[{}]", error.error.code())),
+                              self.paint(BOLD, &error.error.to_string()));
+        match (error.path.is_empty(), error.location.as_ref()) {
+            (false, Some(location)) => {
+                self.line(&mut out, "at", &format!("{} ({})", error.path, location))
+            }
+            (false, None) => self.line(&mut out, "at", &error.path),
+            (true, Some(location)) => self.line(&mut out, "at", &location.to_string()),
+            (true, None) => (),
+        }
+        if let Some(profile) = self.profile {
+            self.line(&mut out, "profile", profile);
+        }
+        if let Some(expected) = expected(&error.error) {
+            self.line(&mut out, "expected", &expected);
+        }
+        if let Some(found) = self.found(error) {
+            self.line(&mut out, "found", &found);
+        }
+        if let Some(hint) = error.error.hint() {
+            let hint = self.paint(GREEN, &hint);
+            self.line(&mut out, "hint", &hint);
+        }
+        out
+    }
+
+    /// Render all errors, separated by empty lines, followed by a summary line.
+    pub fn render_all(&self, errors: &[At<Error>]) -> String {
+        let mut out = errors.iter().map(|e| self.render(e)).collect::<Vec<_>>().join("\n");
+        if errors.len() > 1 {
+            out.push_str(&format!("\n{}\n",
+                                  self.paint(RED,
+                                             &format!("{} errors in the config", errors.len()))));
+        }
+        out
+    }
+
+    fn found(&self, error: &At<Error>) -> Option<String> {
+        match error.error {
+            Error::IncorrectValue { ref value, .. } => Some(snippet(value)),
+            Error::ExpectedOneOfTypes { ref found_type, .. } => Some(found_type.clone()),
+            Error::UnexpectedNull { .. } => Some("null".to_string()),
+            Error::ExpectedProperty(..) |
+            Error::ExpectedProperties(..) |
+            Error::ExpectedOneOfProperties(..) => None,
+            _ => self.value.and_then(|v| v.get_path(&error.path)).map(snippet),
+        }
+    }
+
+    fn line(&self, out: &mut String, label: &str, text: &str) {
+        out.push_str(&format!("  {}: {}\n", self.paint(CYAN, label), text));
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.colors {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Return the expected type or values for errors that have them.
+fn expected(error: &Error) -> Option<String> {
+    Some(match *error {
+        Error::ExpectedTable { .. } => "table".to_string(),
+        Error::ExpectedString { .. } => "string".to_string(),
+        Error::ExpectedInteger { .. } => "integer".to_string(),
+        Error::ExpectedFloat { .. } => "float".to_string(),
+        Error::ExpectedBool { .. } => "boolean".to_string(),
+        Error::ExpectedDatetime { .. } => "datetime".to_string(),
+        Error::ExpectedSlice { .. } => "array".to_string(),
+        Error::ExpectedOneOfTypes { ref possible_list, .. } => possible_list.join(" or "),
+        Error::IncorrectValue { ref possible_list, .. } if !possible_list.is_empty() => {
+            possible_list.iter().map(|v| v.to_pretty_string()).collect::<Vec<_>>().join(", ")
+        }
+        _ => return None,
+    })
+}
+
+/// Single-line text of the value, cut to `SNIPPET_LEN` characters.
+fn snippet(value: &Value) -> String {
+    let text = value.to_pretty_string().split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= SNIPPET_LEN {
+        return text;
+    }
+    let mut cut: String = text.chars().take(SNIPPET_LEN).collect();
+    cut.push_str("...");
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use decode::{At, Error, Location, Path};
+    use Value;
+    use super::Report;
+
+    fn port_error(value: &Value) -> At<Error> {
+        let root = Path::new(value, "config");
+        let server = root.table_property("server", "server").unwrap();
+        server.table_property("port", "port").unwrap().as_integer().unwrap_err()
+    }
+
+    fn mode_error(value: &Value) -> At<Error> {
+        let root = Path::new(value, "config");
+        root.table_property("mode", "mode").unwrap().one_of_str(&["a", "b"]).unwrap_err()
+    }
+
+    fn missing_error(value: &Value) -> At<Error> {
+        Path::new(value, "config").table_property("missing", "missing thing").unwrap_err()
+    }
+
+    fn located(mut error: At<Error>) -> At<Error> {
+        error.location = Some(Box::new(Location {
+            file: "config.toml".to_string(),
+            line: 2,
+            column: 8,
+        }));
+        error
+    }
+
+    #[test]
+    fn render_shows_found_values_from_the_config() {
+        let value = value!{ server: { port: "8080" } };
+        let error = port_error(&value);
+        assert_eq!(Report::new().render(&error),
+                   "error[E_EXPECTED_INTEGER]: expected an integer for port\n\
+                    \x20 at: server.port\n\
+                    \x20 expected: integer\n\
+                    \x20 hint: write the number without quotes, or enable lenient parsing\n");
+        assert_eq!(Report::new().value(&value).profile("prod").render(&located(error)),
+                   "error[E_EXPECTED_INTEGER]: expected an integer for port\n\
+                    \x20 at: server.port (config.toml:2:8)\n\
+                    \x20 profile: prod\n\
+                    \x20 expected: integer\n\
+                    \x20 found: \"8080\"\n\
+                    \x20 hint: write the number without quotes, or enable lenient parsing\n");
+    }
+
+    #[test]
+    fn render_handles_root_errors_and_long_values() {
+        let error = Error::ExpectedTable { desc: "config".to_string() }.at(String::new());
+        assert_eq!(Report::new().render(&error),
+                   "error[E_EXPECTED_TABLE]: expected a table for config\n  expected: table\n");
+        assert_eq!(Report::new().render(&located(error)),
+                   "error[E_EXPECTED_TABLE]: expected a table for config\n\
+                    \x20 at: config.toml:2:8\n\
+                    \x20 expected: table\n");
+        let value = value!{ s: "0123456789".repeat(7) };
+        let error = Error::ExpectedTable { desc: "s".to_string() }.at("s".to_string());
+        assert_eq!(Report::new().value(&value).render(&error),
+                   "error[E_EXPECTED_TABLE]: expected a table for s\n\
+                    \x20 at: s\n\
+                    \x20 expected: table\n\
+                    \x20 found: \"0123456789012345678901234567890123456789\
+                    0123456789012345678...\n");
+    }
+
+    #[test]
+    fn render_all_separates_errors_and_counts_them() {
+        let value = value!{ mode: "fast" };
+        let errors = [mode_error(&value), missing_error(&value)];
+        assert_eq!(Report::new().render_all(&errors),
+                   "error[E_INCORRECT_VALUE]: unknown mode (found \"fast\"), expected one of \
+                    \"a\", \"b\"\n\
+                    \x20 at: mode\n\
+                    \x20 expected: \"a\", \"b\"\n\
+                    \x20 found: \"fast\"\n\
+                    \x20 hint: use one of \"a\", \"b\"\n\
+                    \n\
+                    error[E_EXPECTED_PROPERTY]: missing property 'missing' (missing thing)\n\
+                    \x20 at: missing\n\
+                    \x20 hint: add `missing` (missing thing)\n\
+                    \n\
+                    2 errors in the config\n");
+        assert_eq!(Report::new().render_all(&errors[1..]), Report::new().render(&errors[1]));
+        assert_eq!(Report::new().render_all(&[]), "");
+    }
+
+    #[test]
+    fn colors_highlight_labels_and_messages() {
+        let value = value!{ server: { port: "8080" } };
+        let errors = [port_error(&value), missing_error(&value)];
+        assert_eq!(Report::new().colors(true).value(&value).render_all(&errors),
+                   "\x1b[1;31merror[E_EXPECTED_INTEGER]\x1b[0m: \
+                    \x1b[1mexpected an integer for port\x1b[0m\n\
+                    \x20 \x1b[36mat\x1b[0m: server.port\n\
+                    \x20 \x1b[36mexpected\x1b[0m: integer\n\
+                    \x20 \x1b[36mfound\x1b[0m: \"8080\"\n\
+                    \x20 \x1b[36mhint\x1b[0m: \x1b[32mwrite the number without quotes, or \
+                    enable lenient parsing\x1b[0m\n\
+                    \n\
+                    \x1b[1;31merror[E_EXPECTED_PROPERTY]\x1b[0m: \
+                    \x1b[1mmissing property 'missing' (missing thing)\x1b[0m\n\
+                    \x20 \x1b[36mat\x1b[0m: missing\n\
+                    \x20 \x1b[36mhint\x1b[0m: \x1b[32madd `missing` (missing thing)\x1b[0m\n\
+                    \n\
+                    \x1b[1;31m2 errors in the config\x1b[0m\n");
+    }
+}
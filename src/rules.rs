@@ -0,0 +1,267 @@
+//! Checks that relate several values of the configuration.
+//!
+//! Getters of `decode::Path` check one value at a time. `Rules` are checked on the whole
+//! config after it was decoded, for constraints like "`tls.cert` is required if
+//! `tls.enabled` is true", or "`min` must not be greater than `max`":
+//!
+//! ```
+//! # #[macro_use] extern crate config_model;
+//! # use config_model::rules::Rules;
+//! # use config_model::Value;
+//! # fn main() {
+//! let rules = Rules::new()
+//!     .require_if("tls.enabled", Value::Boolean(true), &["tls.cert", "tls.key"])
+//!     .less_or_equal("pool.min", "pool.max");
+//! let value = value!{ tls: { enabled: true, cert: "a.pem" }, pool: { min: 8, max: 4 } };
+//! let errors = rules.check(&value).unwrap_err();
+//! assert_eq!(errors.0[0].path, "tls.key");
+//! assert_eq!(errors.0[1].path, "pool.min");
+//! # }
+//! ```
+//!
+//! Values that are missing or of an unexpected type are left to the decoder: a rule only
+//! reports what it is about.
+
+use decode::{At, Error, Errors, Path, Property};
+use value::parse_dotted_path;
+use {Value, PathComponent};
+
+/// Root description of the path passed to custom rules.
+const ROOT_DESC: &str = "config";
+
+type Rule<'r> = Box<dyn Fn(&Value, &mut Vec<At<Error>>) + 'r>;
+
+/// List of rules, configured with the builder methods.
+#[derive(Default)]
+pub struct Rules<'r> {
+    rules: Vec<Rule<'r>>,
+}
+
+impl<'r> Rules<'r> {
+    pub fn new() -> Rules<'r> {
+        Rules::default()
+    }
+
+    /// Require the `required` paths if the value at `path` equals `value`.
+    ///
+    /// Each missing path is an `ExpectedProperty` error at that path.
+    pub fn require_if(self, path: &str, value: Value, required: &[&str]) -> Rules<'r> {
+        let path = path.to_string();
+        let required = to_strings(required);
+        self.add(move |root, errors| {
+            if root.get_path(&path) == Some(&value) {
+                let desc = format!("required when {} is {}", path, value.to_pretty_string());
+                require(root, &required, &desc, errors);
+            }
+        })
+    }
+
+    /// Require the `required` paths if there is a value at `path`.
+    pub fn require_with(self, path: &str, required: &[&str]) -> Rules<'r> {
+        let path = path.to_string();
+        let required = to_strings(required);
+        self.add(move |root, errors| {
+            if is_present(root, &path) {
+                let desc = format!("required with {}", path);
+                require(root, &required, &desc, errors);
+            }
+        })
+    }
+
+    /// Allow at most one of `paths` to have a value.
+    ///
+    /// The error is `ConflictingProperties` at the first path present.
+    pub fn mutually_exclusive(self, paths: &[&str]) -> Rules<'r> {
+        let paths = to_strings(paths);
+        self.add(move |root, errors| {
+            let present: Vec<String> =
+                paths.iter().filter(|p| is_present(root, p)).cloned().collect();
+            if present.len() > 1 {
+                let at = present[0].clone();
+                errors.push(Error::ConflictingProperties { paths: present }.at(at));
+            }
+        })
+    }
+
+    /// Require the number at `a` to be less than or equal to the number at `b`.
+    ///
+    /// Integers and floats compare with each other. The error is `IncorrectValue` at `a`.
+    pub fn less_or_equal(self, a: &str, b: &str) -> Rules<'r> {
+        let (a, b) = (a.to_string(), b.to_string());
+        self.add(move |root, errors| {
+            let (a_value, b_value) = match (root.get_path(&a), root.get_path(&b)) {
+                (Some(a_value), Some(b_value)) => (a_value, b_value),
+                _ => return,
+            };
+            if greater(a_value, b_value) {
+                errors.push(Error::IncorrectValue {
+                        explanation: Some(format!("{} must not be greater than {} ({})",
+                                                  a,
+                                                  b,
+                                                  b_value.to_pretty_string())),
+                        value: a_value.clone(),
+                        possible_list: vec![],
+                    }
+                    .at(a.clone()))
+            }
+        })
+    }
+
+    /// Add a rule checking the root of the config with `Path` getters.
+    ///
+    /// The errors of getters already have the right paths, so child paths can be read
+    /// with `table_property` and checked like in a decoder.
+    pub fn rule<F>(self, f: F) -> Rules<'r>
+        where F: Fn(&Path) -> ::decode::Result<()> + 'r
+    {
+        self.add(move |root, errors| {
+            if let Err(e) = f(&Path::new(root, ROOT_DESC)) {
+                errors.push(e);
+            }
+        })
+    }
+
+    /// Check all rules on `value`, in the order they were added, returning all errors.
+    pub fn check(&self, value: &Value) -> Result<(), Errors> {
+        let mut errors = vec![];
+        for rule in &self.rules {
+            rule(value, &mut errors);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Errors(errors))
+        }
+    }
+
+    fn add<F>(mut self, rule: F) -> Rules<'r>
+        where F: Fn(&Value, &mut Vec<At<Error>>) + 'r
+    {
+        self.rules.push(Box::new(rule));
+        self
+    }
+}
+
+fn to_strings(paths: &[&str]) -> Vec<String> {
+    paths.iter().map(|p| p.to_string()).collect()
+}
+
+/// Whether there is a value at `path`, null counting as missing.
+fn is_present(root: &Value, path: &str) -> bool {
+    root.get_path(path).is_some_and(|v| !v.is_null())
+}
+
+fn require(root: &Value, required: &[String], desc: &str, errors: &mut Vec<At<Error>>) {
+    for path in required.iter().filter(|p| !is_present(root, p)) {
+        let name = match parse_dotted_path(path).and_then(|mut c| c.pop()) {
+            Some(PathComponent::Key(key)) => key,
+            _ => path.clone(),
+        };
+        errors.push(Error::ExpectedProperty(Property {
+                name,
+                desc: desc.to_string(),
+            })
+            .at(path.clone()));
+    }
+}
+
+/// Whether `a` is a number greater than the number `b`.
+///
+/// Two integers are compared exactly, an integer and a float as floats.
+fn greater(a: &Value, b: &Value) -> bool {
+    if let (&Value::Integer(x), &Value::Integer(y)) = (a, b) {
+        return x > y;
+    }
+    match (number(a), number(b)) {
+        (Some(x), Some(y)) => x > y,
+        _ => false,
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Integer(i) => Some(i as f64),
+        Value::Float(f) => Some(f),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use decode::{Error, Errors};
+    use Value;
+    use super::Rules;
+
+    fn failures(rules: &Rules, value: Value) -> Vec<(String, &'static str)> {
+        match rules.check(&value) {
+            Ok(()) => vec![],
+            Err(Errors(errors)) => {
+                errors.into_iter().map(|e| (e.path, e.error.code())).collect()
+            }
+        }
+    }
+
+    #[test]
+    fn require_if_checks_paths_only_for_the_value() {
+        let rules = Rules::new().require_if("tls.enabled", Value::Boolean(true), &["tls.cert"]);
+        assert_eq!(failures(&rules, value!{ tls: { enabled: false } }), []);
+        assert_eq!(failures(&rules, value!{ tls: { enabled: true, cert: "a" } }), []);
+        assert_eq!(failures(&rules, value!{ tls: { enabled: true, cert: null } }),
+                   [("tls.cert".to_string(), "E_EXPECTED_PROPERTY")]);
+        let errors = rules.check(&value!{ tls: { enabled: true } }).unwrap_err();
+        assert_eq!(errors.0[0].to_string(),
+                   "at 'tls.cert': missing property 'cert' (required when tls.enabled is \
+                    true)");
+    }
+
+    #[test]
+    fn require_with_treats_null_as_missing() {
+        let rules = Rules::new().require_with("proxy.host", &["proxy.port", "proxy.user"]);
+        assert_eq!(failures(&rules, value!{ proxy: { host: null } }), []);
+        assert_eq!(failures(&rules, value!{ proxy: { host: "a", port: 1, user: null } }),
+                   [("proxy.user".to_string(), "E_EXPECTED_PROPERTY")]);
+    }
+
+    #[test]
+    fn mutually_exclusive_reports_all_present_paths() {
+        let rules = Rules::new().mutually_exclusive(&["password", "key", "token"]);
+        assert_eq!(failures(&rules, value!{ password: "a", key: null }), []);
+        let errors = rules.check(&value!{ key: "k", token: "t", password: null }).unwrap_err();
+        assert_eq!(errors.0[0].path, "key");
+        match errors.0[0].error {
+            Error::ConflictingProperties { ref paths } => assert_eq!(paths, &["key", "token"]),
+            ref other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn less_or_equal_compares_integers_and_floats() {
+        let rules = Rules::new().less_or_equal("min", "max");
+        assert_eq!(failures(&rules, value!{ min: 1, max: 1.5 }), []);
+        assert_eq!(failures(&rules, value!{ min: 3, max: 3.0 }), []);
+        assert_eq!(failures(&rules, value!{ min: 2, max: 1.5 }),
+                   [("min".to_string(), "E_INCORRECT_VALUE")]);
+        assert_eq!(failures(&rules, value!{ min: 0.5, max: 0 }),
+                   [("min".to_string(), "E_INCORRECT_VALUE")]);
+        assert_eq!(failures(&rules, value!{ min: i64::MAX, max: i64::MAX - 1 }),
+                   [("min".to_string(), "E_INCORRECT_VALUE")]);
+        assert_eq!(failures(&rules, value!{ min: "5", max: 1 }), []);
+        assert_eq!(failures(&rules, value!{ min: 5 }), []);
+        let errors = rules.check(&value!{ min: 2, max: 1 }).unwrap_err();
+        assert_eq!(errors.0[0].to_string(), "at 'min': min must not be greater than max (1) \
+                                             (found 2)");
+    }
+
+    #[test]
+    fn rule_reports_getter_errors() {
+        let rules = Rules::new()
+            .rule(|root| root.table_property("port", "port")?.as_integer_in(1..).map(|_| ()))
+            .mutually_exclusive(&["a", "b"]);
+        assert_eq!(failures(&rules, value!{ port: 80 }), []);
+        assert_eq!(failures(&rules, value!{ port: "x", a: 1, b: 2 }),
+                   [("port".to_string(), "E_EXPECTED_INTEGER"),
+                    ("a".to_string(), "E_CONFLICTING_PROPERTIES")]);
+        assert_eq!(failures(&rules, value!{ port: 0 }),
+                   [("port".to_string(), "E_INCORRECT_VALUE")]);
+    }
+}
@@ -0,0 +1,474 @@
+//! Declaring the expected shape of a configuration as data.
+//!
+//! A `Schema` describes a value: its type, whether it is required, its default, simple
+//! constraints and a description. The schema can validate any `Value` without writing a
+//! decoder, fill in the defaults, and render documentation and a commented example:
+//!
+//! ```
+//! # use config_model::encode::TableBuilder;
+//! # use config_model::schema::Schema;
+//! let schema = Schema::table()
+//!     .field("host", Schema::string().desc("address to bind"))
+//!     .field("port", Schema::integer().range(1..65536).desc("listen port"));
+//! let value = TableBuilder::new().property("host", "::").property("port", &0).build();
+//! let errors = schema.validate(&value).unwrap_err();
+//! assert_eq!(errors.0[0].path, "port");
+//! ```
+//!
+//! Errors are the same as the ones of `Path` getters. Unlike the `decode::Schema`
+//! recorder, which learns the shape from a decoder, this schema is written by hand.
+
+use std::borrow::Cow;
+use std::ops::{Bound, RangeBounds};
+use decode::{self, At, Error, Errors, Path, SchemaEntry};
+use env::parse_scalar;
+use value::components_to_string;
+use {Value, Table, PathComponent};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Any,
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Datetime,
+    Array(Box<Schema>),
+    /// Known fields, checked in the order they were declared.
+    Table(Vec<(String, Schema)>),
+    /// Table with user-chosen keys and values of the same schema.
+    Map(Box<Schema>),
+}
+
+/// Expected shape of a value, configured with the builder methods.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    kind: Kind,
+    desc: String,
+    required: bool,
+    default: Option<Value>,
+    range: Option<(Bound<i64>, Bound<i64>)>,
+    allowed: Vec<String>,
+    allow_unknown: bool,
+}
+
+impl Schema {
+    fn new(kind: Kind) -> Schema {
+        Schema {
+            kind,
+            desc: String::new(),
+            required: true,
+            default: None,
+            range: None,
+            allowed: vec![],
+            allow_unknown: false,
+        }
+    }
+
+    /// A value of any type.
+    pub fn any() -> Schema {
+        Schema::new(Kind::Any)
+    }
+
+    pub fn string() -> Schema {
+        Schema::new(Kind::String)
+    }
+
+    pub fn integer() -> Schema {
+        Schema::new(Kind::Integer)
+    }
+
+    pub fn float() -> Schema {
+        Schema::new(Kind::Float)
+    }
+
+    pub fn boolean() -> Schema {
+        Schema::new(Kind::Boolean)
+    }
+
+    pub fn datetime() -> Schema {
+        Schema::new(Kind::Datetime)
+    }
+
+    /// An array with elements of `element` schema.
+    pub fn array(element: Schema) -> Schema {
+        Schema::new(Kind::Array(Box::new(element)))
+    }
+
+    /// A table with the fields added by `field`; other keys are errors.
+    pub fn table() -> Schema {
+        Schema::new(Kind::Table(vec![]))
+    }
+
+    /// A table with any keys, and values of `value` schema.
+    pub fn map(value: Schema) -> Schema {
+        Schema::new(Kind::Map(Box::new(value)))
+    }
+
+    /// Add the field `name` to a table schema, replacing an earlier field of that name.
+    ///
+    /// Panics if this is not a table schema.
+    pub fn field(mut self, name: &str, schema: Schema) -> Schema {
+        match self.kind {
+            Kind::Table(ref mut fields) => {
+                fields.retain(|(n, _)| n != name);
+                fields.push((name.to_string(), schema));
+            }
+            _ => panic!("field {:?} added to a schema that is not a table", name),
+        }
+        self
+    }
+
+    pub fn desc(self, desc: &str) -> Schema {
+        Schema { desc: desc.to_string(), ..self }
+    }
+
+    /// Allow the value to be missing or null.
+    pub fn optional(self) -> Schema {
+        Schema { required: false, ..self }
+    }
+
+    /// Make the value optional, with `default` used by `fill_defaults` and in examples.
+    pub fn default(self, default: Value) -> Schema {
+        Schema {
+            required: false,
+            default: Some(default),
+            ..self
+        }
+    }
+
+    /// Require an integer to be in `range`, like `1..65536`.
+    pub fn range<R: RangeBounds<i64>>(self, range: R) -> Schema {
+        Schema { range: Some((range.start_bound().cloned(), range.end_bound().cloned())), ..self }
+    }
+
+    /// Require a string to be one of `allowed`.
+    pub fn one_of(self, allowed: &[&str]) -> Schema {
+        Schema { allowed: allowed.iter().map(|s| s.to_string()).collect(), ..self }
+    }
+
+    /// Accept keys of a table schema that are not declared as fields.
+    pub fn allow_unknown(self) -> Schema {
+        Schema { allow_unknown: true, ..self }
+    }
+
+    /// Check `value` against this schema, reporting every problem found.
+    ///
+    /// Validation goes on after errors, except inside a value of the wrong type. Unknown
+    /// table keys are `UnexpectedProperty` errors suggesting a similar field name.
+    pub fn validate(&self, value: &Value) -> Result<(), Errors> {
+        let mut errors = vec![];
+        self.validate_at(&Path::new(value, self.root_desc()), &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Errors(errors))
+        }
+    }
+
+    /// Set the default of every missing field that has one, in tables that are present.
+    pub fn fill_defaults(&self, value: &mut Value) {
+        match (&self.kind, value) {
+            (Kind::Table(fields), Value::Table(table)) => {
+                for (name, schema) in fields {
+                    if table.get(name).is_none_or(Value::is_null) {
+                        match schema.default {
+                            Some(ref default) => {
+                                table.insert(name.clone(), default.clone());
+                            }
+                            None => continue,
+                        }
+                    }
+                    if let Some(child) = table.get_mut(name) {
+                        schema.fill_defaults(child);
+                    }
+                }
+            }
+            (Kind::Map(schema), Value::Table(table)) => {
+                for child in table.values_mut() {
+                    schema.fill_defaults(child);
+                }
+            }
+            (Kind::Array(schema), Value::Array(array)) => {
+                for child in array {
+                    schema.fill_defaults(child);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Return the fields of this table schema as `SchemaEntry` documentation entries,
+    /// with defaults as examples.
+    pub fn entries(&self) -> Vec<SchemaEntry> {
+        self.entry("").children
+    }
+
+    /// Render the fields as a nested markdown list, see `decode::Schema::to_markdown`.
+    pub fn to_markdown(&self) -> String {
+        decode::Schema::from_entries(self.entries()).to_markdown()
+    }
+
+    /// Render the fields as indented plain text, see `decode::Schema::to_text`.
+    pub fn to_text(&self) -> String {
+        decode::Schema::from_entries(self.entries()).to_text()
+    }
+
+    /// Render the fields as a commented example TOML document, see
+    /// `decode::Schema::to_example_toml`.
+    pub fn to_example_toml(&self) -> String {
+        decode::Schema::from_entries(self.entries()).to_example_toml()
+    }
+
+    /// Build a sample config with every field, see `decode::Schema::sample_value`.
+    ///
+    /// Fields have their default value, or else their first allowed value, or else a
+    /// placeholder of their type:
+    ///
+    /// ```
+    /// # #[macro_use] extern crate config_model;
+    /// # use config_model::schema::Schema;
+    /// # use config_model::Value;
+    /// # fn main() {
+    /// let schema = Schema::table()
+    ///     .field("host", Schema::string())
+    ///     .field("port", Schema::integer().default(Value::Integer(8080)))
+    ///     .field("mode", Schema::string().one_of(&["fast", "safe"]));
+    /// assert_eq!(schema.sample_value(), value!{ host: "", port: 8080, mode: "fast" });
+    /// # }
+    /// ```
+    pub fn sample_value(&self) -> Value {
+        decode::Schema::from_entries(self.entries()).sample_value()
+    }
+
+    /// Find the schema of the value at `path`, `None` if any value is allowed there.
+    ///
+    /// A key that is not a field is an `UnexpectedProperty` error, and a key or index
+    /// under a schema that is not a table or an array an `ExpectedTable` or
+    /// `ExpectedSlice` error.
+    pub(crate) fn lookup(&self, path: &[PathComponent]) -> decode::Result<Option<&Schema>> {
+        let mut current = self;
+        for (i, component) in path.iter().enumerate() {
+            current = match (&current.kind, component) {
+                (Kind::Any, _) => return Ok(None),
+                (Kind::Table(fields), PathComponent::Key(key)) => {
+                    match fields.iter().find(|(name, _)| name == key) {
+                        Some((_, schema)) => schema,
+                        None if current.allow_unknown => return Ok(None),
+                        None => {
+                            return Err(Error::UnexpectedProperty {
+                                    name: key.clone(),
+                                    suggestion: decode::closest_name(key,
+                                                                     fields.iter()
+                                                                         .map(|f| &f.0[..])),
+                                }
+                                .at(components_to_string(&path[..i + 1])))
+                        }
+                    }
+                }
+                (Kind::Map(schema), PathComponent::Key(..)) |
+                (Kind::Array(schema), PathComponent::Index(..)) => schema,
+                (_, PathComponent::Key(..)) => {
+                    let parent = components_to_string(&path[..i]);
+                    let desc = current.desc_or(&parent).to_string();
+                    return Err(Error::ExpectedTable { desc }.at(parent));
+                }
+                (_, PathComponent::Index(..)) => {
+                    let parent = components_to_string(&path[..i]);
+                    let desc = current.desc_or(&parent).to_string();
+                    return Err(Error::ExpectedSlice { desc }.at(parent));
+                }
+            };
+        }
+        Ok(Some(current))
+    }
+
+    /// Description of the value at `path`, the one `validate` gives it.
+    ///
+    /// A field is described by its schema or by its name, an array element as an element
+    /// of the array, and a map entry by its key, like `web of servers`.
+    pub(crate) fn describe(&self, path: &[PathComponent]) -> String {
+        let mut current = Some(self);
+        let mut desc = self.root_desc().to_string();
+        for component in path {
+            current = match (current.map(|s| &s.kind), component) {
+                (Some(Kind::Table(fields)), PathComponent::Key(key)) => {
+                    let field = fields.iter().find(|(name, _)| name == key).map(|f| &f.1);
+                    desc = field.map_or(&key[..], |s| s.desc_or(key)).to_string();
+                    field
+                }
+                (Some(Kind::Map(schema)), PathComponent::Key(key)) => {
+                    desc = format!("{} of {}", key, desc);
+                    Some(schema)
+                }
+                (Some(Kind::Array(schema)), PathComponent::Index(..)) => {
+                    desc = format!("element of {}", desc);
+                    Some(schema)
+                }
+                (_, PathComponent::Key(key)) => {
+                    desc = key.clone();
+                    None
+                }
+                (_, PathComponent::Index(..)) => {
+                    desc = format!("element of {}", desc);
+                    None
+                }
+            };
+        }
+        desc
+    }
+
+    /// Convert text given for a value of this schema, keeping it a string if a string is
+    /// expected, and otherwise inferring the type like environment overrides do.
+    pub(crate) fn parse_str(&self, s: &str) -> Value {
+        match self.kind {
+            Kind::String => Value::String(s.to_string()),
+            Kind::Datetime => Value::Datetime(s.to_string()),
+            Kind::Float => s.parse().map(Value::Float).unwrap_or_else(|_| parse_scalar(s)),
+            _ => parse_scalar(s),
+        }
+    }
+
+    /// Description of values of this schema, `default` if there is none.
+    pub(crate) fn desc_or<'d>(&'d self, default: &'d str) -> &'d str {
+        if self.desc.is_empty() { default } else { &self.desc }
+    }
+
+    fn root_desc(&self) -> &str {
+        self.desc_or("config")
+    }
+
+    fn kind_str(&self) -> Option<&'static str> {
+        Some(match self.kind {
+            Kind::Any => return None,
+            Kind::String => "string",
+            Kind::Integer => "integer",
+            Kind::Float => "float",
+            Kind::Boolean => "boolean",
+            Kind::Datetime => "datetime",
+            Kind::Array(..) => "array",
+            Kind::Table(..) | Kind::Map(..) => "table",
+        })
+    }
+
+    fn entry(&self, name: &str) -> SchemaEntry {
+        let children = match self.kind {
+            Kind::Table(ref fields) => fields.iter().map(|(n, s)| s.entry(n)).collect(),
+            Kind::Map(ref schema) => vec![schema.entry("*")],
+            Kind::Array(ref schema) => vec![schema.entry("[]")],
+            _ => vec![],
+        };
+        SchemaEntry {
+            name: name.to_string(),
+            desc: self.desc.clone(),
+            kind: self.kind_str(),
+            example: self.default.clone().filter(|v| v.as_table().is_none()),
+            allowed: self.allowed.iter().map(|s| Value::String(s.clone())).collect(),
+            children,
+        }
+    }
+
+    pub(crate) fn validate_at(&self, path: &Path, errors: &mut Vec<At<Error>>) {
+        let result = match self.kind {
+            Kind::Any => Ok(()),
+            Kind::String if !self.allowed.is_empty() => {
+                let allowed: Vec<&str> = self.allowed.iter().map(|s| &s[..]).collect();
+                path.one_of_str(&allowed).map(|_| ())
+            }
+            Kind::String => path.as_str().map(|_| ()),
+            Kind::Integer => {
+                match self.range {
+                    Some(range) => path.as_integer_in(range).map(|_| ()),
+                    None => path.as_integer().map(|_| ()),
+                }
+            }
+            Kind::Float => path.as_float().map(|_| ()),
+            Kind::Boolean => path.as_bool().map(|_| ()),
+            Kind::Datetime => path.as_datetime().map(|_| ()),
+            Kind::Array(ref element) => {
+                path.array_elements(format!("element of {}", path.description())).map(|elements| {
+                    for element_path in elements {
+                        element.validate_at(&element_path, errors);
+                    }
+                })
+            }
+            Kind::Table(ref fields) => self.validate_table(path, fields, errors),
+            Kind::Map(ref schema) => {
+                path.as_table().map(|table| {
+                    for key in table.keys() {
+                        let desc = format!("{} of {}", key, path.description());
+                        if let Ok(child) = path.table_property(key, desc) {
+                            schema.validate_at(&child, errors);
+                        }
+                    }
+                })
+            }
+        };
+        if let Err(e) = result {
+            errors.push(e);
+        }
+    }
+
+    fn validate_table(&self,
+                      path: &Path,
+                      fields: &[(String, Schema)],
+                      errors: &mut Vec<At<Error>>)
+                      -> decode::Result<()> {
+        let table: &Table = path.as_table()?;
+        for (name, schema) in fields {
+            let desc = Cow::Borrowed(schema.desc_or(name));
+            if schema.required {
+                match path.table_property(name, desc) {
+                    Ok(child) => schema.validate_at(&child, errors),
+                    Err(e) => errors.push(e),
+                }
+            } else if let Ok(Some(child)) = path.optional_table_property(name, desc) {
+                schema.validate_at(&child, errors);
+            }
+        }
+        if !self.allow_unknown {
+            for key in table.keys() {
+                if fields.iter().any(|(name, _)| name == key) {
+                    continue;
+                }
+                errors.push(Error::UnexpectedProperty {
+                        name: key.clone(),
+                        suggestion: decode::closest_name(key,
+                                                         fields.iter().map(|f| &f.0[..])),
+                    }
+                    .at(path.child_path_string(key)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Schema;
+
+    #[test]
+    fn sample_value_of_nested_schema() {
+        let schema = Schema::table()
+            .field("server",
+                   Schema::table()
+                       .field("host", Schema::string())
+                       .field("tls", Schema::boolean().optional()))
+            .field("workers", Schema::array(Schema::table().field("threads", Schema::integer())))
+            .field("limits", Schema::map(Schema::float()))
+            .field("level", Schema::string().one_of(&["info", "debug"]));
+        assert_eq!(schema.sample_value(),
+                   value!{
+                       server: { host: "", tls: false },
+                       workers: [{ threads: 0 }],
+                       limits: {},
+                       level: "info"
+                   });
+    }
+
+    #[test]
+    fn example_toml_uses_first_allowed_value() {
+        let schema = Schema::table().field("level", Schema::string().one_of(&["info", "debug"]));
+        assert_eq!(schema.to_example_toml(), "# (string)\n# level = \"info\"\n");
+    }
+}
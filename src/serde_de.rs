@@ -0,0 +1,352 @@
+//! A serde `Deserializer` over `Path`, for decoding into `#[derive(Deserialize)]` types.
+//!
+//! Errors keep the path of the value they are about. A missing struct field is reported
+//! as `ExpectedProperty` at the path of the field, exactly like `Path::table_property`
+//! would, and a missing `Option` field is `None`, like `Path::optional_table_property`.
+//! A `Value::Null` is `None` too.
+//!
+//! Enums are read externally tagged: a unit variant is a string with the variant name,
+//! other variants are a table with the variant name as the only key.
+
+use std::error;
+use std::fmt;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use Value;
+use decode::{self, Path};
+
+/// Deserialize a value of type `T` from the value at `path`.
+pub fn from_path<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    T::deserialize(Deserializer::new(path))
+}
+
+/// Deserialize a value of type `T` from the root `value`.
+///
+/// Same as `from_path` on `Path::new(value, "config")`.
+pub fn from_value<T: DeserializeOwned>(value: &Value) -> Result<T, Error> {
+    from_path(&Path::new(value, "config"))
+}
+
+/// Error deserializing a value.
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// Location of the problem, formatted like `decode::At::path`.
+    pub path: String,
+    located: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum ErrorKind {
+    /// Error from the decoding of this crate, for example a missing property.
+    Decode(decode::Error),
+    /// Error reported by a `Deserialize` implementation, like an integer out of range.
+    Custom(String),
+}
+
+impl Error {
+    /// Set the path of an error that does not have one yet.
+    fn locate(self, path: &Path) -> Error {
+        if self.located {
+            return self;
+        }
+        if let ErrorKind::Decode(decode::Error::ExpectedProperty(ref p)) = self.kind {
+            if let Err(at) = path.table_property(&p.name, p.desc.clone()) {
+                return Error::from(at);
+            }
+        }
+        Error {
+            path: path.to_string(),
+            located: true,
+            ..self
+        }
+    }
+}
+
+impl From<decode::At<decode::Error>> for Error {
+    fn from(at: decode::At<decode::Error>) -> Error {
+        Error {
+            kind: ErrorKind::Decode(at.error),
+            path: at.path,
+            located: true,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Decode(ref e) => e.fmt(f),
+            ErrorKind::Custom(ref message) => message.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    /// Formats like `decode::At`, as `at 'server.port': ...`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            self.kind.fmt(f)
+        } else {
+            write!(f, "at '{}': {}", self.path, self.kind)
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Error {
+        Error {
+            kind: ErrorKind::Custom(message.to_string()),
+            path: String::new(),
+            located: false,
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Error {
+        Error {
+            kind: ErrorKind::Decode(decode::Error::ExpectedProperty(decode::Property {
+                name: field.to_string(),
+                desc: field.to_string(),
+            })),
+            path: String::new(),
+            located: false,
+        }
+    }
+}
+
+/// Deserializer of the value at a `Path`.
+#[derive(Clone, Copy)]
+pub struct Deserializer<'p> {
+    path: &'p Path<'p>,
+}
+
+impl<'p> Deserializer<'p> {
+    pub fn new(path: &'p Path<'p>) -> Deserializer<'p> {
+        Deserializer { path }
+    }
+
+    fn entries(&self) -> Result<Vec<(&'p str, Path<'p>)>, Error> {
+        let path = self.path;
+        Ok(path.as_table()?
+            .iter()
+            .map(|(key, value)| (&key[..], path.join(value, key, &key[..])))
+            .collect())
+    }
+}
+
+impl<'de, 'p> de::Deserializer<'de> for Deserializer<'p> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let path = self.path;
+        match *path.value() {
+            Value::String(ref s) |
+            Value::Datetime(ref s) => visitor.visit_str(s),
+            Value::Integer(i) => visitor.visit_i64(i),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Null => visitor.visit_unit(),
+            Value::Array(..) => {
+                let elements = path.array_elements(format!("element of {}", path.description()))?;
+                visitor.visit_seq(SeqAccess {
+                    elements: elements.iter(),
+                })
+            }
+            Value::Table(..) => {
+                visitor.visit_map(MapAccess {
+                    entries: self.entries()?.into_iter(),
+                    value: None,
+                })
+            }
+        }
+        .map_err(|e| e.locate(path))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.path.is_null() {
+            return visitor.visit_none();
+        }
+        visitor.visit_some(self).map_err(|e| e.locate(self.path))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self,
+                                                   _name: &'static str,
+                                                   visitor: V)
+                                                   -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self).map_err(|e| e.locate(self.path))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self,
+                                           _name: &'static str,
+                                           _fields: &'static [&'static str],
+                                           visitor: V)
+                                           -> Result<V::Value, Error> {
+        visitor.visit_map(MapAccess {
+                entries: self.entries()?.into_iter(),
+                value: None,
+            })
+            .map_err(|e| e.locate(self.path))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self,
+                                         _name: &'static str,
+                                         _variants: &'static [&'static str],
+                                         visitor: V)
+                                         -> Result<V::Value, Error> {
+        let path = self.path;
+        match *path.value() {
+            Value::String(ref s) => visitor.visit_enum(s.as_str().into_deserializer()),
+            Value::Table(ref t) if t.len() == 1 => {
+                let (variant, value) = self.entries()?.pop().unwrap();
+                visitor.visit_enum(EnumAccess {
+                    variant,
+                    value,
+                })
+            }
+            _ => {
+                Err(decode::Error::ExpectedOneOfTypes {
+                        found_type: path.value().type_str().to_string(),
+                        possible_list: vec!["string".to_string(),
+                                            "table with a single key".to_string()],
+                    }
+                    .at(path.to_string())
+                    .into())
+            }
+        }
+        .map_err(|e| e.locate(path))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map identifier ignored_any
+    }
+}
+
+struct SeqAccess<'e, 'p: 'e> {
+    elements: ::std::slice::Iter<'e, Path<'p>>,
+}
+
+impl<'de, 'e, 'p> de::SeqAccess<'de> for SeqAccess<'e, 'p> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+        where T: de::DeserializeSeed<'de>
+    {
+        match self.elements.next() {
+            Some(path) => seed.deserialize(Deserializer::new(path)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+struct MapAccess<'p> {
+    entries: ::std::vec::IntoIter<(&'p str, Path<'p>)>,
+    value: Option<Path<'p>>,
+}
+
+impl<'de, 'p> de::MapAccess<'de> for MapAccess<'p> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+        where K: de::DeserializeSeed<'de>
+    {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                let key: de::value::StrDeserializer<Error> = key.into_deserializer();
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+        where V: de::DeserializeSeed<'de>
+    {
+        let value = self.value.take().expect("next_value_seed called before next_key_seed");
+        seed.deserialize(Deserializer::new(&value))
+    }
+}
+
+struct EnumAccess<'p> {
+    variant: &'p str,
+    value: Path<'p>,
+}
+
+impl<'de, 'p> de::EnumAccess<'de> for EnumAccess<'p> {
+    type Error = Error;
+    type Variant = VariantAccess<'p>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, VariantAccess<'p>), Error>
+        where V: de::DeserializeSeed<'de>
+    {
+        let variant: de::value::StrDeserializer<Error> = self.variant.into_deserializer();
+        let variant = seed.deserialize(variant)?;
+        Ok((variant, VariantAccess { value: self.value }))
+    }
+}
+
+struct VariantAccess<'p> {
+    value: Path<'p>,
+}
+
+impl<'de, 'p> de::VariantAccess<'de> for VariantAccess<'p> {
+    type Error = Error;
+
+    /// The value of a unit variant written as a table is ignored.
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+        where T: de::DeserializeSeed<'de>
+    {
+        seed.deserialize(Deserializer::new(&self.value))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(Deserializer::new(&self.value), visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self,
+                                       fields: &'static [&'static str],
+                                       visitor: V)
+                                       -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_struct(Deserializer::new(&self.value), "", fields, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use decode;
+    use super::{from_value, ErrorKind};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        server: Server,
+    }
+
+    #[test]
+    fn deserializes_struct_fields() {
+        let config: Config = from_value(&value!{ server: { host: "::", port: 8080 } }).unwrap();
+        assert_eq!(config.server, Server { host: "::".to_string(), port: 8080 });
+    }
+
+    #[test]
+    fn missing_field_is_expected_property_at_its_path() {
+        let e = from_value::<Config>(&value!{ server: { host: "::" } }).unwrap_err();
+        assert_eq!(e.path, "server.port");
+        match e.kind {
+            ErrorKind::Decode(decode::Error::ExpectedProperty(ref p)) => assert_eq!(p.name, "port"),
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+}
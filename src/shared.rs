@@ -0,0 +1,162 @@
+//! A cheaply clonable config value representation.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use Value;
+
+/// Copy-on-write variant of `Value`.
+///
+/// Arrays and tables are kept behind an `Arc`, so cloning a `SharedValue` only clones
+/// the top level, no matter how large the tree is. Mutable accessors copy the shared
+/// container on first write, which means only the touched subtree gets duplicated.
+///
+/// This is useful when many near-identical configs are derived from a base, for example
+/// applying per-request overrides.
+///
+/// Shared containers are never mutated in place while shared, and there is no interior
+/// mutability, so a `SharedValue` can not contain a reference cycle. Recursive operations
+/// therefore need no cycle detection; the same containers may only appear in multiple
+/// places of the tree, which is safe to visit more than once.
+#[derive(PartialEq, Clone, Debug)]
+#[allow(missing_docs)]
+pub enum SharedValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Datetime(String),
+    Array(Arc<SharedArray>),
+    Table(Arc<SharedTable>),
+    Null,
+}
+
+/// Type representing an array, payload of the SharedValue::Array variant
+pub type SharedArray = Vec<SharedValue>;
+
+/// Type representing a table, payload of the SharedValue::Table variant
+pub type SharedTable = BTreeMap<String, SharedValue>;
+
+impl SharedValue {
+    /// Returns a human-readable representation of the type of this value.
+    pub fn type_str(&self) -> &'static str {
+        match *self {
+            SharedValue::String(..) => "string",
+            SharedValue::Integer(..) => "integer",
+            SharedValue::Float(..) => "float",
+            SharedValue::Boolean(..) => "boolean",
+            SharedValue::Datetime(..) => "datetime",
+            SharedValue::Array(..) => "array",
+            SharedValue::Table(..) => "table",
+            SharedValue::Null => "null",
+        }
+    }
+
+    /// Extracts the array value if it is an array.
+    pub fn as_slice(&self) -> Option<&[SharedValue]> {
+        match *self {
+            SharedValue::Array(ref s) => Some(&***s),
+            _ => None,
+        }
+    }
+
+    /// Extracts the table value if it is a table.
+    pub fn as_table(&self) -> Option<&SharedTable> {
+        match *self {
+            SharedValue::Table(ref s) => Some(&**s),
+            _ => None,
+        }
+    }
+
+    /// Extracts the mutable array value if it is an array.
+    ///
+    /// If the array is shared with other clones, it is copied first.
+    pub fn as_array_mut(&mut self) -> Option<&mut SharedArray> {
+        match *self {
+            SharedValue::Array(ref mut s) => Some(Arc::make_mut(s)),
+            _ => None,
+        }
+    }
+
+    /// Extracts the mutable table value if it is a table.
+    ///
+    /// If the table is shared with other clones, it is copied first.
+    pub fn as_table_mut(&mut self) -> Option<&mut SharedTable> {
+        match *self {
+            SharedValue::Table(ref mut s) => Some(Arc::make_mut(s)),
+            _ => None,
+        }
+    }
+
+    /// Returns true if both values point to the same shared container.
+    ///
+    /// Scalars are never considered shared.
+    pub fn ptr_eq(&self, other: &SharedValue) -> bool {
+        match (self, other) {
+            (SharedValue::Array(a), SharedValue::Array(b)) => Arc::ptr_eq(a, b),
+            (SharedValue::Table(a), SharedValue::Table(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Convert into a plain `Value`, cloning the containers that are still shared.
+    pub fn to_value(&self) -> Value {
+        match *self {
+            SharedValue::String(ref s) => Value::String(s.clone()),
+            SharedValue::Integer(i) => Value::Integer(i),
+            SharedValue::Float(f) => Value::Float(f),
+            SharedValue::Boolean(b) => Value::Boolean(b),
+            SharedValue::Datetime(ref s) => Value::Datetime(s.clone()),
+            SharedValue::Null => Value::Null,
+            SharedValue::Array(ref a) => Value::Array(a.iter().map(|v| v.to_value()).collect()),
+            SharedValue::Table(ref t) => {
+                Value::Table(t.iter().map(|(k, v)| (k.clone(), v.to_value())).collect())
+            }
+        }
+    }
+}
+
+impl From<Value> for SharedValue {
+    fn from(value: Value) -> SharedValue {
+        match value {
+            Value::String(s) => SharedValue::String(s),
+            Value::Integer(i) => SharedValue::Integer(i),
+            Value::Float(f) => SharedValue::Float(f),
+            Value::Boolean(b) => SharedValue::Boolean(b),
+            Value::Datetime(s) => SharedValue::Datetime(s),
+            Value::Null => SharedValue::Null,
+            Value::Array(a) => {
+                SharedValue::Array(Arc::new(a.into_iter().map(SharedValue::from).collect()))
+            }
+            Value::Table(t) => {
+                SharedValue::Table(Arc::new(t.into_iter()
+                    .map(|(k, v)| (k, SharedValue::from(v)))
+                    .collect()))
+            }
+        }
+    }
+}
+
+impl From<SharedValue> for Value {
+    fn from(value: SharedValue) -> Value {
+        match value {
+            SharedValue::String(s) => Value::String(s),
+            SharedValue::Integer(i) => Value::Integer(i),
+            SharedValue::Float(f) => Value::Float(f),
+            SharedValue::Boolean(b) => Value::Boolean(b),
+            SharedValue::Datetime(s) => Value::Datetime(s),
+            SharedValue::Null => Value::Null,
+            SharedValue::Array(a) => {
+                match Arc::try_unwrap(a) {
+                    Ok(a) => Value::Array(a.into_iter().map(Value::from).collect()),
+                    Err(a) => Value::Array(a.iter().map(|v| v.to_value()).collect()),
+                }
+            }
+            SharedValue::Table(t) => {
+                match Arc::try_unwrap(t) {
+                    Ok(t) => Value::Table(t.into_iter().map(|(k, v)| (k, Value::from(v))).collect()),
+                    Err(t) => Value::Table(t.iter().map(|(k, v)| (k.clone(), v.to_value())).collect()),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Building a configuration from layered sources.
+//!
+//! A `Source` produces a value, and `ConfigBuilder` merges the values of its sources in
+//! the order they were added, so later sources override earlier ones. The usual stack is
+//! built-in defaults, then a config file, then the environment:
+//!
+//! ```
+//! # use config_model::encode::TableBuilder;
+//! # use config_model::source::{ConfigBuilder, EnvSource};
+//! # use config_model::Value;
+//! let defaults = TableBuilder::new().property("port", &8080).build();
+//! let value = ConfigBuilder::new()
+//!     .add_source(defaults)
+//!     .add_source(EnvSource::new("APP_"))
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(value.get_path("port").and_then(|v| v.as_integer()), Some(8080));
+//! ```
+
+use std::io;
+use std::path::PathBuf;
+use decode::Limits;
+use env::EnvOverlay;
+use format::{self, Format, LoadError};
+use {Value, Table, MergeStrategy};
+
+/// A place the configuration value can be loaded from.
+pub trait Source {
+    fn load(&self) -> Result<Value, LoadError>;
+
+    /// Load like `load`, failing with `LoadError::LimitExceeded` if the value exceeds
+    /// `limits`.
+    ///
+    /// The default checks the loaded value, sources that parse text check while parsing,
+    /// see `Format::parse_limited`.
+    fn load_limited(&self, limits: &Limits) -> Result<Value, LoadError> {
+        let value = self.load()?;
+        limits.check(&value).map_err(|e| LoadError::LimitExceeded(Box::new(e)))?;
+        Ok(value)
+    }
+}
+
+impl Source for Value {
+    fn load(&self) -> Result<Value, LoadError> {
+        Ok(self.clone())
+    }
+}
+
+/// File in the given format.
+#[derive(Clone)]
+pub struct FileSource<'f> {
+    path: PathBuf,
+    format: &'f dyn Format,
+    required: bool,
+}
+
+impl<'f> FileSource<'f> {
+    /// Load the file at `path`, which must exist.
+    pub fn new<P: Into<PathBuf>>(path: P, format: &'f dyn Format) -> FileSource<'f> {
+        FileSource {
+            path: path.into(),
+            format,
+            required: true,
+        }
+    }
+
+    /// Whether a missing file is an error; if not, it loads as an empty table.
+    pub fn required(self, required: bool) -> FileSource<'f> {
+        FileSource { required, ..self }
+    }
+}
+
+impl<'f> FileSource<'f> {
+    fn handle_missing(&self, result: Result<Value, LoadError>) -> Result<Value, LoadError> {
+        match result {
+            Err(LoadError::Io(ref e)) if !self.required && e.kind() == io::ErrorKind::NotFound => {
+                Ok(Value::Table(Table::new()))
+            }
+            Err(LoadError::Io(e)) => {
+                Err(LoadError::Io(io::Error::new(e.kind(),
+                                                 format!("{}: {}", self.path.display(), e))))
+            }
+            result => result,
+        }
+    }
+}
+
+impl<'f> Source for FileSource<'f> {
+    /// The name of the file is added to the message of I/O errors.
+    fn load(&self) -> Result<Value, LoadError> {
+        self.handle_missing(format::load(&self.path, self.format))
+    }
+
+    fn load_limited(&self, limits: &Limits) -> Result<Value, LoadError> {
+        self.handle_missing(format::load_limited(&self.path, self.format, limits))
+    }
+}
+
+/// Configuration text in the given format.
+#[derive(Clone)]
+pub struct StringSource<'f> {
+    text: String,
+    format: &'f dyn Format,
+}
+
+impl<'f> StringSource<'f> {
+    pub fn new<S: Into<String>>(text: S, format: &'f dyn Format) -> StringSource<'f> {
+        StringSource {
+            text: text.into(),
+            format,
+        }
+    }
+}
+
+impl<'f> Source for StringSource<'f> {
+    fn load(&self) -> Result<Value, LoadError> {
+        self.format.parse(&self.text).map_err(LoadError::Parse)
+    }
+
+    fn load_limited(&self, limits: &Limits) -> Result<Value, LoadError> {
+        self.format.parse_limited(&self.text, limits)
+    }
+}
+
+/// Variables of the process environment, mapped by an `EnvOverlay`.
+#[derive(Debug, Clone)]
+pub struct EnvSource {
+    overlay: EnvOverlay,
+}
+
+impl EnvSource {
+    /// Variables starting with `prefix`, see `EnvOverlay::new`.
+    pub fn new(prefix: &str) -> EnvSource {
+        EnvSource::with_overlay(EnvOverlay::new(prefix))
+    }
+
+    pub fn with_overlay(overlay: EnvOverlay) -> EnvSource {
+        EnvSource { overlay }
+    }
+}
+
+impl Source for EnvSource {
+    /// Returns a table of the matching variables, which may be empty.
+    fn load(&self) -> Result<Value, LoadError> {
+        let mut value = Value::Table(Table::new());
+        self.overlay.apply(&mut value);
+        Ok(value)
+    }
+}
+
+/// Stack of sources merged into a single value, configured with the builder methods.
+pub struct ConfigBuilder<'s> {
+    sources: Vec<Box<dyn Source + 's>>,
+    strategy: MergeStrategy,
+    profile: Option<String>,
+    limits: Option<Limits>,
+}
+
+impl<'s> Default for ConfigBuilder<'s> {
+    fn default() -> ConfigBuilder<'s> {
+        ConfigBuilder {
+            sources: vec![],
+            strategy: MergeStrategy::ArrayReplace,
+            profile: None,
+            limits: None,
+        }
+    }
+}
+
+impl<'s> ConfigBuilder<'s> {
+    /// Empty stack, replacing arrays when merging.
+    pub fn new() -> ConfigBuilder<'s> {
+        ConfigBuilder::default()
+    }
+
+    /// Add a source with higher priority than the ones added before.
+    pub fn add_source<S: Source + 's>(mut self, source: S) -> ConfigBuilder<'s> {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Combine arrays of different sources with `strategy`.
+    pub fn merge_strategy(self, strategy: MergeStrategy) -> ConfigBuilder<'s> {
+        ConfigBuilder { strategy, ..self }
+    }
+
+    /// Apply the profile `name` to the merged value, see `Value::apply_profile`.
+    ///
+    /// Profiles of all sources are merged first, so a later source can add to a profile
+    /// of an earlier one.
+    pub fn profile(self, name: &str) -> ConfigBuilder<'s> {
+        ConfigBuilder { profile: Some(name.to_string()), ..self }
+    }
+
+    /// The profile selected with `profile`, to mention in error reports.
+    pub fn active_profile(&self) -> Option<&str> {
+        self.profile.as_ref().map(|p| &p[..])
+    }
+
+    /// Check every loaded source, the merged value and the selected profile against
+    /// `limits`, see `Value::merge_with_limits`.
+    ///
+    /// Sources are loaded with `Source::load_limited`. Most values are checked after they
+    /// are parsed, so this relies on the recursion limits of the parsers themselves for
+    /// deeply nested input.
+    pub fn limits(self, limits: Limits) -> ConfigBuilder<'s> {
+        ConfigBuilder { limits: Some(limits), ..self }
+    }
+
+    /// Load all sources and merge them into an initially empty table.
+    ///
+    /// Stops at the first source that fails to load.
+    pub fn build(&self) -> Result<Value, LoadError> {
+        let mut value = Value::Table(Table::new());
+        for source in &self.sources {
+            match self.limits {
+                Some(ref limits) => {
+                    let loaded = source.load_limited(limits)?;
+                    value.merge_with_limits(loaded, self.strategy, limits)
+                        .map_err(|e| LoadError::LimitExceeded(Box::new(e)))?
+                }
+                None => value.merge_with(source.load()?, self.strategy),
+            }
+        }
+        if let Some(ref profile) = self.profile {
+            value.apply_profile(profile).map_err(LoadError::Profile)?;
+            if let Some(ref limits) = self.limits {
+                limits.check(&value).map_err(|e| LoadError::LimitExceeded(Box::new(e)))?;
+            }
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,106 @@
+//! Parsing of human-friendly byte sizes and durations, like `"256MiB"` or `"1h30m"`.
+
+use std::time::Duration;
+
+/// Byte size suffixes with their multipliers, matched ignoring ASCII case.
+///
+/// Both the `KB` and the `KiB` forms are multiples of 1024.
+pub const BYTE_SIZE_SUFFIXES: &[(&str, u64)] = &[("b", 1),
+                                                 ("k", 1 << 10),
+                                                 ("kb", 1 << 10),
+                                                 ("kib", 1 << 10),
+                                                 ("m", 1 << 20),
+                                                 ("mb", 1 << 20),
+                                                 ("mib", 1 << 20),
+                                                 ("g", 1 << 30),
+                                                 ("gb", 1 << 30),
+                                                 ("gib", 1 << 30),
+                                                 ("t", 1 << 40),
+                                                 ("tb", 1 << 40),
+                                                 ("tib", 1 << 40)];
+
+/// Duration suffixes with their length in nanoseconds.
+pub const DURATION_SUFFIXES: &[(&str, u64)] = &[("ns", 1),
+                                                ("us", 1_000),
+                                                ("ms", 1_000_000),
+                                                ("s", 1_000_000_000),
+                                                ("m", 60 * 1_000_000_000),
+                                                ("h", 60 * 60 * 1_000_000_000),
+                                                ("d", 24 * 60 * 60 * 1_000_000_000)];
+
+/// Parse a byte size, like `"256MiB"`, `"1.5 GB"` or `"1024"` (bytes).
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, suffix) = split_number(s);
+    let number = parse_number(number)?;
+    let suffix = suffix.trim();
+    let multiplier = if suffix.is_empty() {
+        1
+    } else {
+        find_suffix(BYTE_SIZE_SUFFIXES, suffix)?
+    };
+    to_integer(number * multiplier as f64, "byte size")
+}
+
+/// Parse a duration made of one or more number and suffix pairs, like `"1h30m"`.
+///
+/// A number without a suffix is a number of seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return Err("the duration is empty".to_string());
+    }
+    let mut total = 0f64;
+    while !rest.is_empty() {
+        let (number, after) = split_number(rest);
+        let number = parse_number(number)?;
+        let after = after.trim_start();
+        let suffix_len = after.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(after.len());
+        let (suffix, after) = after.split_at(suffix_len);
+        let nanos = if suffix.is_empty() {
+            if !after.trim().is_empty() {
+                return Err("every part of the duration except the last needs a suffix"
+                    .to_string());
+            }
+            1_000_000_000
+        } else {
+            find_suffix(DURATION_SUFFIXES, suffix)?
+        };
+        total += number * nanos as f64;
+        rest = after.trim_start();
+    }
+    let nanos = to_integer(total, "duration")?;
+    Ok(Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32))
+}
+
+/// List suffixes for error messages, like `b, kb, kib`.
+pub fn suffix_list(suffixes: &[(&str, u64)]) -> String {
+    suffixes.iter().map(|&(s, _)| s).collect::<Vec<_>>().join(", ")
+}
+
+fn split_number(s: &str) -> (&str, &str) {
+    let len = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    s.split_at(len)
+}
+
+fn parse_number(s: &str) -> Result<f64, String> {
+    if s.is_empty() {
+        return Err("expected a number".to_string());
+    }
+    s.parse::<f64>().map_err(|_| format!("{:?} is not a number", s))
+}
+
+fn find_suffix(suffixes: &[(&str, u64)], suffix: &str) -> Result<u64, String> {
+    let lower = suffix.to_ascii_lowercase();
+    suffixes.iter()
+        .find(|&&(s, _)| s == lower)
+        .map(|&(_, multiplier)| multiplier)
+        .ok_or_else(|| format!("unknown suffix {:?}", suffix))
+}
+
+fn to_integer(value: f64, what: &str) -> Result<u64, String> {
+    if value >= u64::MAX as f64 {
+        return Err(format!("the {} is too large", what));
+    }
+    Ok(value.round() as u64)
+}
@@ -1,6 +1,16 @@
 //! A config value representation.
 
-use std::collections::BTreeMap;
+use std::cmp::{self, Ordering};
+use std::error;
+use std::fmt;
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::btree_map::Entry;
+use std::ops::Index;
+use decode::{push_key, At, Error, Limits, REDACTED};
+#[cfg(feature = "indexmap")]
+use decode::SourceMap;
+#[cfg(feature = "indexmap")]
+use indexmap::IndexMap;
 
 /// Representation of a config value.
 #[derive(PartialEq, Clone, Debug)]
@@ -13,6 +23,8 @@ pub enum Value {
     Datetime(String),
     Array(Array),
     Table(Table),
+    /// Explicitly empty value, like `null` in JSON or `~` in YAML. TOML has no null.
+    Null,
 }
 
 /// Type representing an array, payload of the Value::Array variant
@@ -21,35 +33,389 @@ pub type Array = Vec<Value>;
 /// Type representing a table, payload of the Value::Table variant
 pub type Table = BTreeMap<String, Value>;
 
+/// Table keeping its entries in insertion order, see `SourceMap::ordered_table`.
+#[cfg(feature = "indexmap")]
+pub type OrderedTable = IndexMap<String, Value>;
+
+/// Error returned by `Value` operations that require a value of a specific type.
+#[derive(PartialEq, Clone, Debug)]
+pub struct TypeMismatch {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+/// How `Value::merge_with` combines two arrays found at the same key.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MergeStrategy {
+    /// The array from the overlay replaces the base array.
+    ArrayReplace,
+    /// Elements of the overlay array are appended to the base array.
+    ArrayConcat,
+    /// Elements of the overlay array are appended to the base array, unless an equal
+    /// element (using `==`) is already there. Existing duplicates in the base are kept.
+    ArrayUnion,
+    /// Each element of the overlay array is merged over the base element at the same
+    /// index, using the same strategy for nested arrays. Overlay elements past the end of
+    /// the base array are appended, and base elements past the end of the overlay are kept.
+    ArrayMergeByIndex,
+}
+
+/// A single step in the location of a nested value.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum PathComponent {
+    /// Key of a table entry.
+    Key(String),
+    /// Index of an array element.
+    Index(usize),
+}
+
+/// Formatting options of `Value::to_pretty_string_with`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct PrettyConfig {
+    /// Number of spaces to indent each nesting level by. Defaults to 2.
+    pub indent: usize,
+    /// Arrays of at most this many scalars are written on one line. Defaults to 4; 0
+    /// writes every non-empty array one element per line.
+    pub inline_array_threshold: usize,
+    /// Sort table keys the way people read them: ignoring ASCII case, and with numbers
+    /// compared by value, so `node9` comes before `Node10`. Defaults to false, which
+    /// writes keys in the order of `Table`, by their bytes.
+    pub sort_keys: bool,
+}
+
+impl Default for PrettyConfig {
+    fn default() -> PrettyConfig {
+        PrettyConfig {
+            indent: 2,
+            inline_array_threshold: 4,
+            sort_keys: false,
+        }
+    }
+}
+
+/// Error returned by `Value::sort_array_by_key`.
+#[derive(PartialEq, Clone, Debug)]
+pub enum SortError {
+    /// Nothing was found at the array path.
+    NotFound,
+    /// The value at the array path is not an array.
+    NotAnArray {
+        found: &'static str,
+    },
+    /// The element at `index` is not a table.
+    NotATable {
+        index: usize,
+        found: &'static str,
+    },
+    /// The element at `index` has no key field.
+    MissingKey {
+        index: usize,
+    },
+    /// The key of the element at `index` is an array, a table or null.
+    NonScalarKey {
+        index: usize,
+        found: &'static str,
+    },
+    /// The key of the element at `index` can not be compared with the key of the first
+    /// element, for example a string and an integer.
+    MixedKeyTypes {
+        index: usize,
+    },
+}
+
+/// A difference between two values, returned by `Value::diff`.
+///
+/// Paths are written like in `get_path`, an empty path is the value itself.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Change {
+    /// The entry or array element at `path` is only in the new value.
+    Added {
+        path: String,
+        value: Value,
+    },
+    /// The entry or array element at `path` is only in the old value.
+    Removed {
+        path: String,
+        value: Value,
+    },
+    /// The value at `path` is different, or has a different type.
+    Changed {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl Change {
+    /// Returns the path of the changed value.
+    pub fn path(&self) -> &str {
+        match *self {
+            Change::Added { ref path, .. } |
+            Change::Removed { ref path, .. } |
+            Change::Changed { ref path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    /// Formats as `+ path = value`, `- path = value` or `~ path = old -> new`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Added { ref path, ref value } => {
+                write!(f, "+ {} = {}", path, value.to_pretty_string())
+            }
+            Change::Removed { ref path, ref value } => {
+                write!(f, "- {} = {}", path, value.to_pretty_string())
+            }
+            Change::Changed { ref path, ref old, ref new } => {
+                write!(f,
+                       "~ {} = {} -> {}",
+                       path,
+                       old.to_pretty_string(),
+                       new.to_pretty_string())
+            }
+        }
+    }
+}
+
+/// Error returned by `Value::set_path`.
+///
+/// Paths in errors are written like in `get_path`, an empty path is this value.
+#[derive(PartialEq, Clone, Debug)]
+pub enum SetPathError {
+    /// The path is empty or malformed.
+    InvalidPath,
+    /// The value at `path` is not a table for a key, or not an array for an index.
+    NotAContainer {
+        path: String,
+        found: &'static str,
+    },
+    /// The array at `path` has no element at `index`.
+    IndexOutOfRange {
+        path: String,
+        index: usize,
+        len: usize,
+    },
+    /// Nothing is at `path`, and no table can be created there, because an array index
+    /// follows it.
+    NotFound {
+        path: String,
+    },
+}
+
+/// Error returned by `Value::apply_profile`.
+#[derive(PartialEq, Clone, Debug)]
+pub enum ProfileError {
+    /// There is no profile `name`. `available` lists the existing profiles in key order.
+    Unknown {
+        name: String,
+        available: Vec<String>,
+    },
+    /// The value at `path`, the root, `profile` or the selected profile, is not a table.
+    NotATable {
+        path: String,
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProfileError::Unknown { ref name, ref available } if available.is_empty() => {
+                write!(f, "unknown profile '{}', no profiles are defined", name)
+            }
+            ProfileError::Unknown { ref name, ref available } => {
+                write!(f,
+                       "unknown profile '{}', available profiles: {}",
+                       name,
+                       available.join(", "))
+            }
+            ProfileError::NotATable { ref path, found } if path.is_empty() => {
+                write!(f, "expected a table for profiles, found {}", found)
+            }
+            ProfileError::NotATable { ref path, found } => {
+                write!(f, "expected a table at '{}', found {}", path, found)
+            }
+        }
+    }
+}
+
+impl error::Error for ProfileError {}
+
+/// What `Value::walk` does after visiting a value.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Walk {
+    /// Visit the children of the value, if it is a table or an array.
+    Continue,
+    /// Do not visit the children of the value, and go on with its next sibling.
+    Skip,
+    /// Stop the walk.
+    Stop,
+}
+
+/// Callback of `Value::walk`.
+///
+/// Implemented for closures, so `value.walk(&mut |path: &str, value: &Value| ..)` works.
+pub trait Visitor {
+    /// Visit `value` at dotted `path` (see `Value::get_path`), empty for the root.
+    fn visit(&mut self, path: &str, value: &Value) -> Walk;
+}
+
+impl<F: FnMut(&str, &Value) -> Walk> Visitor for F {
+    fn visit(&mut self, path: &str, value: &Value) -> Walk {
+        self(path, value)
+    }
+}
+
+/// Callback of `Value::walk_visitor_mut`, which may change the visited values.
+pub trait VisitorMut {
+    /// Visit `value` at dotted `path` (see `Value::get_path`), empty for the root.
+    fn visit_mut(&mut self, path: &str, value: &mut Value) -> Walk;
+}
+
+impl<F: FnMut(&str, &mut Value) -> Walk> VisitorMut for F {
+    fn visit_mut(&mut self, path: &str, value: &mut Value) -> Walk {
+        self(path, value)
+    }
+}
+
 impl Value {
+    /// Creates an empty table value.
+    pub fn new_table() -> Value {
+        Value::Table(Table::new())
+    }
+
+    /// Creates an empty array value.
+    pub fn new_array() -> Value {
+        Value::Array(Array::new())
+    }
+
+    /// Creates a table value from key and value pairs.
+    ///
+    /// If a key repeats, the last value wins.
+    pub fn table<I, K>(entries: I) -> Value
+        where I: IntoIterator<Item = (K, Value)>,
+              K: Into<String>
+    {
+        Value::Table(entries.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Creates an array value from items.
+    pub fn array<I>(items: I) -> Value
+        where I: IntoIterator<Item = Value>
+    {
+        Value::Array(items.into_iter().collect())
+    }
+
     /// Tests whether this and another value have the same type.
     pub fn same_type(&self, other: &Value) -> bool {
-        match (self, other) {
-            (&Value::String(..), &Value::String(..)) |
-            (&Value::Integer(..), &Value::Integer(..)) |
-            (&Value::Float(..), &Value::Float(..)) |
-            (&Value::Boolean(..), &Value::Boolean(..)) |
-            (&Value::Datetime(..), &Value::Datetime(..)) |
-            (&Value::Array(..), &Value::Array(..)) |
-            (&Value::Table(..), &Value::Table(..)) => true,
+        matches!((self, other),
+                 (&Value::String(..), &Value::String(..)) |
+                 (&Value::Integer(..), &Value::Integer(..)) |
+                 (&Value::Float(..), &Value::Float(..)) |
+                 (&Value::Boolean(..), &Value::Boolean(..)) |
+                 (&Value::Datetime(..), &Value::Datetime(..)) |
+                 (&Value::Array(..), &Value::Array(..)) |
+                 (&Value::Table(..), &Value::Table(..)) |
+                 (&Value::Null, &Value::Null))
+    }
 
-            _ => false,
+    /// Tests whether this and another value have the same type, treating integers and
+    /// floats as the same type.
+    ///
+    /// Useful to check that an array like `[1, 2.0, 3]` contains only numbers.
+    pub fn same_type_numeric(&self, other: &Value) -> bool {
+        (self.is_numeric() && other.is_numeric()) || self.same_type(other)
+    }
+
+    /// Returns true if this value is an integer or a float.
+    pub fn is_numeric(&self) -> bool {
+        matches!(*self, Value::Integer(..) | Value::Float(..))
+    }
+
+    /// Tests whether this and another value are equal, treating integers and floats with
+    /// the same numeric value as equal.
+    ///
+    /// `Integer(i)` equals `Float(f)` only if `f` has no fractional part and converts to
+    /// exactly `i`, so `Integer(5)` equals `Float(5.0)`, but not `Float(5.5)`. Arrays and
+    /// tables are compared element by element using the same rule. As with `==`, NaN is
+    /// not equal to anything, including itself. Derived `PartialEq` stays strict.
+    pub fn eq_numeric_loose(&self, other: &Value) -> bool {
+        match (self, other) {
+            (&Value::Integer(i), &Value::Float(f)) |
+            (&Value::Float(f), &Value::Integer(i)) => {
+                f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 && f as i64 == i
+            }
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_numeric_loose(b))
+            }
+            (Value::Table(a), Value::Table(b)) => {
+                a.len() == b.len() &&
+                a.iter().zip(b).all(|((ka, va), (kb, vb))| ka == kb && va.eq_numeric_loose(vb))
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Tests whether this and another value are equal, allowing floats to differ by at
+    /// most `epsilon`.
+    ///
+    /// Arrays and tables are compared element by element. Integers are never equal to
+    /// floats, infinities are equal to themselves, and NaN is not equal to anything.
+    pub fn approx_eq(&self, other: &Value, epsilon: f64) -> bool {
+        match (self, other) {
+            (&Value::Float(a), &Value::Float(b)) => a == b || (a - b).abs() <= epsilon,
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.approx_eq(b, epsilon))
+            }
+            (Value::Table(a), Value::Table(b)) => {
+                a.len() == b.len() &&
+                a.iter().zip(b).all(|((ka, va), (kb, vb))| ka == kb && va.approx_eq(vb, epsilon))
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Tests whether this value matches `pattern`, where the string `"*"` is a wildcard.
+    ///
+    /// A `Value::String("*")` anywhere in the pattern matches any value at that position,
+    /// including a whole table or array. Everything else must align exactly: tables must
+    /// have the same keys, with each value matching, and arrays the same length, with
+    /// elements matching by index. Other values are compared with `==`. The wildcard is
+    /// only the exact string `"*"`; strings like `"a*"` are compared literally.
+    pub fn matches_pattern(&self, pattern: &Value) -> bool {
+        match (self, pattern) {
+            (_, Value::String(p)) if p == "*" => true,
+            (Value::Array(a), Value::Array(p)) => {
+                a.len() == p.len() && a.iter().zip(p).all(|(a, p)| a.matches_pattern(p))
+            }
+            (Value::Table(a), Value::Table(p)) => {
+                a.len() == p.len() &&
+                a.iter().zip(p).all(|((ka, va), (kp, vp))| ka == kp && va.matches_pattern(vp))
+            }
+            _ => self == pattern,
         }
     }
 
     /// Returns a human-readable representation of the type of this value.
     pub fn type_str(&self) -> &'static str {
-        match *self {
-            Value::String(..) => "string",
-            Value::Integer(..) => "integer",
-            Value::Float(..) => "float",
-            Value::Boolean(..) => "boolean",
-            Value::Datetime(..) => "datetime",
-            Value::Array(..) => "array",
-            Value::Table(..) => "table",
+        match *self {
+            Value::String(..) => "string",
+            Value::Integer(..) => "integer",
+            Value::Float(..) => "float",
+            Value::Boolean(..) => "boolean",
+            Value::Datetime(..) => "datetime",
+            Value::Array(..) => "array",
+            Value::Table(..) => "table",
+            Value::Null => "null",
         }
     }
 
+    /// Returns true if this is `Value::Null`.
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
     /// Extracts the string of this value if it is a string.
     pub fn as_str(&self) -> Option<&str> {
         match *self {
@@ -112,4 +478,1360 @@ impl Value {
             _ => None,
         }
     }
+
+    /// Returns the entries of this table sorted by key, or `None` if it is not a table.
+    pub fn table_entries_sorted(&self) -> Option<Vec<(&str, &Value)>> {
+        self.as_table().map(|t| t.iter().map(|(k, v)| (&k[..], v)).collect())
+    }
+
+    /// Returns the entries of this table, the table at `path`, in the order they are
+    /// written in the source, or `None` if it is not a table.
+    ///
+    /// The order comes from `source_map`, see `SourceMap::keys_in_source_order`.
+    #[cfg(feature = "indexmap")]
+    pub fn table_entries_insertion_order<'v>(&'v self,
+                                             path: &str,
+                                             source_map: &SourceMap)
+                                             -> Option<Vec<(&'v str, &'v Value)>> {
+        self.as_table().map(|t| {
+            source_map.keys_in_source_order(path, t).into_iter().map(|k| (k, &t[k])).collect()
+        })
+    }
+
+    /// Looks up a nested value by dotted path, like `server.hosts[0].name`.
+    ///
+    /// Path components are table keys separated by `.`, each optionally followed by one or
+    /// more array indices in brackets. Returns `None` if the path is empty, has empty
+    /// components (leading, trailing or doubled dots), indexes a non-array, or nothing is
+    /// found. Keys containing `.`, `[` or `]` are written quoted in brackets, like
+    /// `hosts["db.example.com"].port`, the same way decode errors write their paths, so
+    /// the path of an error can be looked up directly.
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        let mut current = self;
+        for component in parse_dotted_path(path)? {
+            current = match (current, component) {
+                (Value::Table(t), PathComponent::Key(k)) => t.get(&k)?,
+                (Value::Array(a), PathComponent::Index(i)) => a.get(i)?,
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+
+    /// Looks up a nested value by dotted path like `get_path`, returning `default` if
+    /// nothing is found.
+    ///
+    /// There is no type checking: a value that is present but of an unexpected type is
+    /// returned as-is.
+    pub fn get_path_or<'a>(&'a self, path: &str, default: &'a Value) -> &'a Value {
+        self.get_path(path).unwrap_or(default)
+    }
+
+    /// Mutable version of `get_path`.
+    pub fn get_path_mut(&mut self, path: &str) -> Option<&mut Value> {
+        self.get_components_mut(&parse_dotted_path(path)?)
+    }
+
+    /// Sets the value at dotted `path` (see `get_path`), returning the replaced value.
+    ///
+    /// Missing tables on the way are created, so `set_path("server.tls.enabled", ..)`
+    /// works on an empty table. Arrays are never created or extended: an index must
+    /// address an existing element. Existing values that are not tables or arrays are
+    /// not replaced by tables, that is an error. Nothing is modified if an error is
+    /// returned.
+    pub fn set_path(&mut self, path: &str, value: Value) -> Result<Option<Value>, SetPathError> {
+        let components = parse_dotted_path(path).ok_or(SetPathError::InvalidPath)?;
+        let (last, parents) = components.split_last().ok_or(SetPathError::InvalidPath)?;
+        let mut current = self;
+        for (i, component) in parents.iter().enumerate() {
+            current = match (current, component) {
+                (Value::Table(t), PathComponent::Key(k)) => {
+                    let creates_index = components[i + 1..]
+                        .iter()
+                        .any(|c| matches!(*c, PathComponent::Index(..)));
+                    if creates_index && !t.contains_key(k) {
+                        return Err(SetPathError::NotFound {
+                            path: components_to_string(&components[..i + 1]),
+                        });
+                    }
+                    t.entry(k.clone()).or_insert_with(Value::new_table)
+                }
+                (Value::Array(a), &PathComponent::Index(index)) => {
+                    let len = a.len();
+                    a.get_mut(index).ok_or_else(|| {
+                        SetPathError::IndexOutOfRange {
+                            path: components_to_string(&components[..i]),
+                            index,
+                            len,
+                        }
+                    })?
+                }
+                (other, _) => {
+                    return Err(SetPathError::NotAContainer {
+                        path: components_to_string(&components[..i]),
+                        found: other.type_str(),
+                    })
+                }
+            };
+        }
+        match (current, last) {
+            (Value::Table(t), PathComponent::Key(k)) => Ok(t.insert(k.clone(), value)),
+            (Value::Array(a), &PathComponent::Index(index)) => {
+                match a.get_mut(index) {
+                    Some(element) => Ok(Some(::std::mem::replace(element, value))),
+                    None => {
+                        Err(SetPathError::IndexOutOfRange {
+                            path: components_to_string(parents),
+                            index,
+                            len: a.len(),
+                        })
+                    }
+                }
+            }
+            (other, _) => {
+                Err(SetPathError::NotAContainer {
+                    path: components_to_string(parents),
+                    found: other.type_str(),
+                })
+            }
+        }
+    }
+
+    /// Removes the value at dotted `path` (see `get_path`) and returns it.
+    ///
+    /// Removing an array element shifts the elements after it. Returns `None` if the
+    /// path is malformed or nothing is found.
+    pub fn remove_path(&mut self, path: &str) -> Option<Value> {
+        let components = parse_dotted_path(path)?;
+        let (last, parents) = components.split_last()?;
+        match (self.get_components_mut(parents)?, last) {
+            (Value::Table(t), PathComponent::Key(k)) => t.remove(k),
+            (Value::Array(a), &PathComponent::Index(index)) if index < a.len() => {
+                Some(a.remove(index))
+            }
+            _ => None,
+        }
+    }
+
+    /// Looks up a nested value by RFC 6901 JSON Pointer, like `/server/hosts/0`.
+    ///
+    /// The empty pointer refers to this value. In keys, `~1` stands for `/` and `~0`
+    /// for `~`, so keys containing dots or slashes can be addressed. Array indices must
+    /// be written without leading zeros. Returns `None` if the pointer is malformed or
+    /// nothing is found.
+    pub fn get_pointer(&self, pointer: &str) -> Option<&Value> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        if !pointer.starts_with('/') {
+            return None;
+        }
+        let mut current = self;
+        for token in pointer[1..].split('/') {
+            current = match *current {
+                Value::Table(ref t) => t.get(&unescape_pointer_token(token)?)?,
+                Value::Array(ref a) => {
+                    if token.is_empty() || (token.len() > 1 && token.starts_with('0')) ||
+                       !token.bytes().all(|b| b.is_ascii_digit()) {
+                        return None;
+                    }
+                    a.get(token.parse::<usize>().ok()?)?
+                }
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+
+    /// Gets the entry of the given key in this table for in-place manipulation.
+    ///
+    /// The returned entry is the `BTreeMap` one, so `or_insert` and `and_modify` work
+    /// as usual. Returns `TypeMismatch` error if this value is not a table.
+    pub fn entry(&mut self, key: &str) -> Result<Entry<'_, String, Value>, TypeMismatch> {
+        match *self {
+            Value::Table(ref mut t) => Ok(t.entry(key.to_string())),
+            _ => Err(self.type_mismatch("table")),
+        }
+    }
+
+    /// Flattens this table into a map of dotted keys to stringified scalar leaves.
+    ///
+    /// Keys are paths written the way `get_path` reads them, so `{server = {hosts = ["a"]}}`
+    /// becomes `server.hosts[0] = "a"`, and a key containing `.`, `[` or `]` is quoted in
+    /// brackets. Every scalar leaf gets its own key. Leaves are stringified like this:
+    ///
+    /// - strings and datetimes are used as-is;
+    /// - integers and floats use their `Display` form (`5`, `0.5`, `5` for `5.0`);
+    /// - booleans become `true` or `false`;
+    /// - nulls are skipped and produce no entry, there is no string for a missing value.
+    ///
+    /// Empty tables and arrays produce no entries either. Returns `TypeMismatch` error if
+    /// this value is not a table.
+    pub fn to_string_map(&self) -> Result<BTreeMap<String, String>, TypeMismatch> {
+        let table = self.as_table().ok_or_else(|| self.type_mismatch("table"))?;
+        let mut result = BTreeMap::new();
+        for (key, value) in table {
+            let mut path = String::new();
+            push_key(&mut path, key);
+            value.flatten_strings_into(path, &mut result);
+        }
+        Ok(result)
+    }
+
+    /// Recursively removes table entries whose value is an empty table or array.
+    ///
+    /// Nested values are pruned first, so a key mapping to a table that only contained
+    /// empty tables is considered empty too and gets removed. Array elements are pruned
+    /// inside, but never removed, so indices stay the same. This value itself is kept
+    /// even if it ends up empty.
+    pub fn prune_empty(&mut self) {
+        self.prune(true)
+    }
+
+    /// Same as `prune_empty`, but keeps empty arrays, since an explicitly empty list is
+    /// often meaningful.
+    pub fn prune_empty_tables(&mut self) {
+        self.prune(false)
+    }
+
+    fn prune(&mut self, arrays: bool) {
+        match *self {
+            Value::Array(ref mut a) => {
+                for value in a.iter_mut() {
+                    value.prune(arrays);
+                }
+            }
+            Value::Table(ref mut t) => {
+                for value in t.values_mut() {
+                    value.prune(arrays);
+                }
+                t.retain(|_, value| {
+                    match *value {
+                        Value::Table(ref t) => !t.is_empty(),
+                        Value::Array(ref a) => !(arrays && a.is_empty()),
+                        _ => true,
+                    }
+                });
+            }
+            _ => (),
+        }
+    }
+
+    /// Deep-merges another value over this one, replacing arrays.
+    ///
+    /// Same as `merge_with` using `MergeStrategy::ArrayReplace`.
+    pub fn merge(&mut self, other: Value) {
+        self.merge_with(other, MergeStrategy::ArrayReplace)
+    }
+
+    /// Deep-merges another value over this one.
+    ///
+    /// Tables are merged key by key, recursively. Arrays are combined using the
+    /// specified strategy. Any other value from `other`, including a value of a different
+    /// type than the one in this value, replaces the value here.
+    pub fn merge_with(&mut self, other: Value, strategy: MergeStrategy) {
+        self.merge_into(other, strategy, false)
+    }
+
+    /// Same as `merge_with`, if neither `other` nor the result exceed `limits`.
+    ///
+    /// `other` is checked before merging, which also bounds the recursion of the merge by
+    /// its depth, and the result after, because merging makes tables and arrays grow.
+    /// Nothing is modified if an error is returned.
+    pub fn merge_with_limits(&mut self,
+                             other: Value,
+                             strategy: MergeStrategy,
+                             limits: &Limits)
+                             -> Result<(), At<Error>> {
+        limits.check(&other)?;
+        let mut merged = self.clone();
+        merged.merge_with(other, strategy);
+        limits.check(&merged)?;
+        *self = merged;
+        Ok(())
+    }
+
+    /// Same as `merge_with`, but a null table entry of `other` removes the entry here
+    /// instead of setting it to null, so an overlay can unset a key of the base.
+    pub fn merge_removing_nulls(&mut self, other: Value, strategy: MergeStrategy) {
+        self.merge_into(other, strategy, true)
+    }
+
+    fn merge_into(&mut self, other: Value, strategy: MergeStrategy, remove_nulls: bool) {
+        match (self, other) {
+            (&mut Value::Table(ref mut base), Value::Table(overlay)) => {
+                for (key, value) in overlay {
+                    match base.entry(key) {
+                        Entry::Occupied(e) if remove_nulls && value.is_null() => {
+                            e.remove();
+                        }
+                        Entry::Occupied(mut e) => {
+                            e.get_mut().merge_into(value, strategy, remove_nulls)
+                        }
+                        Entry::Vacant(_) if remove_nulls && value.is_null() => (),
+                        Entry::Vacant(e) => {
+                            e.insert(value);
+                        }
+                    }
+                }
+            }
+            (&mut Value::Array(ref mut base), Value::Array(overlay)) => {
+                match strategy {
+                    MergeStrategy::ArrayReplace => *base = overlay,
+                    MergeStrategy::ArrayConcat => base.extend(overlay),
+                    MergeStrategy::ArrayUnion => {
+                        for value in overlay {
+                            if !base.contains(&value) {
+                                base.push(value);
+                            }
+                        }
+                    }
+                    MergeStrategy::ArrayMergeByIndex => {
+                        for (index, value) in overlay.into_iter().enumerate() {
+                            match base.get_mut(index) {
+                                Some(existing) => {
+                                    existing.merge_into(value, strategy, remove_nulls)
+                                }
+                                None => base.push(value),
+                            }
+                        }
+                    }
+                }
+            }
+            (this, other) => *this = other,
+        }
+    }
+
+    /// Merges layers in order, each over the previous ones, and records which layer set
+    /// each leaf of the result.
+    ///
+    /// Layers are `(label, value)` pairs, merged like `merge` into an initially empty
+    /// table. The returned map has an entry for every non-table value of the result,
+    /// keyed by its path like `server.port`, or `hosts["db.example.com"]` for keys that
+    /// need quoting (arrays are leaves, as they are replaced as a whole), with the label
+    /// of the last layer that set it. Empty tables have no entry.
+    pub fn merge_with_provenance(layers: &[(String, Value)]) -> (Value, BTreeMap<String, String>) {
+        let mut result = Value::new_table();
+        let mut provenance = BTreeMap::new();
+        for (label, layer) in layers {
+            result.merge(layer.clone());
+            let mut leaves = vec![];
+            layer.collect_leaf_paths(&mut vec![], &mut leaves);
+            for leaf in leaves {
+                provenance.insert(leaf, label.clone());
+            }
+        }
+        let mut leaves = vec![];
+        result.collect_leaf_paths(&mut vec![], &mut leaves);
+        let leaves: BTreeSet<String> = leaves.into_iter().collect();
+        provenance.retain(|path, _| leaves.contains(path));
+        (result, provenance)
+    }
+
+    /// Merges the profile `name` over this table, and removes all profiles from it.
+    ///
+    /// Profiles are the tables in the `profile` table, like `[profile.prod]`, each
+    /// overriding the rest of the config the same way `merge` does. The `profile` table
+    /// is removed even if nothing is merged, so decoders never see it. Nothing is
+    /// modified if an error is returned.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate config_model;
+    /// # fn main() {
+    /// let mut value = value!{ port: 8080, profile: { prod: { port: 80 } } };
+    /// value.apply_profile("prod").unwrap();
+    /// assert_eq!(value, value!{ port: 80 });
+    /// let err = value!{ profile: { prod: {} } }.apply_profile("dev").unwrap_err();
+    /// assert_eq!(err.to_string(), "unknown profile 'dev', available profiles: prod");
+    /// # }
+    /// ```
+    pub fn apply_profile(&mut self, name: &str) -> Result<(), ProfileError> {
+        let table = match *self {
+            Value::Table(ref mut t) => t,
+            ref other => {
+                return Err(ProfileError::NotATable {
+                    path: String::new(),
+                    found: other.type_str(),
+                })
+            }
+        };
+        let profile = {
+            let profiles = match table.get("profile") {
+                Some(Value::Table(profiles)) => profiles,
+                Some(other) => {
+                    return Err(ProfileError::NotATable {
+                        path: "profile".to_string(),
+                        found: other.type_str(),
+                    })
+                }
+                None => {
+                    return Err(ProfileError::Unknown {
+                        name: name.to_string(),
+                        available: vec![],
+                    })
+                }
+            };
+            match profiles.get(name) {
+                Some(profile @ &Value::Table(..)) => profile.clone(),
+                Some(other) => {
+                    return Err(ProfileError::NotATable {
+                        path: components_to_string(&[PathComponent::Key("profile".to_string()),
+                                                     PathComponent::Key(name.to_string())]),
+                        found: other.type_str(),
+                    })
+                }
+                None => {
+                    return Err(ProfileError::Unknown {
+                        name: name.to_string(),
+                        available: profiles.keys().cloned().collect(),
+                    })
+                }
+            }
+        };
+        table.remove("profile");
+        self.merge(profile);
+        Ok(())
+    }
+
+    /// Visits this value and all nested values depth-first, allowing in-place changes.
+    ///
+    /// The callback receives the location of each value relative to this one (empty for
+    /// this value itself), and is called on a container before its children. Table
+    /// entries are visited in key order, array elements by index. Because children are
+    /// visited after the callback returns, a container replaced by the callback is
+    /// traversed in its new form.
+    pub fn walk_mut<F>(&mut self, mut f: F)
+        where F: FnMut(&[PathComponent], &mut Value)
+    {
+        self.walk_mut_at(&mut vec![], &mut f)
+    }
+
+    fn walk_mut_at<F>(&mut self, path: &mut Vec<PathComponent>, f: &mut F)
+        where F: FnMut(&[PathComponent], &mut Value)
+    {
+        f(path, self);
+        match *self {
+            Value::Array(ref mut a) => {
+                for (index, value) in a.iter_mut().enumerate() {
+                    path.push(PathComponent::Index(index));
+                    value.walk_mut_at(path, f);
+                    path.pop();
+                }
+            }
+            Value::Table(ref mut t) => {
+                for (key, value) in t.iter_mut() {
+                    path.push(PathComponent::Key(key.clone()));
+                    value.walk_mut_at(path, f);
+                    path.pop();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Visits this value and all nested values depth-first with `visitor`.
+    ///
+    /// Values are visited in the same order as in `walk_mut`, a container before its
+    /// children. The `Walk` returned by the visitor decides whether the children of a
+    /// container are visited, and can stop the walk early.
+    pub fn walk<V: Visitor>(&self, visitor: &mut V) {
+        self.walk_at(&mut vec![], visitor);
+    }
+
+    fn walk_at<V: Visitor>(&self, path: &mut Vec<PathComponent>, visitor: &mut V) -> bool {
+        match visitor.visit(&components_to_string(path), self) {
+            Walk::Continue => (),
+            Walk::Skip => return true,
+            Walk::Stop => return false,
+        }
+        match *self {
+            Value::Array(ref a) => {
+                for (index, value) in a.iter().enumerate() {
+                    path.push(PathComponent::Index(index));
+                    let go_on = value.walk_at(path, visitor);
+                    path.pop();
+                    if !go_on {
+                        return false;
+                    }
+                }
+            }
+            Value::Table(ref t) => {
+                for (key, value) in t {
+                    path.push(PathComponent::Key(key.clone()));
+                    let go_on = value.walk_at(path, visitor);
+                    path.pop();
+                    if !go_on {
+                        return false;
+                    }
+                }
+            }
+            _ => (),
+        }
+        true
+    }
+
+    /// Mutable version of `walk`, allowing in-place changes like `walk_mut`.
+    ///
+    /// A container replaced by the visitor is traversed in its new form, unless the
+    /// visitor returns `Walk::Skip`.
+    pub fn walk_visitor_mut<V: VisitorMut>(&mut self, visitor: &mut V) {
+        self.walk_visitor_mut_at(&mut vec![], visitor);
+    }
+
+    fn walk_visitor_mut_at<V: VisitorMut>(&mut self,
+                                          path: &mut Vec<PathComponent>,
+                                          visitor: &mut V)
+                                          -> bool {
+        match visitor.visit_mut(&components_to_string(path), self) {
+            Walk::Continue => (),
+            Walk::Skip => return true,
+            Walk::Stop => return false,
+        }
+        match *self {
+            Value::Array(ref mut a) => {
+                for (index, value) in a.iter_mut().enumerate() {
+                    path.push(PathComponent::Index(index));
+                    let go_on = value.walk_visitor_mut_at(path, visitor);
+                    path.pop();
+                    if !go_on {
+                        return false;
+                    }
+                }
+            }
+            Value::Table(ref mut t) => {
+                for (key, value) in t.iter_mut() {
+                    path.push(PathComponent::Key(key.clone()));
+                    let go_on = value.walk_visitor_mut_at(path, visitor);
+                    path.pop();
+                    if !go_on {
+                        return false;
+                    }
+                }
+            }
+            _ => (),
+        }
+        true
+    }
+
+    /// Sorts the array of tables at `array_path` (see `get_path`) by their `key_field`.
+    ///
+    /// Keys must be scalars of comparable types: strings and datetimes compare as text,
+    /// integers and floats compare numerically with each other, and `false` sorts before
+    /// `true`. Null keys are rejected like tables. The sort is stable, so elements with
+    /// equal keys keep their order. Nothing is modified if an error is returned.
+    pub fn sort_array_by_key(&mut self,
+                             array_path: &str,
+                             key_field: &str)
+                             -> Result<(), SortError> {
+        let array = match self.get_path_mut(array_path) {
+            Some(&mut Value::Array(ref mut a)) => a,
+            Some(other) => return Err(SortError::NotAnArray { found: other.type_str() }),
+            None => return Err(SortError::NotFound),
+        };
+        for (index, element) in array.iter().enumerate() {
+            let key = match *element {
+                Value::Table(ref t) => t.get(key_field).ok_or(SortError::MissingKey { index })?,
+                ref other => {
+                    return Err(SortError::NotATable {
+                        index,
+                        found: other.type_str(),
+                    })
+                }
+            };
+            match *key {
+                Value::Array(..) | Value::Table(..) | Value::Null => {
+                    return Err(SortError::NonScalarKey {
+                        index,
+                        found: key.type_str(),
+                    })
+                }
+                _ => {}
+            }
+            if compare_scalars(&array[0].as_table().unwrap()[key_field], key).is_none() {
+                return Err(SortError::MixedKeyTypes { index });
+            }
+        }
+        array.sort_by(|a, b| {
+            compare_scalars(&a.as_table().unwrap()[key_field],
+                            &b.as_table().unwrap()[key_field])
+                .unwrap()
+        });
+        Ok(())
+    }
+
+    /// Lists the differences from this value to `new`, in key order.
+    ///
+    /// Tables are compared by key, and arrays element by element: elements past the end
+    /// of the shorter array are added or removed. Any other pair of values is a single
+    /// `Changed` entry if they are not equal, including an integer and a float with the
+    /// same value. An empty list means the values are equal.
+    pub fn diff(&self, new: &Value) -> Vec<Change> {
+        self.diff_redacted(new, |_| false)
+    }
+
+    /// Same as `diff`, but changes under a table key that satisfies `is_secret` carry
+    /// `REDACTED` instead of the old and new values, like in `to_pretty_string_redacted`.
+    pub fn diff_redacted<F>(&self, new: &Value, is_secret: F) -> Vec<Change>
+        where F: Fn(&str) -> bool
+    {
+        let mut changes = vec![];
+        diff_into(self, new, &mut vec![], &is_secret, &mut changes);
+        changes
+    }
+
+    /// Renders this value as indented text, for logging and debugging.
+    ///
+    /// Table entries are written as `key = value`, one per line in key order, and array
+    /// elements one per line, with nested tables and arrays indented. Short arrays of
+    /// scalars are written on one line, like `[1, 2]`. Strings are quoted, floats always
+    /// have a fractional part, and datetimes are written bare. The output is meant for
+    /// humans and is not a stable format.
+    ///
+    /// Uses the default `PrettyConfig`, see `to_pretty_string_with`.
+    pub fn to_pretty_string(&self) -> String {
+        self.to_pretty_string_with(&PrettyConfig::default())
+    }
+
+    /// Same as `to_pretty_string`, with the formatting specified by `config`.
+    pub fn to_pretty_string_with(&self, config: &PrettyConfig) -> String {
+        let mut result = String::new();
+        self.write_pretty(&mut result, 0, config, &|_| false);
+        result
+    }
+
+    /// Same as `to_pretty_string`, but writes `***` instead of every value whose key
+    /// satisfies `is_secret`.
+    ///
+    /// `is_secret` receives the key of each table entry, not the full path, at any depth.
+    /// A matched value is hidden as a whole, even if it is a table or an array, and keys
+    /// inside it are not checked. Array elements are only hidden with the array's key.
+    pub fn to_pretty_string_redacted<F>(&self, is_secret: F) -> String
+        where F: Fn(&str) -> bool
+    {
+        let mut result = String::new();
+        self.write_pretty(&mut result, 0, &PrettyConfig::default(), &is_secret);
+        result
+    }
+
+    /// Computes a structural hash of this value.
+    ///
+    /// The hash is FNV-1a over the type and contents of every nested value, so it is
+    /// stable across program runs and Rust versions, and can be stored for change detection.
+    /// Floats are hashed by their bit representation, so `Integer(5)` and `Float(5.0)`
+    /// hash differently, as do `0.0` and `-0.0`.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fnv::new();
+        self.hash_into(&mut hasher, false);
+        hasher.0
+    }
+
+    /// Same as `fingerprint`, but hashes numbers by their numeric value.
+    ///
+    /// Before hashing, a float with no fractional part that fits `i64` is treated as
+    /// that integer, so `5` and `5.0` hash the same; this also maps `-0.0` to `0`.
+    /// All NaN values hash the same. Values that are equal by `eq_numeric_loose` have the
+    /// same canonical hash.
+    pub fn structural_hash_canonical(&self) -> u64 {
+        let mut hasher = Fnv::new();
+        self.hash_into(&mut hasher, true);
+        hasher.0
+    }
+
+    fn hash_into(&self, hasher: &mut Fnv, canonical: bool) {
+        match *self {
+            Value::String(ref s) => {
+                hasher.write(&[0]);
+                hasher.write_str(s);
+            }
+            Value::Integer(i) => {
+                hasher.write(&[1]);
+                hasher.write(&i.to_le_bytes());
+            }
+            Value::Float(f) if canonical && f.fract() == 0.0 && f >= i64::MIN as f64 &&
+                               f < i64::MAX as f64 => {
+                hasher.write(&[1]);
+                hasher.write(&(f as i64).to_le_bytes());
+            }
+            Value::Float(f) => {
+                hasher.write(&[2]);
+                let f = if canonical && f.is_nan() { f64::NAN } else { f };
+                hasher.write(&f.to_bits().to_le_bytes());
+            }
+            Value::Boolean(b) => hasher.write(&[3, b as u8]),
+            Value::Null => hasher.write(&[7]),
+            Value::Datetime(ref s) => {
+                hasher.write(&[4]);
+                hasher.write_str(s);
+            }
+            Value::Array(ref a) => {
+                hasher.write(&[5]);
+                hasher.write(&(a.len() as u64).to_le_bytes());
+                for value in a {
+                    value.hash_into(hasher, canonical);
+                }
+            }
+            Value::Table(ref t) => {
+                hasher.write(&[6]);
+                hasher.write(&(t.len() as u64).to_le_bytes());
+                for (key, value) in t {
+                    hasher.write_str(key);
+                    value.hash_into(hasher, canonical);
+                }
+            }
+        }
+    }
+
+    fn get_components_mut(&mut self, components: &[PathComponent]) -> Option<&mut Value> {
+        let mut current = self;
+        for component in components {
+            current = match (current, component) {
+                (Value::Table(t), PathComponent::Key(k)) => t.get_mut(k)?,
+                (Value::Array(a), &PathComponent::Index(i)) => a.get_mut(i)?,
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+
+    fn type_mismatch(&self, expected: &'static str) -> TypeMismatch {
+        TypeMismatch {
+            expected,
+            found: self.type_str(),
+        }
+    }
+
+    fn write_pretty(&self,
+                    out: &mut String,
+                    indent: usize,
+                    config: &PrettyConfig,
+                    is_secret: &dyn Fn(&str) -> bool) {
+        let inner = indent + config.indent;
+        match *self {
+            Value::String(ref s) => out.push_str(&format!("{:?}", s)),
+            Value::Integer(i) => out.push_str(&i.to_string()),
+            Value::Float(f) => out.push_str(&format!("{:?}", f)),
+            Value::Boolean(b) => out.push_str(&b.to_string()),
+            Value::Null => out.push_str("null"),
+            Value::Datetime(ref s) => out.push_str(s),
+            Value::Array(ref a) if a.is_empty() => out.push_str("[]"),
+            Value::Table(ref t) if t.is_empty() => out.push_str("{}"),
+            Value::Array(ref a) if a.len() <= config.inline_array_threshold &&
+                                   a.iter().all(|v| v.as_slice().is_none() &&
+                                                    v.as_table().is_none()) => {
+                out.push('[');
+                for (index, value) in a.iter().enumerate() {
+                    if index > 0 {
+                        out.push_str(", ");
+                    }
+                    value.write_pretty(out, inner, config, is_secret);
+                }
+                out.push(']');
+            }
+            Value::Array(ref a) => {
+                out.push_str("[\n");
+                for value in a {
+                    out.push_str(&" ".repeat(inner));
+                    value.write_pretty(out, inner, config, is_secret);
+                    out.push('\n');
+                }
+                out.push_str(&" ".repeat(indent));
+                out.push(']');
+            }
+            Value::Table(ref t) => {
+                let mut entries: Vec<(&String, &Value)> = t.iter().collect();
+                if config.sort_keys {
+                    entries.sort_by(|a, b| natural_cmp(a.0, b.0));
+                }
+                out.push_str("{\n");
+                for (key, value) in entries {
+                    out.push_str(&" ".repeat(inner));
+                    out.push_str(key);
+                    out.push_str(" = ");
+                    if is_secret(key) {
+                        out.push_str(REDACTED);
+                    } else {
+                        value.write_pretty(out, inner, config, is_secret);
+                    }
+                    out.push('\n');
+                }
+                out.push_str(&" ".repeat(indent));
+                out.push('}');
+            }
+        }
+    }
+
+    fn collect_leaf_paths(&self, path: &mut Vec<PathComponent>, leaves: &mut Vec<String>) {
+        match *self {
+            Value::Table(ref t) => {
+                for (key, value) in t {
+                    path.push(PathComponent::Key(key.clone()));
+                    value.collect_leaf_paths(path, leaves);
+                    path.pop();
+                }
+            }
+            _ => leaves.push(components_to_string(path)),
+        }
+    }
+
+    fn flatten_strings_into(&self, prefix: String, result: &mut BTreeMap<String, String>) {
+        match *self {
+            Value::String(ref s) |
+            Value::Datetime(ref s) => {
+                result.insert(prefix, s.clone());
+            }
+            Value::Integer(i) => {
+                result.insert(prefix, i.to_string());
+            }
+            Value::Float(f) => {
+                result.insert(prefix, f.to_string());
+            }
+            Value::Boolean(b) => {
+                result.insert(prefix, b.to_string());
+            }
+            Value::Null => (),
+            Value::Array(ref a) => {
+                for (index, value) in a.iter().enumerate() {
+                    value.flatten_strings_into(format!("{}[{}]", prefix, index), result);
+                }
+            }
+            Value::Table(ref t) => {
+                for (key, value) in t {
+                    let mut path = prefix.clone();
+                    push_key(&mut path, key);
+                    value.flatten_strings_into(path, result);
+                }
+            }
+        }
+    }
+}
+
+/// Looks up a table entry, like `value["server"]["port"]`.
+///
+/// Panics if the value is not a table or has no such key, use `as_table` to check.
+impl Index<&str> for Value {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        match *self {
+            Value::Table(ref t) => {
+                t.get(key).unwrap_or_else(|| panic!("no key {:?} in the table", key))
+            }
+            _ => panic!("can not look up key {:?} in a {}", key, self.type_str()),
+        }
+    }
+}
+
+/// Looks up an array element, like `value[0]`.
+///
+/// Panics if the value is not an array or the index is out of bounds, use `as_slice`
+/// to check.
+impl Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Value {
+        match *self {
+            Value::Array(ref a) => {
+                a.get(index).unwrap_or_else(|| {
+                    panic!("index {} is out of bounds of an array of length {}",
+                           index,
+                           a.len())
+                })
+            }
+            _ => panic!("can not index a {} with {}", self.type_str(), index),
+        }
+    }
+}
+
+impl<'s> From<&'s str> for Value {
+    fn from(s: &'s str) -> Value {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Boolean(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Value {
+        Value::Float(f)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(f: f32) -> Value {
+        Value::Float(f as f64)
+    }
+}
+
+macro_rules! impl_from_integer {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Value {
+                fn from(i: $t) -> Value {
+                    Value::Integer(i as i64)
+                }
+            }
+        )*
+    }
+}
+
+impl_from_integer!(i8, i16, i32, i64, u8, u16, u32);
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(v: Vec<T>) -> Value {
+        Value::Array(v.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> From<BTreeMap<String, T>> for Value {
+    fn from(t: BTreeMap<String, T>) -> Value {
+        Value::Table(t.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+/// `None` becomes `Value::Null`.
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(o: Option<T>) -> Value {
+        o.map_or(Value::Null, Into::into)
+    }
+}
+
+pub(crate) fn parse_dotted_path(path: &str) -> Option<Vec<PathComponent>> {
+    let mut components = vec![];
+    let mut rest = path;
+    loop {
+        let (key, mut tail) = rest.split_at(rest.find(['.', '[']).unwrap_or(rest.len()));
+        if !key.is_empty() {
+            components.push(PathComponent::Key(key.to_string()));
+        } else if !tail.starts_with('[') {
+            return None;
+        }
+        while let Some(bracket) = tail.strip_prefix('[') {
+            if let Some(quoted) = bracket.strip_prefix('"') {
+                let (key, after) = parse_quoted_key(quoted)?;
+                components.push(PathComponent::Key(key));
+                tail = after.strip_prefix(']')?;
+                continue;
+            }
+            let (index, after) = bracket.split_once(']')?;
+            if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            components.push(PathComponent::Index(index.parse().ok()?));
+            tail = after;
+        }
+        if tail.is_empty() {
+            return Some(components);
+        }
+        rest = tail.strip_prefix('.')?;
+    }
+}
+
+fn diff_into(old: &Value,
+             new: &Value,
+             path: &mut Vec<PathComponent>,
+             is_secret: &dyn Fn(&str) -> bool,
+             changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Value::Table(a), Value::Table(b)) => {
+            let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                path.push(PathComponent::Key(key.clone()));
+                diff_entry(a.get(key), b.get(key), path, is_secret, changes);
+                path.pop();
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for index in 0..cmp::max(a.len(), b.len()) {
+                path.push(PathComponent::Index(index));
+                diff_entry(a.get(index), b.get(index), path, is_secret, changes);
+                path.pop();
+            }
+        }
+        _ if old == new => (),
+        _ => {
+            changes.push(Change::Changed {
+                path: components_to_string(path),
+                old: redact(old, path, is_secret),
+                new: redact(new, path, is_secret),
+            })
+        }
+    }
+}
+
+fn diff_entry(old: Option<&Value>,
+              new: Option<&Value>,
+              path: &mut Vec<PathComponent>,
+              is_secret: &dyn Fn(&str) -> bool,
+              changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Some(old), Some(new)) => diff_into(old, new, path, is_secret, changes),
+        (Some(old), None) => {
+            changes.push(Change::Removed {
+                path: components_to_string(path),
+                value: redact(old, path, is_secret),
+            })
+        }
+        (None, Some(new)) => {
+            changes.push(Change::Added {
+                path: components_to_string(path),
+                value: redact(new, path, is_secret),
+            })
+        }
+        (None, None) => (),
+    }
+}
+
+/// Copy of `value`, or `REDACTED` if a key of `path` satisfies `is_secret`.
+fn redact(value: &Value, path: &[PathComponent], is_secret: &dyn Fn(&str) -> bool) -> Value {
+    let secret = path.iter().any(|c| match *c {
+        PathComponent::Key(ref key) => is_secret(key),
+        PathComponent::Index(..) => false,
+    });
+    if secret {
+        Value::String(REDACTED.to_string())
+    } else {
+        value.clone()
+    }
+}
+
+/// Write path components the way `get_path` reads them, quoting keys if necessary.
+pub(crate) fn components_to_string(components: &[PathComponent]) -> String {
+    let mut result = String::new();
+    for component in components {
+        match *component {
+            PathComponent::Key(ref key) => push_key(&mut result, key),
+            PathComponent::Index(index) => result.push_str(&format!("[{}]", index)),
+        }
+    }
+    result
+}
+
+/// Compare keys ignoring ASCII case, with runs of digits compared by their value.
+///
+/// Keys that compare equal this way, like `a` and `A`, are ordered by their bytes.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a.as_bytes(), b.as_bytes());
+    while let (Some(&cx), Some(&cy)) = (x.first(), y.first()) {
+        let ordering = if cx.is_ascii_digit() && cy.is_ascii_digit() {
+            let digits = |s: &[u8]| s.iter().take_while(|b| b.is_ascii_digit()).count();
+            let (nx, ny) = (digits(x), digits(y));
+            let trim = |s: &[u8]| s.iter().position(|&b| b != b'0').unwrap_or(s.len());
+            let (vx, vy) = (&x[trim(&x[..nx])..nx], &y[trim(&y[..ny])..ny]);
+            let ordering = vx.len().cmp(&vy.len()).then_with(|| vx.cmp(vy));
+            x = &x[nx..];
+            y = &y[ny..];
+            ordering
+        } else {
+            x = &x[1..];
+            y = &y[1..];
+            cx.to_ascii_lowercase().cmp(&cy.to_ascii_lowercase())
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    x.len().cmp(&y.len()).then_with(|| a.cmp(b))
+}
+
+/// Parse a key quoted with `{:?}` formatting, starting after the opening quote.
+///
+/// Returns the key and the text after the closing quote.
+fn parse_quoted_key(input: &str) -> Option<(String, &str)> {
+    let mut key = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((key, &input[i + 1..])),
+            '\\' => {
+                key.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    '0' => '\0',
+                    'u' => {
+                        let hex = chars.as_str().strip_prefix('{')?.split('}').next()?;
+                        for _ in 0..hex.len() + 2 {
+                            chars.next();
+                        }
+                        char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+                    }
+                    c => c,
+                })
+            }
+            c => key.push(c),
+        }
+    }
+    None
+}
+
+/// Order of scalar sort keys, `None` if the types can not be compared.
+fn compare_scalars(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) |
+        (Value::Datetime(a), Value::Datetime(b)) => Some(a.cmp(b)),
+        (&Value::Integer(a), &Value::Integer(b)) => Some(a.cmp(&b)),
+        (&Value::Boolean(a), &Value::Boolean(b)) => Some(a.cmp(&b)),
+        (&Value::Integer(a), &Value::Float(b)) => Some((a as f64).total_cmp(&b)),
+        (&Value::Float(a), &Value::Integer(b)) => Some(a.total_cmp(&(b as f64))),
+        (&Value::Float(a), &Value::Float(b)) => Some(a.total_cmp(&b)),
+        _ => None,
+    }
+}
+
+fn unescape_pointer_token(token: &str) -> Option<String> {
+    let mut result = String::with_capacity(token.len());
+    let mut chars = token.chars();
+    while let Some(c) = chars.next() {
+        if c == '~' {
+            match chars.next() {
+                Some('0') => result.push('~'),
+                Some('1') => result.push('/'),
+                _ => return None,
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    Some(result)
+}
+
+/// 64-bit FNV-1a, used where hashes must not change between Rust versions.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.write(&(s.len() as u64).to_le_bytes());
+        self.write(s.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use PathComponent;
+    use super::{components_to_string, natural_cmp, parse_dotted_path, MergeStrategy, PrettyConfig,
+                SortError, Value};
+
+    #[test]
+    fn get_pointer_unescapes_keys() {
+        let value = value!{ "a/b": { "c~d": 1 }, "~1": 2 };
+        assert_eq!(value.get_pointer("/a~1b/c~0d"), Some(&Value::Integer(1)));
+        assert_eq!(value.get_pointer("/~01"), Some(&Value::Integer(2)));
+        assert_eq!(value.get_pointer("/a/b"), None);
+        assert_eq!(value.get_pointer("/~2"), None);
+    }
+
+    #[test]
+    fn get_pointer_indexes_arrays() {
+        let value = value!{ hosts: ["a", { port: 80 }] };
+        assert_eq!(value.get_pointer(""), Some(&value));
+        assert_eq!(value.get_pointer("/hosts/0"), Some(&Value::from("a")));
+        assert_eq!(value.get_pointer("/hosts/1/port"), Some(&Value::Integer(80)));
+        assert_eq!(value.get_pointer("/hosts/2"), None);
+        assert_eq!(value.get_pointer("/hosts/01"), None);
+        assert_eq!(value.get_pointer("/hosts/-"), None);
+    }
+
+    #[test]
+    fn approx_eq_compares_floats_within_epsilon() {
+        let inf = f64::INFINITY;
+        assert!(Value::Float(0.1 + 0.2).approx_eq(&Value::Float(0.3), 1e-9));
+        assert!(!Value::Float(0.1).approx_eq(&Value::Float(0.2), 1e-9));
+        assert!(Value::Float(inf).approx_eq(&Value::Float(inf), 1e-9));
+        assert!(!Value::Float(inf).approx_eq(&Value::Float(-inf), 1e-9));
+        assert!(!Value::Float(f64::NAN).approx_eq(&Value::Float(f64::NAN), 1.0));
+        assert!(!Value::Integer(1).approx_eq(&Value::Float(1.0), 1e-9));
+        assert!(value!{ a: [1.0, inf] }.approx_eq(&value!{ a: [1.0 + 1e-12, inf] }, 1e-9));
+    }
+
+    #[test]
+    fn merge_with_provenance_quotes_dotted_keys() {
+        let defaults = value!{ port: 80, hosts: { "db.example.com": { port: 5432 } } };
+        let local = value!{ hosts: { "db.example.com": { port: 1 } } };
+        let layers = vec![("defaults".to_string(), defaults), ("local".to_string(), local)];
+        let (value, provenance) = Value::merge_with_provenance(&layers);
+        let paths: Vec<(&str, &str)> =
+            provenance.iter().map(|(path, label)| (&path[..], &label[..])).collect();
+        assert_eq!(paths, [("hosts[\"db.example.com\"].port", "local"), ("port", "defaults")]);
+        for path in provenance.keys() {
+            assert!(value.get_path(path).is_some(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn pretty_config_sorts_keys_naturally() {
+        let value = value!{ node10: 1, Node2: 2, node9: 3, a: 4 };
+        let sorted = PrettyConfig { sort_keys: true, ..PrettyConfig::default() };
+        assert_eq!(value.to_pretty_string_with(&sorted),
+                   "{\n  a = 4\n  Node2 = 2\n  node9 = 3\n  node10 = 1\n}");
+        assert_eq!(value.to_pretty_string(),
+                   "{\n  Node2 = 2\n  a = 4\n  node10 = 1\n  node9 = 3\n}");
+    }
+
+    #[test]
+    fn natural_cmp_orders_numbers_by_value() {
+        let mut keys = vec!["b", "a10", "A2", "a2", "a02", "a", "a1b", "a1"];
+        keys.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(keys, ["a", "a1", "a1b", "A2", "a02", "a2", "a10", "b"]);
+    }
+
+    #[test]
+    fn merge_combines_nested_tables() {
+        let mut base = value!{ server: { host: "::", tls: { enabled: false, port: 443 } } };
+        base.merge(value!{ server: { tls: { enabled: true }, workers: 4 } });
+        assert_eq!(base,
+                   value!{ server: { host: "::", tls: { enabled: true, port: 443 }, workers: 4 } });
+    }
+
+    #[test]
+    fn merge_replaces_arrays_and_scalars() {
+        let mut base = value!{ hosts: ["a", "b", "c"], port: 80, mode: { fast: true } };
+        base.merge(value!{ hosts: ["d"], port: "http", mode: "safe" });
+        assert_eq!(base, value!{ hosts: ["d"], port: "http", mode: "safe" });
+        let mut concat = value!{ hosts: ["a"] };
+        concat.merge_with(value!{ hosts: ["a", "b"] }, MergeStrategy::ArrayConcat);
+        assert_eq!(concat, value!{ hosts: ["a", "a", "b"] });
+    }
+
+    #[test]
+    fn get_path_descends_tables_and_arrays() {
+        let value = value!{ server: { hosts: [{ name: "a" }, { name: "b", ports: [80, 443] }] } };
+        assert_eq!(value.get_path("server.hosts[1].name"), Some(&Value::from("b")));
+        assert_eq!(value.get_path("server.hosts[1].ports[1]"), Some(&Value::Integer(443)));
+        assert_eq!(value.get_path("server.hosts[2]"), None);
+        assert_eq!(value.get_path("server[0]"), None);
+        assert_eq!(value.get_path("server..hosts"), None);
+        assert_eq!(value.get_path(""), None);
+    }
+
+    #[test]
+    fn get_path_reads_quoted_keys() {
+        let value = value!{ hosts: { "db.example.com": { port: 5432 } } };
+        assert_eq!(value.get_path(r#"hosts["db.example.com"].port"#), Some(&Value::Integer(5432)));
+        assert_eq!(value.get_path("hosts.db.example.com.port"), None);
+    }
+
+    #[test]
+    fn index_descends_tables_and_arrays() {
+        let value = value!{ server: { hosts: [{ name: "a" }] } };
+        assert_eq!(value["server"]["hosts"][0]["name"], Value::from("a"));
+    }
+
+    #[test]
+    #[should_panic(expected = "no key \"port\" in the table")]
+    fn index_panics_on_missing_key() {
+        let value = value!{ server: { host: "::" } };
+        let _ = &value["server"]["port"];
+    }
+
+    #[test]
+    #[should_panic(expected = "index 1 is out of bounds of an array of length 1")]
+    fn index_panics_on_out_of_bounds() {
+        let value = value!{ hosts: ["a"] };
+        let _ = &value["hosts"][1];
+    }
+
+    #[test]
+    fn components_to_string_quotes_index_like_keys() {
+        let components = vec![PathComponent::Key("a".to_string()),
+                              PathComponent::Key("[0]".to_string()),
+                              PathComponent::Index(1)];
+        assert_eq!(components_to_string(&components), "a[\"[0]\"][1]");
+        assert_eq!(parse_dotted_path("a[\"[0]\"][1]"), Some(components));
+    }
+
+    #[test]
+    fn same_type_numeric_mixes_integers_and_floats() {
+        let (integer, float) = (Value::Integer(1), Value::Float(1.5));
+        assert!(integer.same_type(&Value::Integer(2)));
+        assert!(!integer.same_type(&float));
+        assert!(integer.same_type_numeric(&float));
+        assert!(float.same_type_numeric(&integer));
+        assert!(!integer.same_type_numeric(&Value::from("1")));
+        assert!(Value::from("a").same_type_numeric(&Value::from("b")));
+        assert!(integer.is_numeric() && float.is_numeric());
+        assert!(!Value::from("1").is_numeric());
+        assert!(!Value::Boolean(true).is_numeric());
+    }
+
+    #[test]
+    fn assert_value_eq_allows_epsilon() {
+        assert_value_eq!(value!{ a: [0.1, 1] }, value!{ a: [0.1, 1] });
+        assert_value_eq!(value!{ a: [0.1 + 0.2] }, value!{ a: [0.3] }, epsilon = 1e-9);
+    }
+
+    #[test]
+    #[should_panic(expected = "values differ at 1 path(s):\n  ~ hosts[\"db.example.com\"] = 0.1")]
+    fn assert_value_eq_reports_quoted_paths() {
+        assert_value_eq!(value!{ hosts: { "db.example.com": 0.1 } },
+                         value!{ hosts: { "db.example.com": 0.2 } },
+                         epsilon = 1e-9);
+    }
+
+    #[test]
+    #[should_panic(expected = "values differ at 1 path(s):\n  - a[2] = 3")]
+    fn assert_value_eq_reports_extra_elements() {
+        assert_value_eq!(value!{ a: [1, 2, 3] }, value!{ a: [1, 2] });
+    }
+
+    #[test]
+    fn same_type_matches_nulls() {
+        assert!(Value::Null.same_type(&Value::Null));
+        assert!(Value::Null.same_type_numeric(&Value::Null));
+        assert!(!Value::Null.same_type(&Value::Integer(0)));
+    }
+
+    #[test]
+    fn sort_array_by_key_rejects_null_keys() {
+        let mut value = value!{ items: [{ k: null }, { k: 1 }] };
+        assert_eq!(value.sort_array_by_key("items", "k"),
+                   Err(SortError::NonScalarKey { index: 0, found: "null" }));
+        let mut value = value!{ items: [{ k: 1 }, { k: null }] };
+        assert_eq!(value.sort_array_by_key("items", "k"),
+                   Err(SortError::NonScalarKey { index: 1, found: "null" }));
+    }
+
+    #[test]
+    fn to_string_map_writes_get_path_keys() {
+        let value = value!{
+            server: { hosts: ["a", null, 2.5], port: 80, tls: true },
+            "a.b": { c: 1 },
+            a: { "b.c": 2 },
+            empty: {},
+            none: null,
+        };
+        let map = value.to_string_map().unwrap();
+        let entries: Vec<(&str, &str)> = map.iter().map(|(k, v)| (&k[..], &v[..])).collect();
+        assert_eq!(entries,
+                   [("[\"a.b\"].c", "1"),
+                    ("a[\"b.c\"]", "2"),
+                    ("server.hosts[0]", "a"),
+                    ("server.hosts[2]", "2.5"),
+                    ("server.port", "80"),
+                    ("server.tls", "true")]);
+        for key in map.keys() {
+            assert!(value.get_path(key).is_some(), "{}", key);
+        }
+        assert!(value!(1).to_string_map().is_err());
+    }
 }
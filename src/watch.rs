@@ -0,0 +1,269 @@
+//! Reloading the configuration when its files change.
+//!
+//! `Watch` keeps the last decoded model of a `ConfigBuilder`, and checks the modification
+//! times of the files it is given. When one of them changes, the sources are loaded and
+//! decoded again, and the new model is delivered with the differences from the previous
+//! value. Call `Watch::poll` from an existing loop, or `Watch::run` to block and poll at
+//! an interval.
+
+use std::fmt;
+use std::error;
+use std::fs;
+use std::path::{self, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use decode::{At, Error, Path};
+use format::LoadError;
+use source::ConfigBuilder;
+use {Value, Change};
+
+/// Root description of the path passed to the decode function.
+const ROOT_DESC: &str = "config";
+
+/// Error reloading the configuration.
+#[derive(Debug)]
+pub enum WatchError {
+    Load(LoadError),
+    Decode(At<Error>),
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WatchError::Load(ref e) => e.fmt(f),
+            WatchError::Decode(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for WatchError {}
+
+/// A reloaded configuration.
+#[derive(Debug)]
+pub struct Reload<T> {
+    pub model: T,
+    pub value: Value,
+    /// Differences from the previously loaded value, never empty.
+    pub changes: Vec<Change>,
+}
+
+/// Watcher of the files of a configuration, see the module documentation.
+pub struct Watch<'s, T, F> {
+    builder: ConfigBuilder<'s>,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    decode: F,
+    value: Value,
+    model: T,
+}
+
+impl<'s, T, F> Watch<'s, T, F>
+    where F: Fn(&Path) -> ::decode::Result<T>
+{
+    /// Load and decode the configuration, then watch `files` for changes.
+    ///
+    /// `files` are usually the paths of the `FileSource`s of `builder`. A missing file
+    /// counts as a file too, so creating it later triggers a reload.
+    pub fn new<I>(builder: ConfigBuilder<'s>, files: I, decode: F) -> Result<Self, WatchError>
+        where I: IntoIterator,
+              I::Item: Into<PathBuf>
+    {
+        let files: Vec<_> = files.into_iter()
+            .map(|p| {
+                let p = p.into();
+                let modified = modified(&p);
+                (p, modified)
+            })
+            .collect();
+        let value = builder.build().map_err(WatchError::Load)?;
+        let model = decode(&Path::new(&value, ROOT_DESC)).map_err(WatchError::Decode)?;
+        Ok(Watch {
+            builder,
+            files,
+            decode,
+            value,
+            model,
+        })
+    }
+
+    /// The last successfully decoded model.
+    pub fn model(&self) -> &T {
+        &self.model
+    }
+
+    /// The value the last model was decoded from.
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Reload the configuration if a watched file changed since the last call.
+    ///
+    /// Returns `None` if no file changed, or if the reloaded value is equal to the
+    /// previous one. On error, the previous model is kept, and the same change is not
+    /// reported again.
+    pub fn poll(&mut self) -> Option<Result<Reload<T>, WatchError>>
+        where T: Clone
+    {
+        let mut changed = false;
+        for &mut (ref path, ref mut last) in &mut self.files {
+            let current = modified(path);
+            if current != *last {
+                *last = current;
+                changed = true;
+            }
+        }
+        if !changed {
+            return None;
+        }
+        let value = match self.builder.build() {
+            Ok(value) => value,
+            Err(e) => return Some(Err(WatchError::Load(e))),
+        };
+        let changes = self.value.diff(&value);
+        if changes.is_empty() {
+            return None;
+        }
+        let model = match (self.decode)(&Path::new(&value, ROOT_DESC)) {
+            Ok(model) => model,
+            Err(e) => return Some(Err(WatchError::Decode(e))),
+        };
+        self.value = value.clone();
+        self.model = model.clone();
+        Some(Ok(Reload {
+            model,
+            value,
+            changes,
+        }))
+    }
+
+    /// Poll every `interval`, passing each result to `f`, until `f` returns false.
+    pub fn run<C>(&mut self, interval: Duration, mut f: C)
+        where T: Clone,
+              C: FnMut(Result<Reload<T>, WatchError>) -> bool
+    {
+        loop {
+            if let Some(result) = self.poll() {
+                if !f(result) {
+                    return;
+                }
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+fn modified(path: &path::Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::{Duration, SystemTime};
+    use env::parse_scalar;
+    use format::{Format, ParseError};
+    use source::{ConfigBuilder, FileSource};
+    use {Value, Change};
+    use super::{Watch, WatchError};
+
+    /// Format of `key=value` lines, enough to write test files with.
+    struct Lines;
+
+    impl Format for Lines {
+        fn parse(&self, input: &str) -> Result<Value, ParseError> {
+            let mut value = Value::new_table();
+            for line in input.lines() {
+                let (key, raw) = line.split_once('=').ok_or_else(|| ParseError {
+                    message: format!("expected key=value, found '{}'", line),
+                    span: None,
+                })?;
+                value.set_path(key, parse_scalar(raw)).unwrap();
+            }
+            Ok(value)
+        }
+    }
+
+    /// Temporary file, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            let name = format!("config_model-{}-{}", process::id(), name);
+            TempFile(env::temp_dir().join(name))
+        }
+
+        /// Write `text` with a modification time of `seconds` after the epoch.
+        fn write(&self, text: &str, seconds: u64) {
+            let mut file = File::create(&self.0).unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn watch<'f>(file: &TempFile)
+                 -> Watch<'f, i64, impl Fn(&::decode::Path) -> ::decode::Result<i64>> {
+        let builder = ConfigBuilder::new().add_source(FileSource::new(&file.0, &Lines));
+        Watch::new(builder, vec![&file.0], |path| path.table_property("port", "port")?.as_integer())
+            .unwrap()
+    }
+
+    #[test]
+    fn reloads_when_the_modification_time_changes() {
+        let file = TempFile::new("reload");
+        file.write("port=80\n", 1000);
+        let mut watch = watch(&file);
+        assert_eq!(*watch.model(), 80);
+        assert!(watch.poll().is_none());
+        file.write("port=81\n", 2000);
+        let reload = watch.poll().unwrap().unwrap();
+        assert_eq!(reload.model, 81);
+        assert_eq!(reload.changes,
+                   [Change::Changed {
+                        path: "port".to_string(),
+                        old: Value::Integer(80),
+                        new: Value::Integer(81),
+                    }]);
+        assert_eq!((*watch.model(), watch.value()), (81, &value!{ port: 81 }));
+        assert!(watch.poll().is_none());
+    }
+
+    #[test]
+    fn ignores_changes_that_keep_the_value() {
+        let file = TempFile::new("same");
+        file.write("port=80\n", 1000);
+        let mut watch = watch(&file);
+        file.write("port=80\n", 2000);
+        assert!(watch.poll().is_none());
+        assert_eq!(*watch.model(), 80);
+    }
+
+    #[test]
+    fn keeps_the_model_on_errors() {
+        let file = TempFile::new("errors");
+        file.write("port=80\n", 1000);
+        let mut watch = watch(&file);
+        file.write("port=high\n", 2000);
+        match watch.poll() {
+            Some(Err(WatchError::Decode(e))) => assert_eq!(e.path, "port"),
+            other => panic!("unexpected poll result {:?}", other),
+        }
+        assert_eq!((*watch.model(), watch.value()), (80, &value!{ port: 80 }));
+        assert!(watch.poll().is_none());
+        file.write("port\n", 3000);
+        match watch.poll() {
+            Some(Err(WatchError::Load(..))) => (),
+            other => panic!("unexpected poll result {:?}", other),
+        }
+        file.write("port=82\n", 4000);
+        assert_eq!(watch.poll().unwrap().unwrap().model, 82);
+    }
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "derive")]
+
+#[macro_use]
+extern crate config_model;
+
+use config_model::decode::{DecodeConfig, Path};
+use config_model::Value;
+
+#[derive(DecodeConfig, Debug, PartialEq)]
+struct Server {
+    #[config(desc = "Server host")]
+    host: String,
+    #[config(default = 8080)]
+    port: u16,
+    #[config(default)]
+    verbose: bool,
+    #[config(rename = "max-connections")]
+    max_connections: u32,
+    timeout: Option<u32>,
+}
+
+#[derive(DecodeConfig, Debug, PartialEq)]
+struct Wrapper<T> {
+    inner: T,
+    all: Vec<T>,
+}
+
+fn decode<T: DecodeConfig>(value: &Value) -> Result<T, String> {
+    Path::new(value, "config").decode().map_err(|e| e.to_string())
+}
+
+#[test]
+fn decodes_all_field_options() {
+    let server: Server = decode(&value!{ host: "a", "max-connections": 10 }).unwrap();
+    assert_eq!(server,
+               Server {
+                   host: "a".to_string(),
+                   port: 8080,
+                   verbose: false,
+                   max_connections: 10,
+                   timeout: None,
+               });
+    let value = value!{ host: "b", port: 81, verbose: true, "max-connections": 1, timeout: 5 };
+    let server: Server = decode(&value).unwrap();
+    assert_eq!((server.port, server.verbose, server.timeout), (81, true, Some(5)));
+}
+
+#[test]
+fn reports_bad_fields() {
+    assert_eq!(decode::<Server>(&value!{ host: 1, "max-connections": 10 }).unwrap_err(),
+               "at 'host': expected a string for Server host");
+    assert_eq!(decode::<Server>(&value!{ host: "a" }).unwrap_err(),
+               "at 'max-connections': missing property 'max-connections' (max-connections)");
+    assert_eq!(decode::<Server>(&value!{ host: "a", "max-connections": 1, timeout: "x" })
+                   .unwrap_err(),
+               "at 'timeout': expected an integer for timeout");
+    assert_eq!(decode::<Wrapper<Server>>(&value!{ inner: { host: true }, all: [] })
+                   .unwrap_err(),
+               "at 'inner.host': expected a string for Server host");
+}
+
+#[test]
+fn decodes_generic_structs() {
+    let wrapper: Wrapper<i64> = decode(&value!{ inner: 1, all: [2, 3] }).unwrap();
+    assert_eq!(wrapper, Wrapper { inner: 1, all: vec![2, 3] });
+}